@@ -1,5 +1,6 @@
 use bincode::{config::standard, Decode, Encode};
-use data_encoding::BASE64;
+use chrono::{Datelike, NaiveDateTime};
+use std::collections::HashMap;
 
 fn main() {
     let db_url = std::env::args()
@@ -8,47 +9,503 @@ fn main() {
     let config = sled::Config::default().path(db_url).use_compression(true);
     let db = config.open().unwrap();
 
-    let tree = db.open_tree("users").unwrap();
-    for i in tree.iter() {
-        let (k, v) = i.unwrap();
-        let (old, _): (OldUser, usize) = bincode::decode_from_slice(&v, standard()).unwrap();
-        let salt = BASE64.decode(old.salt.as_bytes()).unwrap();
-        let pass = BASE64.decode(old.password_hash.as_bytes()).unwrap();
-        let password_hash = BASE64.encode(&[&pass[..], &salt[..]].concat());
-
-        let new = User {
-            uid: old.uid,
-            username: old.username,
-            about: old.about,
-            role: old.role,
-            url: old.url,
-            password_hash,
-            recovery_hash: None,
-            created_at: old.created_at,
+    // drafts: uid#title = OldFormPost -> uid#did = Draft
+    let tree = db.open_tree("drafts").unwrap();
+    let mut drafts_count = db
+        .get("drafts_count")
+        .unwrap()
+        .map(|v| u32::from_be_bytes(v.to_vec().as_slice().try_into().unwrap()))
+        .unwrap_or_default();
+    let now = chrono::Utc::now().timestamp();
+    let old_drafts: Vec<_> = tree.iter().map(|i| i.unwrap()).collect();
+    for (k, v) in old_drafts {
+        let old = match bincode::decode_from_slice::<OldFormPost, _>(&v, standard()) {
+            Ok((old, len)) if len == v.len() => old,
+            _ => continue,
+        };
+        drafts_count += 1;
+        let did = drafts_count;
+
+        let new = Draft {
+            did,
+            post: DraftPost {
+                iid: old.iid,
+                title: old.title,
+                tags: old.tags,
+                content: old.content,
+                ..Default::default()
+            },
+            updated_at: now,
         };
 
         let new_encoded = bincode::encode_to_vec(&new, standard()).unwrap();
-        tree.insert(k, new_encoded).unwrap();
+        let new_k = [&k[0..4], &did.to_be_bytes()].concat();
+        tree.remove(&k).unwrap();
+        tree.insert(new_k, new_encoded).unwrap();
+    }
+    db.insert("drafts_count", &drafts_count.to_be_bytes())
+        .unwrap();
+
+    // drafts and draft_history: Draft with FormPost -> Draft with DraftPost
+    for tree_name in ["drafts", "draft_history"] {
+        let tree = db.open_tree(tree_name).unwrap();
+        for i in tree.iter() {
+            let (k, v) = i.unwrap();
+            if decode_exact::<Draft>(&v).is_some() {
+                continue;
+            }
+            let new = if let Some(old) = decode_exact::<OldDraft<FormPostItem>>(&v) {
+                old.into_draft()
+            } else if let Some(old) = decode_exact::<OldDraft<FormPostLink>>(&v) {
+                old.into_draft()
+            } else if let Some(old) = decode_exact::<OldDraft<FormPostEvent>>(&v) {
+                old.into_draft()
+            } else if let Some(old) = decode_exact::<OldDraft<FormPostPoll>>(&v) {
+                old.into_draft()
+            } else if let Some(old) = decode_exact::<OldDraft<FormPostDraft>>(&v) {
+                old.into_draft()
+            } else {
+                continue;
+            };
+            let new_encoded = bincode::encode_to_vec(&new, standard()).unwrap();
+            tree.insert(k, new_encoded).unwrap();
+        }
     }
 
+    // inns: inn_type String -> InnType
     let tree = db.open_tree("inns").unwrap();
-    for i in tree.iter() {
-        let (k, v) = i.unwrap();
-        let (old, _): (OldInn, usize) = bincode::decode_from_slice(&v, standard()).unwrap();
+    let old_inns: Vec<_> = tree.iter().map(|i| i.unwrap()).collect();
+    for (k, v) in old_inns {
+        let Ok((old, len)) = bincode::decode_from_slice::<OldInn, _>(&v, standard()) else {
+            continue;
+        };
+        let inn_type = match old.inn_type.as_str() {
+            "Public" => InnType::Public,
+            "Apply" => InnType::Apply,
+            "Private" => InnType::Private,
+            _ => continue,
+        };
+        // migrated already
+        if len != v.len() {
+            continue;
+        }
+
         let new = Inn {
             iid: old.iid,
             inn_name: old.inn_name,
             about: old.about,
             description: old.description,
             topics: old.topics,
-            inn_type: old.inn_type,
+            inn_type,
+            early_birds: old.early_birds,
             created_at: old.created_at,
-            early_birds: 0,
         };
+        let new_encoded = bincode::encode_to_vec(&new, standard()).unwrap();
+        tree.insert(k, new_encoded).unwrap();
+    }
 
+    // inn_configs: rows saved before wiki_role, qa_mode and the switches below were added
+    let tree = db.open_tree("inn_configs").unwrap();
+    let old_configs: Vec<_> = tree.iter().map(|i| i.unwrap()).collect();
+    for (k, v) in old_configs {
+        // migrated already
+        if let Ok((_, len)) = bincode::decode_from_slice::<InnConfig, _>(&v, standard()) {
+            if len == v.len() {
+                continue;
+            }
+        }
+        let (old, wiki_role, qa_mode) =
+            match bincode::decode_from_slice::<OldInnConfigQa, _>(&v, standard()) {
+                Ok((old, len)) if len == v.len() => (old.base, old.wiki_role, old.qa_mode),
+                _ => match bincode::decode_from_slice::<OldInnConfigWiki, _>(&v, standard()) {
+                    Ok((old, len)) if len == v.len() => (old.base, old.wiki_role, false),
+                    _ => match bincode::decode_from_slice::<OldInnConfig, _>(&v, standard()) {
+                        Ok((old, len)) if len == v.len() => (old, 0, false),
+                        _ => continue,
+                    },
+                },
+            };
+
+        let new = InnConfig {
+            post_interval: old.post_interval,
+            comment_interval: old.comment_interval,
+            title_max_length: old.title_max_length,
+            article_max_length: old.article_max_length,
+            per_page: old.per_page,
+            post_role: old.post_role,
+            comment_role: old.comment_role,
+            slow_mode: old.slow_mode,
+            sort_by_new: old.sort_by_new,
+            wiki_role,
+            qa_mode,
+            ..Default::default()
+        };
         let new_encoded = bincode::encode_to_vec(&new, standard()).unwrap();
         tree.insert(k, new_encoded).unwrap();
     }
+
+    // inns_log_public, inns_premod and promotion_rules -> InnConfig
+    let update_config = |k: &[u8], f: &dyn Fn(&mut InnConfig)| {
+        let mut config: InnConfig = tree
+            .get(k)
+            .unwrap()
+            .map(|v| bincode::decode_from_slice(&v, standard()).unwrap().0)
+            .unwrap_or_default();
+        f(&mut config);
+        let config_encoded = bincode::encode_to_vec(&config, standard()).unwrap();
+        tree.insert(k, config_encoded).unwrap();
+    };
+    for i in db.open_tree("inns_log_public").unwrap().iter() {
+        let (k, _) = i.unwrap();
+        update_config(&k, &|config| config.log_public = true);
+    }
+    for i in db.open_tree("inns_premod").unwrap().iter() {
+        let (k, v) = i.unwrap();
+        update_config(&k, &|config| {
+            config.premod_role = v[0];
+            config.premod_comments = v[1] == 1;
+        });
+    }
+    for i in db.open_tree("promotion_rules").unwrap().iter() {
+        let (k, v) = i.unwrap();
+        let (rule, _): (PromotionRule, usize) = bincode::decode_from_slice(&v, standard()).unwrap();
+        update_config(&k, &|config| config.promotion_rule = rule.clone());
+    }
+    for name in ["inns_log_public", "inns_premod", "promotion_rules"] {
+        db.drop_tree(name).unwrap();
+    }
+
+    // comment_pending: iid#pid#cid = uid#uid.. -> iid#pid#id = PendingComment
+    let tree = db.open_tree("comment_pending").unwrap();
+    let post_comments_tree = db.open_tree("post_comments").unwrap();
+    let user_comments_tree = db.open_tree("user_comments").unwrap();
+    let mut pending_count = db
+        .get("comment_pending_count")
+        .unwrap()
+        .map(|v| u32::from_be_bytes(v.to_vec().as_slice().try_into().unwrap()))
+        .unwrap_or_default();
+    let old_pending: Vec<_> = tree.iter().map(|i| i.unwrap()).collect();
+    // the new keys may clash with the old ones, so insert them after all the old ones are removed
+    let mut new_pending = Vec::new();
+    for (k, v) in old_pending {
+        match bincode::decode_from_slice::<PendingComment, _>(&v, standard()) {
+            Ok((_, len)) if len == v.len() => continue,
+            _ => {}
+        }
+        tree.remove(&k).unwrap();
+        let Some(comment_v) = post_comments_tree.remove(&k[4..12]).unwrap() else {
+            continue;
+        };
+        let (mut comment, _): (Comment, usize) =
+            bincode::decode_from_slice(&comment_v, standard()).unwrap();
+        let user_k = [&comment.uid.to_be_bytes(), &k[4..12]].concat();
+        user_comments_tree.remove(user_k).unwrap();
+        comment.cid = 0;
+
+        pending_count += 1;
+        let new = PendingComment {
+            comment,
+            mentioned: v
+                .chunks_exact(4)
+                .map(|uid| u32::from_be_bytes(uid.try_into().unwrap()))
+                .collect(),
+        };
+        let new_encoded = bincode::encode_to_vec(&new, standard()).unwrap();
+        let new_k = [&k[0..8], &pending_count.to_be_bytes()].concat();
+        new_pending.push((new_k, new_encoded));
+    }
+    for (k, v) in new_pending {
+        tree.insert(k, v).unwrap();
+    }
+    db.insert("comment_pending_count", &pending_count.to_be_bytes())
+        .unwrap();
+
+    // member_stats: count the posts and comments of the members in each inn, and the votes
+    // they received
+    let tree = db.open_tree("member_stats").unwrap();
+    if tree.is_empty() {
+        let mut stats: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut add = |iid: &[u8], uid: &[u8], stat_type: &str| {
+            *stats
+                .entry([iid, uid, stat_type.as_bytes()].concat())
+                .or_default() += 1;
+        };
+
+        // kv_pair: uid#pid = iid#visibility
+        let mut posts = HashMap::new();
+        for i in db.open_tree("user_posts").unwrap().iter() {
+            let (k, v) = i.unwrap();
+            add(&v[0..4], &k[0..4], "post");
+            posts.insert(k[4..8].to_vec(), (v[0..4].to_vec(), k[0..4].to_vec()));
+        }
+        for (tree_name, stat_type) in [("post_upvotes", "upvote"), ("post_downvotes", "downvote")] {
+            for i in db.open_tree(tree_name).unwrap().iter() {
+                let (k, _) = i.unwrap();
+                if let Some((iid, uid)) = posts.get(&k[0..4]) {
+                    add(iid, uid, stat_type);
+                }
+            }
+        }
+
+        // kv_pair: pid#cid = Comment
+        let mut comments = HashMap::new();
+        for i in db.open_tree("post_comments").unwrap().iter() {
+            let (k, v) = i.unwrap();
+            let Some((iid, _)) = posts.get(&k[0..4]) else {
+                continue;
+            };
+            let (comment, _): (Comment, usize) =
+                bincode::decode_from_slice(&v, standard()).unwrap();
+            let uid = comment.uid.to_be_bytes();
+            add(iid, &uid, "comment");
+            comments.insert(k.to_vec(), (iid.clone(), uid));
+        }
+        for (tree_name, stat_type) in [
+            ("comment_upvotes", "upvote"),
+            ("comment_downvotes", "downvote"),
+        ] {
+            for i in db.open_tree(tree_name).unwrap().iter() {
+                let (k, _) = i.unwrap();
+                if let Some((iid, uid)) = comments.get(&k[0..8]) {
+                    add(iid, uid, stat_type);
+                }
+            }
+        }
+
+        for (k, count) in stats {
+            tree.insert(k, &count.to_be_bytes()).unwrap();
+        }
+    }
+
+    // role_expiry: "hex_iid_uid" -> timestamp#iid#uid, the timestamp is big-endian i64
+    let tree = db.open_tree("role_expiry").unwrap();
+    let old_keys: Vec<_> = tree.iter().keys().map(|k| k.unwrap()).collect();
+    for k in old_keys {
+        let Ok(k_str) = std::str::from_utf8(&k) else {
+            continue;
+        };
+        let mut parts = k_str.split('_');
+        let expires_at = parts.next().and_then(|s| i64::from_str_radix(s, 16).ok());
+        let iid = parts.next().and_then(|s| s.parse::<u32>().ok());
+        let uid = parts.next().and_then(|s| s.parse::<u32>().ok());
+        if let (Some(expires_at), Some(iid), Some(uid), None) = (expires_at, iid, uid, parts.next())
+        {
+            let new_k = [
+                &expires_at.to_be_bytes()[..],
+                &iid.to_be_bytes(),
+                &uid.to_be_bytes(),
+            ]
+            .concat();
+            tree.remove(&k).unwrap();
+            tree.insert(new_k, &[]).unwrap();
+        }
+    }
+
+    // inn_events and inn_contributors: count the posts and comments of each day, votes are
+    // counted from now on since they have no timestamp
+    let tree = db.open_tree("inn_contributors").unwrap();
+    if tree.is_empty() {
+        let date_of = |timestamp: i64| {
+            let date = NaiveDateTime::from_timestamp_opt(timestamp, 0)
+                .unwrap()
+                .date();
+            (date.year() as u32 * 10000 + date.month() * 100 + date.day()).to_be_bytes()
+        };
+        let mut events: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut contributors: HashMap<Vec<u8>, u32> = HashMap::new();
+        let mut add = |iid: &[u8], uid: &[u8], timestamp: i64, event: &str| {
+            let date = date_of(timestamp);
+            *events
+                .entry([iid, &date, event.as_bytes()].concat())
+                .or_default() += 1;
+            *contributors.entry([iid, &date, uid].concat()).or_default() += 1;
+        };
+
+        // kv_pair: iid#pid = timestamp, only the published posts
+        let mut posts = HashMap::new();
+        for i in db.open_tree("post_timeline_idx").unwrap().iter() {
+            let (k, _) = i.unwrap();
+            let Some(v) = db.open_tree("posts").unwrap().get(&k[4..8]).unwrap() else {
+                continue;
+            };
+            let (post, _): (Post, usize) = bincode::decode_from_slice(&v, standard()).unwrap();
+            add(&k[0..4], &post.uid.to_be_bytes(), post.created_at, "post");
+            posts.insert(k[4..8].to_vec(), k[0..4].to_vec());
+        }
+        // kv_pair: pid#cid = Comment
+        for i in db.open_tree("post_comments").unwrap().iter() {
+            let (k, v) = i.unwrap();
+            let Some(iid) = posts.get(&k[0..4]) else {
+                continue;
+            };
+            let (comment, _): (Comment, usize) =
+                bincode::decode_from_slice(&v, standard()).unwrap();
+            add(
+                iid,
+                &comment.uid.to_be_bytes(),
+                comment.created_at,
+                "comment",
+            );
+        }
+
+        let events_tree = db.open_tree("inn_events").unwrap();
+        for (k, count) in events {
+            events_tree.insert(k, &count.to_be_bytes()).unwrap();
+        }
+        for (k, count) in contributors {
+            tree.insert(k, &count.to_be_bytes()).unwrap();
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+struct OldFormPost {
+    iid: u32,
+    title: String,
+    tags: String,
+    content: String,
+    is_draft: Option<bool>,
+    delete_draft: Option<bool>,
+}
+
+/// decode the value only if it is exactly of the layout
+fn decode_exact<T: Decode>(v: &[u8]) -> Option<T> {
+    match bincode::decode_from_slice(v, standard()) {
+        Ok((one, len)) if len == v.len() => Some(one),
+        _ => None,
+    }
+}
+
+// the layouts of FormPost saved in drafts, each one appends fields to the previous one
+#[derive(Encode, Decode)]
+struct FormPostDraft {
+    base: OldFormPost,
+    draft_id: Option<u32>,
+}
+
+#[derive(Encode, Decode)]
+struct FormPostPoll {
+    base: FormPostDraft,
+    poll_options: Option<String>,
+    poll_multiple: Option<bool>,
+    poll_anonymous: Option<bool>,
+    poll_close: Option<String>,
+}
+
+#[derive(Encode, Decode)]
+struct FormPostEvent {
+    base: FormPostPoll,
+    event_start: Option<String>,
+    event_end: Option<String>,
+    event_location: Option<String>,
+}
+
+#[derive(Encode, Decode)]
+struct FormPostLink {
+    base: FormPostEvent,
+    link: Option<String>,
+    link_anyway: Option<bool>,
+}
+
+#[derive(Encode, Decode)]
+struct FormPostItem {
+    base: FormPostLink,
+    item_id: Option<u32>,
+}
+
+impl From<FormPostDraft> for DraftPost {
+    fn from(old: FormPostDraft) -> Self {
+        DraftPost {
+            iid: old.base.iid,
+            title: old.base.title,
+            tags: old.base.tags,
+            content: old.base.content,
+            ..Default::default()
+        }
+    }
+}
+
+impl From<FormPostPoll> for DraftPost {
+    fn from(old: FormPostPoll) -> Self {
+        DraftPost {
+            poll_options: old.poll_options,
+            poll_multiple: old.poll_multiple,
+            poll_anonymous: old.poll_anonymous,
+            poll_close: old.poll_close,
+            ..old.base.into()
+        }
+    }
+}
+
+impl From<FormPostEvent> for DraftPost {
+    fn from(old: FormPostEvent) -> Self {
+        DraftPost {
+            event_start: old.event_start,
+            event_end: old.event_end,
+            event_location: old.event_location,
+            ..old.base.into()
+        }
+    }
+}
+
+impl From<FormPostLink> for DraftPost {
+    fn from(old: FormPostLink) -> Self {
+        DraftPost {
+            link: old.link,
+            ..old.base.into()
+        }
+    }
+}
+
+impl From<FormPostItem> for DraftPost {
+    fn from(old: FormPostItem) -> Self {
+        DraftPost {
+            item_id: old.item_id,
+            ..old.base.into()
+        }
+    }
+}
+
+#[derive(Encode, Decode)]
+struct OldDraft<P> {
+    did: u32,
+    post: P,
+    updated_at: i64,
+}
+
+impl<P: Into<DraftPost>> OldDraft<P> {
+    fn into_draft(self) -> Draft {
+        Draft {
+            did: self.did,
+            post: self.post.into(),
+            updated_at: self.updated_at,
+        }
+    }
+}
+
+#[derive(Encode, Decode, Default)]
+struct DraftPost {
+    iid: u32,
+    title: String,
+    tags: String,
+    content: String,
+    poll_options: Option<String>,
+    poll_multiple: Option<bool>,
+    poll_anonymous: Option<bool>,
+    poll_close: Option<String>,
+    event_start: Option<String>,
+    event_end: Option<String>,
+    event_location: Option<String>,
+    link: Option<String>,
+    item_id: Option<u32>,
+}
+
+#[derive(Encode, Decode)]
+struct Draft {
+    did: u32,
+    // changed from FormPost, breaking the api
+    post: DraftPost,
+    updated_at: i64,
 }
 
 #[derive(Encode, Decode)]
@@ -59,9 +516,20 @@ struct OldInn {
     description: String,
     topics: Vec<String>,
     inn_type: String,
+    early_birds: u32,
     created_at: i64,
 }
 
+#[derive(Encode, Decode)]
+enum InnType {
+    Public,
+    Apply,
+    Private,
+    Announcement,
+    Restricted,
+    Archive,
+}
+
 #[derive(Encode, Decode)]
 struct Inn {
     iid: u32,
@@ -69,32 +537,92 @@ struct Inn {
     about: String,
     description: String,
     topics: Vec<String>,
-    inn_type: String,
-    // add new field, breaking the api
+    // changed from String, breaking the api
+    inn_type: InnType,
     early_birds: u32,
     created_at: i64,
 }
 
 #[derive(Encode, Decode)]
-struct OldUser {
+struct OldInnConfig {
+    post_interval: i64,
+    comment_interval: i64,
+    title_max_length: usize,
+    article_max_length: usize,
+    per_page: usize,
+    post_role: u8,
+    comment_role: u8,
+    slow_mode: i64,
+    sort_by_new: bool,
+}
+
+#[derive(Encode, Decode)]
+struct OldInnConfigWiki {
+    base: OldInnConfig,
+    wiki_role: u8,
+}
+
+#[derive(Encode, Decode)]
+struct OldInnConfigQa {
+    base: OldInnConfig,
+    wiki_role: u8,
+    qa_mode: bool,
+}
+
+#[derive(Encode, Decode, Default, Clone)]
+struct PromotionRule {
+    posts: u32,
+    comments: u32,
+    days: u32,
+    upvotes: u32,
+}
+
+#[derive(Encode, Decode, Default)]
+struct InnConfig {
+    post_interval: i64,
+    comment_interval: i64,
+    title_max_length: usize,
+    article_max_length: usize,
+    per_page: usize,
+    post_role: u8,
+    comment_role: u8,
+    slow_mode: i64,
+    sort_by_new: bool,
+    // add new fields, breaking the api
+    wiki_role: u8,
+    qa_mode: bool,
+    log_public: bool,
+    premod_role: u8,
+    premod_comments: bool,
+    promotion_rule: PromotionRule,
+}
+
+#[derive(Encode, Decode)]
+struct Post {
+    pid: u32,
     uid: u32,
-    username: String,
-    salt: String,
-    password_hash: String,
+    iid: u32,
+    title: String,
+    tags: Vec<String>,
+    content: String,
     created_at: i64,
-    role: u8,
-    url: String,
-    about: String,
+    is_locked: bool,
+    is_hidden: bool,
 }
 
 #[derive(Encode, Decode)]
-struct User {
+struct Comment {
+    cid: u32,
+    pid: u32,
     uid: u32,
-    username: String,
-    password_hash: String,
-    recovery_hash: Option<String>,
+    reply_to: Option<u32>,
+    content: String,
     created_at: i64,
-    role: u8,
-    url: String,
-    about: String,
+    is_hidden: bool,
+}
+
+#[derive(Encode, Decode)]
+struct PendingComment {
+    comment: Comment,
+    mentioned: Vec<u32>,
 }
@@ -1,22 +1,37 @@
 use crate::{
     config::CONFIG,
     controller::{
-        admin::{admin, admin_post, admin_stats, admin_view},
-        feed::{feed, feed_add, feed_add_post, feed_read, feed_star, feed_subscribe, feed_update},
+        admin::{admin, admin_log, admin_post, admin_stats, admin_view},
+        collection::{
+            collection, collection_delete, collection_edit, collection_edit_post, collection_list,
+        },
+        event::{event_ics, event_rsvp, inn_events, inn_ics},
+        feed::{
+            feed, feed_add, feed_add_post, feed_read, feed_star, feed_subscribe, feed_update,
+            mod_feed_delete, mod_feeds, mod_feeds_post,
+        },
         handler_404, health_check, home,
         inn::{
-            comment_delete, comment_downvote, comment_hide, comment_post, comment_upvote,
-            edit_post, edit_post_post, inn, inn_feed, inn_join, inn_list, mod_inn, mod_inn_post,
-            post, post_downvote, post_hide, post_lock, post_upvote, preview, tag,
+            comment_accept, comment_delete, comment_downvote, comment_hide, comment_post,
+            comment_upvote, draft_delete, draft_list, draft_preview, edit_post, edit_post_post,
+            inn, inn_feed, inn_join, inn_list, link_discussions, mod_inn, mod_inn_post,
+            mod_log_view, mod_stats, post, post_downvote, post_feed, post_hide, post_lock,
+            post_save, post_subscribe, post_upvote, post_views, preview, tag, tag_feed, tag_follow,
+            topic_follow, user_feed,
         },
-        notification, serve_dir,
+        invite::{invite, invite_revoke, mod_invites, mod_invites_post},
+        notification,
+        pending::{mod_pending, pending_action},
+        poll::poll_vote,
+        serve_dir,
         solo::{solo, solo_delete, solo_like, solo_list, solo_post},
         style, upload, upload_pic_post, upload_post,
         user::{
             remove_session, reset, reset_post, role_post, signin, signin_post, signout, signup,
-            signup_post, user, user_follow, user_list, user_password_post, user_recovery_code,
-            user_setting, user_setting_post,
+            signup_post, user, user_feed_token, user_follow, user_list, user_password_post,
+            user_recovery_code, user_saved, user_setting, user_setting_post,
         },
+        wiki::{wiki, wiki_delete, wiki_edit, wiki_edit_post, wiki_history, wiki_index},
     },
 };
 use axum::{
@@ -57,25 +72,55 @@ pub(super) async fn router(db: Db) -> Router {
         .route("/user/password", get(user_setting).post(user_password_post))
         .route("/user/recovery", get(user_setting).post(user_recovery_code))
         .route("/user/reset", get(reset).post(reset_post))
+        .route("/user/feed_token", get(user_feed_token))
+        .route("/user/feed/:token", get(user_feed))
         .route("/user/list", get(user_list))
+        .route("/user/saved", get(user_saved))
+        .route("/user/drafts", get(draft_list))
+        .route("/user/collections", get(collection_list))
+        .route("/user/drafts/:did/delete", get(draft_delete))
+        .route("/user/drafts/:did/preview", get(draft_preview))
         .route("/user/remove/:session_id", get(remove_session))
         .route("/role/:id/:uid", get(user_list).post(role_post))
         .route("/notification", get(notification))
         .route("/admin", get(admin).post(admin_post))
         .route("/admin/view", get(admin_view))
         .route("/admin/stats", get(admin_stats))
+        .route("/admin/log", get(admin_log))
         .route("/mod/:iid", get(mod_inn).post(mod_inn_post))
         .route(
             "/mod/inn_icon",
             get(mod_inn).post(upload_pic_post.layer(DefaultBodyLimit::max(UPLOAD_LIMIT))),
         )
+        .route("/mod/:iid/log", get(mod_log_view))
+        .route("/mod/:iid/stats", get(mod_stats))
+        .route("/mod/:iid/invites", get(mod_invites).post(mod_invites_post))
+        .route("/mod/:iid/invites/:code/revoke", get(invite_revoke))
+        .route("/mod/:iid/feeds", get(mod_feeds).post(mod_feeds_post))
+        .route("/mod/:iid/feeds/:feed_id/delete", get(mod_feed_delete))
+        .route("/mod/:iid/pending", get(mod_pending))
+        .route("/mod/:iid/pending/:pid/:cid/:action", get(pending_action))
         .route("/mod/:iid/:pid/lock", get(post_lock))
         .route("/mod/:iid/:pid/hide", get(post_hide))
         .route("/inn/list", get(inn_list))
         .route("/inn/tag/:tag", get(tag))
+        .route("/inn/tag/:tag/follow", get(tag_follow))
+        .route("/inn/tag/:tag/feed", get(tag_feed))
+        .route("/inn/topic/:topic/follow", get(topic_follow))
         .route("/inn/:iid", get(inn))
         .route("/inn/:iid/join", get(inn_join))
+        .route("/inn/:iid/invite/:code", get(invite))
         .route("/inn/:iid/feed", get(inn_feed))
+        .route("/inn/:iid/events", get(inn_events))
+        .route("/inn/:iid/events.ics", get(inn_ics))
+        .route("/inn/:iid/wiki", get(wiki_index))
+        .route("/inn/:iid/wiki/:title", get(wiki))
+        .route("/inn/:iid/wiki/:title/history", get(wiki_history))
+        .route(
+            "/inn/:iid/wiki/:title/edit",
+            get(wiki_edit).post(wiki_edit_post),
+        )
+        .route("/inn/:iid/wiki/:title/delete", get(wiki_delete))
         .route("/post/:iid/:pid", get(post).post(comment_post))
         .route("/post/:iid/:pid/:cid/delete", get(comment_delete))
         .route("/post/:iid/:pid/:cid/hide", get(comment_hide))
@@ -83,8 +128,23 @@ pub(super) async fn router(db: Db) -> Router {
         .route("/post/:iid/:pid/upvote", get(post_upvote))
         .route("/post/:iid/:pid/downvote", get(post_downvote))
         .route("/post/:iid/:pid/:cid/upvote", get(comment_upvote))
+        .route("/post/:iid/:pid/:cid/save", get(post_save))
+        .route("/post/:iid/:pid/:cid/accept", get(comment_accept))
+        .route("/post/:iid/:pid/subscribe", get(post_subscribe))
+        .route("/post/:iid/:pid/poll", get(post).post(poll_vote))
+        .route("/post/:iid/:pid/rsvp/:status", get(event_rsvp))
+        .route("/post/:iid/:pid/event.ics", get(event_ics))
+        .route("/post/:iid/:pid/discussions", get(link_discussions))
+        .route("/post/:iid/:pid/views", get(post_views))
+        .route("/post/:iid/:pid/feed", get(post_feed))
         .route("/post/:iid/:pid/:cid/downvote", get(comment_downvote))
         .route("/preview", get(post).post(preview))
+        .route("/collection/:id", get(collection))
+        .route(
+            "/collection/edit/:id",
+            get(collection_edit).post(collection_edit_post),
+        )
+        .route("/collection/:id/delete", get(collection_delete))
         .route("/solo/user/:uid", get(solo_list).post(solo_post))
         .route("/solo/:sid/like", get(solo_like))
         .route("/solo/:sid/delete", get(solo_delete))
@@ -1,6 +1,9 @@
 use super::{
-    get_site_config, into_response, timestamp_to_date, u8_slice_to_u32, Claim, Feed, FormPost,
-    Item, IterType, PageData, SiteConfig, ValidatedForm,
+    decode_cursor, encode_cursor, get_batch, get_site_config,
+    inn::{OutModLog, PageModLog, ParamsModLog},
+    into_response, iter_by_cursor, mod_log, timestamp_to_date, u8_slice_to_u32, Claim, Collection,
+    Draft, Event, Feed, InnConfig, InnFeed, Invite, Item, ModLog, PageData, ParamsPage,
+    PendingComment, Poll, RoleChange, SiteConfig, ValidatedForm, WikiPage,
 };
 use crate::{
     controller::{ivec_to_u32, Comment, Inn, Post, Solo, User},
@@ -25,6 +28,7 @@ struct PageAdminView<'a> {
     ones: Vec<String>,
     tree_name: String,
     anchor: usize,
+    cursor: String,
     is_desc: bool,
     n: usize,
 }
@@ -34,6 +38,7 @@ struct PageAdminView<'a> {
 pub(crate) struct ParamsAdminView {
     tree_name: Option<String>,
     anchor: Option<usize>,
+    cursor: Option<String>,
     is_desc: Option<bool>,
 }
 
@@ -53,6 +58,14 @@ pub(crate) async fn admin_view(
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor,
+    };
+    let mut cursor = String::new();
 
     let mut tree_names = Vec::with_capacity(64);
     for i in db.tree_names() {
@@ -68,22 +81,20 @@ pub(crate) async fn admin_view(
 
     if tree_names.contains(&tree_name) {
         let tree = db.open_tree(&tree_name)?;
-        let iter = if is_desc {
-            IterType::Rev(tree.iter().rev())
-        } else {
-            IterType::Iter(tree.iter())
-        };
+        let iter = iter_by_cursor(&tree, &[], &page_params);
+        let skip = page_params.skip();
 
         for (idx, i) in iter.enumerate() {
-            if idx < anchor {
+            if idx < skip {
                 continue;
             }
 
-            if idx >= anchor + n {
+            if idx >= skip + n {
                 break;
             }
 
             let (k, v) = i?;
+            cursor = encode_cursor(&k);
             match tree_name.as_str() {
                 "__sled__default" => {
                     let key = String::from_utf8_lossy(&k);
@@ -156,6 +167,29 @@ pub(crate) async fn admin_view(
                     let count = ivec_to_u32(&v);
                     ones.push(format!("id: {id}, count: {count}"));
                 }
+                "post_pageviews_daily" => {
+                    let pid = u8_slice_to_u32(&k[0..4]);
+                    let date = u8_slice_to_u32(&k[4..8]);
+                    let count = ivec_to_u32(&v);
+                    ones.push(format!("pid: {pid}, date: {date}, count: {count}"));
+                }
+                "post_related" => {
+                    let pid = u8_slice_to_u32(&k);
+                    let related: Vec<u32> = v.chunks_exact(4).map(u8_slice_to_u32).collect();
+                    ones.push(format!("pid: {pid}, related: {related:?}"));
+                }
+                "post_viewers" => {
+                    // kv_pair: timestamp_pid_viewer = &[]
+                    let mut k_str = std::str::from_utf8(&k)?.split('_');
+                    let (Some(timestamp), Some(pid)) = (k_str.next(), k_str.next()) else {
+                        continue;
+                    };
+                    let Ok(timestamp) = i64::from_str_radix(timestamp, 16) else {
+                        continue;
+                    };
+                    let date = timestamp_to_date(timestamp);
+                    ones.push(format!("{pid} - {date}"));
+                }
                 "hashtags" | "topics" | "tags" => {
                     let len = k.len();
                     let str = String::from_utf8_lossy(&k[0..len - 4]);
@@ -164,7 +198,8 @@ pub(crate) async fn admin_view(
                 }
                 "user_following" | "user_followers" | "mod_inns" | "user_inns" | "inn_users"
                 | "inn_apply" | "post_upvotes" | "post_downvotes" | "user_solos_like"
-                | "inn_posts" | "solo_users_like" | "feed_items" | "read" | "star" => {
+                | "inn_posts" | "solo_users_like" | "feed_items" | "read" | "star"
+                | "post_pending" | "post_subscribers" | "item_posts" | "item_solos" => {
                     let id1 = u8_slice_to_u32(&k[0..4]);
                     let id2 = u8_slice_to_u32(&k[4..8]);
                     ones.push(format!("k: {id1}#{id2}, v: {v:?}"));
@@ -178,12 +213,109 @@ pub(crate) async fn admin_view(
                     let count = ivec_to_u32(&v);
                     ones.push(format!("{uid} - {date} - {stat_type} - {count}"));
                 }
+                "user_tags" | "user_topics" => {
+                    let uid = u8_slice_to_u32(&k[0..4]);
+                    let str = String::from_utf8_lossy(&k[4..]);
+                    ones.push(format!("{uid}#{str}"));
+                }
                 "inn_names" | "usernames" | "feed_links" | "item_links" => {
                     let name = std::str::from_utf8(&k)?;
                     let id = u8_slice_to_u32(&v);
                     ones.push(format!("name: {name}, id: {id}"));
                 }
-                "inns_private" => {
+                "mod_logs" => {
+                    let key = ivec_to_u32(&k);
+                    let (one, _): (ModLog, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{key}: {one:?}"));
+                }
+                "inn_mod_logs" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let lid = u8_slice_to_u32(&k[4..8]);
+                    ones.push(format!("iid: {iid}, lid: {lid}"));
+                }
+                "comment_pending" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let pid = u8_slice_to_u32(&k[4..8]);
+                    let id = u8_slice_to_u32(&k[8..12]);
+                    let (one, _): (PendingComment, usize) =
+                        bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("iid: {iid}, pid: {pid}, id: {id}, {one:?}"));
+                }
+                "thread_notifications" => {
+                    let uid = u8_slice_to_u32(&k[0..4]);
+                    let pid = u8_slice_to_u32(&k[4..8]);
+                    let count = u8_slice_to_u32(&v[0..4]);
+                    let last_cid = u8_slice_to_u32(&v[4..8]);
+                    ones.push(format!(
+                        "uid: {uid}, pid: {pid}, count: {count}, last_cid: {last_cid}"
+                    ));
+                }
+                "feed_tokens" => {
+                    // tokens are secrets, only show whose they are
+                    let uid = u8_slice_to_u32(&v);
+                    ones.push(format!("uid: {uid}"));
+                }
+                "user_feed_tokens" => {
+                    let uid = u8_slice_to_u32(&k);
+                    ones.push(format!("uid: {uid}"));
+                }
+                "auto_subscribe_off" => {
+                    let uid = u8_slice_to_u32(&k);
+                    ones.push(format!("uid: {uid}"));
+                }
+                "user_saved" => {
+                    let uid = u8_slice_to_u32(&k[0..4]);
+                    let pid = u8_slice_to_u32(&k[4..8]);
+                    let cid = u8_slice_to_u32(&k[8..12]);
+                    let iid = u8_slice_to_u32(&v[0..4]);
+                    let date = timestamp_to_date(i64::from(u8_slice_to_u32(&v[4..8])));
+                    ones.push(format!(
+                        "uid: {uid}, iid: {iid}, pid: {pid}, cid: {cid}, {date}"
+                    ));
+                }
+                "inn_events" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let date = u8_slice_to_u32(&k[4..8]);
+                    let event = String::from_utf8_lossy(&k[8..]);
+                    let count = ivec_to_u32(&v);
+                    ones.push(format!("iid: {iid}, date: {date}, {event}: {count}"));
+                }
+                "member_stats" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let uid = u8_slice_to_u32(&k[4..8]);
+                    let stat_type = String::from_utf8_lossy(&k[8..]);
+                    let count = ivec_to_u32(&v);
+                    ones.push(format!("iid: {iid}, uid: {uid}, {stat_type}: {count}"));
+                }
+                "inn_invites" => {
+                    let (one, _): (Invite, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{one:?}"));
+                }
+                "invite_uses" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let code = String::from_utf8_lossy(&k[4..k.len() - 4]);
+                    let uid = u8_slice_to_u32(&k[k.len() - 4..]);
+                    let date = timestamp_to_date(i64::from(ivec_to_u32(&v)));
+                    ones.push(format!("iid: {iid}, code: {code}, uid: {uid}, {date}"));
+                }
+                "inn_configs" => {
+                    let iid = ivec_to_u32(&k);
+                    let (one, _): (InnConfig, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{iid}: {one:?}"));
+                }
+                "inn_slow_mode" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let uid = u8_slice_to_u32(&k[4..8]);
+                    let date = timestamp_to_date(i64::from(ivec_to_u32(&v)));
+                    ones.push(format!("iid: {iid}, uid: {uid}, last write: {date}"));
+                }
+                "inn_joined" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let uid = u8_slice_to_u32(&k[4..8]);
+                    let date = timestamp_to_date(i64::from(ivec_to_u32(&v)));
+                    ones.push(format!("iid: {iid}, uid: {uid}, joined: {date}"));
+                }
+                "inns_private" | "post_edited" => {
                     let id = u8_slice_to_u32(&k);
                     ones.push(format!("id: {id}"));
                 }
@@ -208,6 +340,19 @@ pub(crate) async fn admin_view(
                         pid, cid, uid, v[0]
                     ));
                 }
+                "role_changes" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let uid = u8_slice_to_u32(&k[4..8]);
+                    let (one, _): (RoleChange, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("iid: {iid}, uid: {uid}, {one:?}"));
+                }
+                "role_expiry" => {
+                    let expires_at = i64::from_be_bytes(k[0..8].try_into().unwrap());
+                    let iid = u8_slice_to_u32(&k[8..12]);
+                    let uid = u8_slice_to_u32(&k[12..16]);
+                    let date = timestamp_to_date(expires_at);
+                    ones.push(format!("iid: {iid}, uid: {uid}, expires at: {date}"));
+                }
                 "captcha" | "sessions" => {
                     let k_str = std::str::from_utf8(&k)?;
                     let time_stamp = k_str
@@ -248,9 +393,94 @@ pub(crate) async fn admin_view(
                 }
                 "drafts" => {
                     let uid = u8_slice_to_u32(&k[0..4]);
-                    let (draft, _): (FormPost, usize) = bincode::decode_from_slice(&v, standard())?;
+                    let (draft, _): (Draft, usize) = bincode::decode_from_slice(&v, standard())?;
                     ones.push(format!("{uid}: {draft:?}"));
                 }
+                "draft_history" => {
+                    let did = u8_slice_to_u32(&k[0..4]);
+                    let (draft, _): (Draft, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{did}: {draft:?}"));
+                }
+                "collections" => {
+                    let key = ivec_to_u32(&k);
+                    let (one, _): (Collection, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{key}: {one:?}"));
+                }
+                "user_collections" => {
+                    let uid = u8_slice_to_u32(&k[0..4]);
+                    let id = u8_slice_to_u32(&k[4..8]);
+                    ones.push(format!("{uid} - {id}"));
+                }
+                "events" => {
+                    let key = ivec_to_u32(&k);
+                    let (one, _): (Event, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{key}: {one:?}"));
+                }
+                "inn_calendar" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let start = timestamp_to_date(i64::from(u8_slice_to_u32(&k[4..8])));
+                    let pid = u8_slice_to_u32(&k[8..12]);
+                    ones.push(format!("{iid} - {start} - {pid}"));
+                }
+                "event_rsvps" => {
+                    let pid = u8_slice_to_u32(&k[0..4]);
+                    let uid = u8_slice_to_u32(&k[4..8]);
+                    ones.push(format!("{pid} - {uid}: {}", v[0]));
+                }
+                "post_links" => {
+                    let link = std::str::from_utf8(&k)?;
+                    let pids: Vec<u32> = v.chunks_exact(4).map(u8_slice_to_u32).collect();
+                    ones.push(format!("{link}: {pids:?}"));
+                }
+                "inn_feeds" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let feed_id = u8_slice_to_u32(&k[4..8]);
+                    let (one, _): (InnFeed, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{iid} - {feed_id}: {one:?}"));
+                }
+                "inn_feed_items" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let item_id = u8_slice_to_u32(&k[4..8]);
+                    let pid = ivec_to_u32(&v);
+                    ones.push(format!("{iid} - {item_id}: {pid}"));
+                }
+                "post_url" => {
+                    let pid = ivec_to_u32(&k);
+                    let link = std::str::from_utf8(&v)?;
+                    ones.push(format!("{pid}: {link}"));
+                }
+                "polls" => {
+                    let key = ivec_to_u32(&k);
+                    let (one, _): (Poll, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{key}: {one:?}"));
+                }
+                "poll_votes" => {
+                    let pid = u8_slice_to_u32(&k[0..4]);
+                    let uid = u8_slice_to_u32(&k[4..8]);
+                    ones.push(format!("{pid} - {uid}: {:?}", v.to_vec()));
+                }
+                "post_accepted" => {
+                    let pid = ivec_to_u32(&k);
+                    let cid = ivec_to_u32(&v);
+                    ones.push(format!("{pid}: {cid}"));
+                }
+                "wiki_pages" => {
+                    let key = ivec_to_u32(&k);
+                    let (one, _): (WikiPage, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{key}: {one:?}"));
+                }
+                "inn_wiki" => {
+                    let iid = u8_slice_to_u32(&k[0..4]);
+                    let title = String::from_utf8_lossy(&k[4..]);
+                    let wid = ivec_to_u32(&v);
+                    ones.push(format!("{iid} - {title}: {wid}"));
+                }
+                "wiki_history" => {
+                    let wid = u8_slice_to_u32(&k[0..4]);
+                    let rev = u8_slice_to_u32(&k[4..8]);
+                    let (one, _): (WikiPage, usize) = bincode::decode_from_slice(&v, standard())?;
+                    ones.push(format!("{wid} - {rev}: {one:?}"));
+                }
                 "items" => {
                     let key = ivec_to_u32(&k);
                     let (one, _): (Item, usize) = bincode::decode_from_slice(&v, standard())?;
@@ -268,6 +498,7 @@ pub(crate) async fn admin_view(
         ones,
         tree_name,
         anchor,
+        cursor,
         is_desc,
         n,
     };
@@ -316,9 +547,60 @@ pub(crate) async fn admin_post(
 
     let site_config = bincode::encode_to_vec(&input, standard())?;
     db.insert("site_config", site_config)?;
+    mod_log(
+        &db,
+        0,
+        claim.uid,
+        "update site config".into(),
+        "/admin".into(),
+        String::new(),
+    )?;
     Ok(Redirect::to("/admin"))
 }
 
+/// `GET /admin/log` site-wide mod log
+pub(crate) async fn admin_log(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Query(params): Query<ParamsModLog>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+
+    let n = site_config.per_page;
+    let anchor = params.anchor.unwrap_or(0);
+    let is_desc = params.is_desc.unwrap_or(true);
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor: None,
+    };
+
+    let mod_logs: Vec<ModLog> =
+        get_batch(&db, "default", "mod_logs_count", "mod_logs", &page_params)?;
+    let mut logs = Vec::with_capacity(mod_logs.len());
+    for log in mod_logs {
+        logs.push(OutModLog::new(&db, log)?);
+    }
+
+    let page_data = PageData::new("Admin-log", &site_config, Some(claim), false);
+    let page_mod_log = PageModLog {
+        page_data,
+        logs,
+        iid: 0,
+        inn_name: site_config.site_name.clone(),
+        anchor,
+        n,
+        is_desc,
+    };
+    Ok(into_response(&page_mod_log, "html"))
+}
+
 impl Default for SiteConfig {
     fn default() -> Self {
         SiteConfig {
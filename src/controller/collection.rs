@@ -0,0 +1,388 @@
+//! ## Collections
+//!
+//! A collection combines the posts of some inns, tags and users into one timeline. It is private
+//! to its owner unless it is made public, and posts of private inns are only shown to members.
+
+use super::{
+    decode_cursor, get_ids_by_prefix, get_ids_by_tag, get_one, get_site_config, get_uid_by_name,
+    has_unread, incr_id,
+    inn::{get_out_post_list, get_pids_by_timeline, OutPostList, ParamsTag, PidsPage},
+    into_response, ivec_to_u32, u32_to_ivec, u8_slice_to_u32, Claim, Collection, Inn, PageData,
+    ParamsPage, User, ValidatedForm,
+};
+use crate::error::AppError;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State, TypedHeader},
+    headers::Cookie,
+    response::{IntoResponse, Redirect},
+};
+use bincode::config::standard;
+use chrono::Utc;
+use serde::Deserialize;
+use sled::Db;
+use std::collections::BTreeSet;
+use validator::Validate;
+
+/// Vec data: collection
+struct OutCollection {
+    id: u32,
+    name: String,
+    inns: usize,
+    tags: usize,
+    users: usize,
+    is_public: bool,
+}
+
+/// Page data: `collections.html`
+#[derive(Template)]
+#[template(path = "collections.html")]
+struct PageCollections<'a> {
+    page_data: PageData<'a>,
+    collections: Vec<OutCollection>,
+}
+
+/// `GET /user/collections` collections of the user
+pub(crate) async fn collection_list(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let mut collections = Vec::new();
+    for id in get_ids_by_prefix(&db, "user_collections", u32_to_ivec(claim.uid), None)? {
+        let collection: Collection = get_one(&db, "collections", id)?;
+        collections.push(OutCollection {
+            id,
+            name: collection.name,
+            inns: collection.iids.len(),
+            tags: collection.tags.len(),
+            users: collection.uids.len(),
+            is_public: collection.is_public,
+        });
+    }
+
+    let has_unread = has_unread(&db, claim.uid)?;
+    let page_data = PageData::new("Collections", &site_config, Some(claim), has_unread);
+    let page_collections = PageCollections {
+        page_data,
+        collections,
+    };
+    Ok(into_response(&page_collections, "html"))
+}
+
+/// Page data: `collection_edit.html`
+#[derive(Template)]
+#[template(path = "collection_edit.html")]
+struct PageCollectionEdit<'a> {
+    page_data: PageData<'a>,
+    id: u32,
+    name: String,
+    inns: String,
+    tags: String,
+    users: String,
+    is_public: bool,
+    joined_inns: Vec<String>,
+}
+
+/// `GET /collection/edit/:id` collection create/edit page
+///
+/// if id is 0, then create a new collection
+pub(crate) async fn collection_edit(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let mut joined_inns = Vec::new();
+    for iid in get_ids_by_prefix(&db, "user_inns", u32_to_ivec(claim.uid), None)? {
+        let inn: Inn = get_one(&db, "inns", iid)?;
+        joined_inns.push(inn.inn_name);
+    }
+
+    let claim_uid = claim.uid;
+    let page_data = PageData::new("edit collection", &site_config, Some(claim), false);
+    let mut page_collection_edit = PageCollectionEdit {
+        page_data,
+        id,
+        name: String::new(),
+        inns: String::new(),
+        tags: String::new(),
+        users: String::new(),
+        is_public: false,
+        joined_inns,
+    };
+
+    if id > 0 {
+        let collection: Collection = get_one(&db, "collections", id)?;
+        if collection.uid != claim_uid {
+            return Err(AppError::Unauthorized);
+        }
+        let mut inns = Vec::with_capacity(collection.iids.len());
+        for iid in collection.iids {
+            if let Ok(inn) = get_one::<Inn>(&db, "inns", iid) {
+                inns.push(inn.inn_name);
+            }
+        }
+        let mut users = Vec::with_capacity(collection.uids.len());
+        for uid in collection.uids {
+            if let Ok(user) = get_one::<User>(&db, "users", uid) {
+                users.push(user.username);
+            }
+        }
+        page_collection_edit.name = collection.name;
+        page_collection_edit.inns = inns.join(" #");
+        page_collection_edit.tags = collection.tags.join(" #");
+        page_collection_edit.users = users.join(" #");
+        page_collection_edit.is_public = collection.is_public;
+    }
+
+    Ok(into_response(&page_collection_edit, "html"))
+}
+
+/// Form data: `/collection/edit/:id` collection create/edit page
+#[derive(Deserialize, Validate)]
+pub(crate) struct FormCollection {
+    #[validate(length(min = 1, max = 64))]
+    name: String,
+    #[validate(length(max = 2048))]
+    inns: String,
+    #[validate(length(max = 512))]
+    tags: String,
+    #[validate(length(max = 512))]
+    users: String,
+    is_public: Option<bool>,
+}
+
+/// `POST /collection/edit/:id` collection create/edit page
+///
+/// Inns, tags and users are separated by `#`. Unknown inns and users are ignored.
+pub(crate) async fn collection_edit_post(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(id): Path<u32>,
+    ValidatedForm(input): ValidatedForm<FormCollection>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let split = |s: &str| -> Vec<String> {
+        let set: BTreeSet<String> = s
+            .split('#')
+            .map(|s| s.trim().to_owned())
+            .filter(|s| !s.is_empty())
+            .collect();
+        set.into_iter().collect()
+    };
+
+    let inn_names_tree = db.open_tree("inn_names")?;
+    let mut iids = Vec::new();
+    for name in split(&input.inns).iter().take(50) {
+        if let Some(v) = inn_names_tree.get(name)? {
+            iids.push(ivec_to_u32(&v));
+        }
+    }
+    let mut uids = Vec::new();
+    for name in split(&input.users).iter().take(20) {
+        if let Some(uid) = get_uid_by_name(&db, name)? {
+            uids.push(uid);
+        }
+    }
+    let mut tags = split(&input.tags);
+    tags.truncate(20);
+
+    let id = if id == 0 {
+        incr_id(&db, "collections_count")?
+    } else {
+        let collection: Collection = get_one(&db, "collections", id)?;
+        if collection.uid != claim.uid {
+            return Err(AppError::Unauthorized);
+        }
+        id
+    };
+
+    let collection = Collection {
+        id,
+        uid: claim.uid,
+        name: input.name,
+        iids,
+        tags,
+        uids,
+        is_public: input.is_public.unwrap_or_default(),
+        created_at: Utc::now().timestamp(),
+    };
+    let id_ivec = u32_to_ivec(id);
+    let collection_encoded = bincode::encode_to_vec(&collection, standard())?;
+    db.open_tree("collections")?
+        .insert(&id_ivec, collection_encoded)?;
+    let k = [&u32_to_ivec(claim.uid), &id_ivec].concat();
+    db.open_tree("user_collections")?.insert(k, &[])?;
+
+    let target = format!("/collection/{id}");
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /collection/:id/delete`
+pub(crate) async fn collection_delete(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(id): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let collection: Collection = get_one(&db, "collections", id)?;
+    if collection.uid != claim.uid {
+        return Err(AppError::Unauthorized);
+    }
+    let id_ivec = u32_to_ivec(id);
+    db.open_tree("collections")?.remove(&id_ivec)?;
+    let k = [&u32_to_ivec(claim.uid), &id_ivec].concat();
+    db.open_tree("user_collections")?.remove(k)?;
+
+    Ok(Redirect::to("/user/collections"))
+}
+
+/// Page data: `collection.html`
+#[derive(Template)]
+#[template(path = "collection.html")]
+struct PageCollection<'a> {
+    page_data: PageData<'a>,
+    posts: Vec<OutPostList>,
+    anchor: usize,
+    prev_cursor: String,
+    cursor: String,
+    n: usize,
+    is_desc: bool,
+    id: u32,
+    name: String,
+    uid: u32,
+    username: String,
+    inns: Vec<(u32, String)>,
+    tags: Vec<String>,
+    users: Vec<(u32, String)>,
+    is_public: bool,
+    is_owner: bool,
+}
+
+/// `GET /collection/:id` combined timeline of a collection. Only the owner can see it unless it is public.
+pub(crate) async fn collection(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(id): Path<u32>,
+    Query(params): Query<ParamsTag>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+
+    let collection: Collection = get_one(&db, "collections", id)?;
+    let claim_uid = claim.as_ref().map(|claim| claim.uid);
+    let is_owner = claim_uid == Some(collection.uid);
+    if !collection.is_public && !is_owner {
+        return Err(AppError::NotFound);
+    }
+
+    let n = site_config.per_page;
+    let anchor = params.anchor.unwrap_or(0);
+    let is_desc = params.is_desc.unwrap_or(true);
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let is_prev = params.prev.unwrap_or_default() && cursor.is_some();
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc: is_desc != is_prev,
+        cursor,
+    };
+
+    let joined_inns = match claim_uid {
+        Some(uid) => get_ids_by_prefix(&db, "user_inns", u32_to_ivec(uid), None)?,
+        None => vec![],
+    };
+    let (mut index, mut prev_cursor, mut cursor) =
+        get_pids_by_collection(&db, &collection, &joined_inns, &page_params)?;
+    if is_prev {
+        index.reverse();
+        std::mem::swap(&mut prev_cursor, &mut cursor);
+    }
+    let out_post_list = get_out_post_list(&db, &index)?;
+
+    let mut inns = Vec::with_capacity(collection.iids.len());
+    for iid in collection.iids {
+        if let Ok(inn) = get_one::<Inn>(&db, "inns", iid) {
+            inns.push((iid, inn.inn_name));
+        }
+    }
+    let mut users = Vec::with_capacity(collection.uids.len());
+    for uid in collection.uids {
+        if let Ok(user) = get_one::<User>(&db, "users", uid) {
+            users.push((uid, user.username));
+        }
+    }
+    let user: User = get_one(&db, "users", collection.uid)?;
+
+    let has_unread = match claim_uid {
+        Some(uid) => has_unread(&db, uid)?,
+        None => false,
+    };
+    let page_data = PageData::new("collection", &site_config, claim, has_unread);
+    let page_collection = PageCollection {
+        page_data,
+        posts: out_post_list,
+        anchor,
+        prev_cursor,
+        cursor,
+        n,
+        is_desc,
+        id,
+        name: collection.name,
+        uid: collection.uid,
+        username: user.username,
+        inns,
+        tags: collection.tags,
+        users,
+        is_public: collection.is_public,
+        is_owner,
+    };
+
+    Ok(into_response(&page_collection, "html"))
+}
+
+/// get pids of a collection: the posts of its inns, tags and users, sorted by timestamp.
+///
+/// Private inns are skipped unless the viewer has joined them. The cursors are
+/// `timestamp#iid#pid`, the same as [get_pids_by_timeline].
+fn get_pids_by_collection(
+    db: &Db,
+    collection: &Collection,
+    joined_inns: &[u32],
+    page_params: &ParamsPage,
+) -> Result<PidsPage, AppError> {
+    // pids of the posts by tags and users, only ids from the index trees
+    let mut pids = BTreeSet::new();
+    for tag in &collection.tags {
+        pids.extend(get_ids_by_tag(db, "tags", tag, None)?);
+    }
+    let user_posts_tree = db.open_tree("user_posts")?;
+    for uid in &collection.uids {
+        // kv_pair: uid#pid = iid#visibility
+        for i in user_posts_tree.scan_prefix(u32_to_ivec(*uid)) {
+            let (k, _) = i?;
+            pids.insert(u8_slice_to_u32(&k[4..8]));
+        }
+    }
+
+    let inns_private_tree = db.open_tree("inns_private")?;
+    get_pids_by_timeline(db, page_params, |iid, pid| {
+        if !collection.iids.contains(&iid) && !pids.contains(&pid) {
+            return Ok(false);
+        }
+        Ok(joined_inns.contains(&iid) || !inns_private_tree.contains_key(u32_to_ivec(iid))?)
+    })
+}
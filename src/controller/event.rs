@@ -0,0 +1,392 @@
+//! ## Events
+//!
+//! A post can be an event with the start, end and location. Members rsvp to events as going,
+//! maybe or not. Upcoming events of an inn are listed on its events page, and the events can be
+//! subscribed as iCalendar, with the feed token of the user for private inns.
+
+use super::{
+    get_one, get_site_config, has_unread,
+    inn::{can_view_inn, get_uid_by_feed_token},
+    into_response, u32_to_ivec, u8_slice_to_u32, Claim, Event, Inn, InnType, PageData, Post,
+};
+use crate::error::AppError;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State, TypedHeader},
+    headers::Cookie,
+    http::header::CONTENT_TYPE,
+    response::{IntoResponse, Redirect, Response},
+};
+use bincode::config::standard;
+use chrono::{NaiveDateTime, Utc};
+use serde::Deserialize;
+use sled::Db;
+
+/// get the start and end of a new event. None if there is no event.
+pub(super) fn get_event_time(
+    start: Option<&str>,
+    end: Option<&str>,
+) -> Result<Option<(i64, i64)>, AppError> {
+    let parse = |s: &str| {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M")
+            .map(|t| t.timestamp())
+            .map_err(|_| AppError::InvalidEvent)
+    };
+    let start = match start.filter(|s| !s.is_empty()) {
+        Some(s) => parse(s)?,
+        None => return Ok(None),
+    };
+    let end = match end.filter(|s| !s.is_empty()) {
+        Some(s) => parse(s)?,
+        None => start + 3600,
+    };
+    if end < start {
+        return Err(AppError::InvalidEvent);
+    }
+    Ok(Some((start, end)))
+}
+
+/// format the timestamp of events, in UTC
+fn event_time(timestamp: i64) -> String {
+    NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .unwrap()
+        .format("%Y-%m-%d %H:%M UTC")
+        .to_string()
+}
+
+/// get the event of a post. Hidden and pending posts have no event.
+pub(super) fn get_event(db: &Db, pid: u32) -> Result<Option<(Event, Post)>, AppError> {
+    let event: Event = match db.open_tree("events")?.get(u32_to_ivec(pid))? {
+        Some(v) => bincode::decode_from_slice(&v, standard())?.0,
+        None => return Ok(None),
+    };
+    let post: Post = get_one(db, "posts", pid)?;
+    let k = [&u32_to_ivec(post.iid), &u32_to_ivec(pid)].concat();
+    if post.is_hidden || db.open_tree("post_pending")?.contains_key(k)? {
+        return Ok(None);
+    }
+    Ok(Some((event, post)))
+}
+
+/// Vec data: event
+pub(super) struct OutEvent {
+    pub(super) pid: u32,
+    pub(super) iid: u32,
+    pub(super) title: String,
+    pub(super) start: String,
+    pub(super) end: String,
+    pub(super) location: String,
+    pub(super) going: usize,
+    pub(super) maybe: usize,
+    pub(super) not: usize,
+    /// 1 going, 2 maybe, 3 not, 0 no rsvp
+    pub(super) rsvp: u8,
+    pub(super) is_over: bool,
+}
+
+impl OutEvent {
+    pub(super) fn new(
+        db: &Db,
+        event: Event,
+        post: Post,
+        claim_uid: Option<u32>,
+    ) -> Result<Self, AppError> {
+        let mut counts = [0; 4];
+        let mut rsvp = 0;
+        // kv_pair: pid#uid = 1/2/3
+        for i in db
+            .open_tree("event_rsvps")?
+            .scan_prefix(u32_to_ivec(event.pid))
+        {
+            let (k, v) = i?;
+            let status = v[0].min(3);
+            counts[status as usize] += 1;
+            if Some(u8_slice_to_u32(&k[4..8])) == claim_uid {
+                rsvp = status;
+            }
+        }
+        Ok(OutEvent {
+            pid: event.pid,
+            iid: event.iid,
+            title: post.title,
+            start: event_time(event.start),
+            end: event_time(event.end),
+            location: event.location,
+            going: counts[1],
+            maybe: counts[2],
+            not: counts[3],
+            rsvp,
+            is_over: event.end < Utc::now().timestamp(),
+        })
+    }
+}
+
+/// `GET /post/:iid/:pid/rsvp/:status` rsvp to an event, status is `going`, `maybe` or `not`.
+///
+/// The same status again cancels it.
+pub(crate) async fn event_rsvp(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid, status)): Path<(u32, u32, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let status: u8 = match status.as_str() {
+        "going" => 1,
+        "maybe" => 2,
+        "not" => 3,
+        _ => return Err(AppError::NotFound),
+    };
+    let (event, _) = get_event(&db, pid)?.ok_or(AppError::NotFound)?;
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if event.iid != iid || !can_view_inn(&db, &inn, Some(claim.uid))? {
+        return Err(AppError::NotFound);
+    }
+    if inn.inn_type == InnType::Archive {
+        return Err(AppError::InnArchived);
+    }
+
+    let k = [&u32_to_ivec(pid), &u32_to_ivec(claim.uid)].concat();
+    let event_rsvps_tree = db.open_tree("event_rsvps")?;
+    if event_rsvps_tree.get(&k)?.as_deref() == Some(&[status]) {
+        event_rsvps_tree.remove(&k)?;
+    } else {
+        event_rsvps_tree.insert(&k, &[status])?;
+    }
+
+    let target = format!("/post/{iid}/{pid}#event");
+    Ok(Redirect::to(&target))
+}
+
+/// Page data: `inn_events.html`
+#[derive(Template)]
+#[template(path = "inn_events.html")]
+struct PageInnEvents<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    events: Vec<OutEvent>,
+}
+
+/// `GET /inn/:iid/events` upcoming events of the inn
+pub(crate) async fn inn_events(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+    let claim_uid = claim.as_ref().map(|claim| claim.uid);
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if !can_view_inn(&db, &inn, claim_uid)? {
+        return Err(AppError::NotFound);
+    }
+
+    let now = Utc::now().timestamp();
+    let mut events = Vec::new();
+    for (event, post) in get_inn_events(&db, iid)? {
+        if event.end >= now {
+            events.push(OutEvent::new(&db, event, post, claim_uid)?);
+        }
+    }
+
+    let has_unread = match claim_uid {
+        Some(uid) => has_unread(&db, uid)?,
+        None => false,
+    };
+    let page_data = PageData::new("Events", &site_config, claim, has_unread);
+    let page_inn_events = PageInnEvents {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        events,
+    };
+
+    Ok(into_response(&page_inn_events, "html"))
+}
+
+/// get the events of the inn, sorted by start time
+fn get_inn_events(db: &Db, iid: u32) -> Result<Vec<(Event, Post)>, AppError> {
+    let mut events = Vec::new();
+    // kv_pair: iid#start#pid = &[]
+    for i in db.open_tree("inn_calendar")?.scan_prefix(u32_to_ivec(iid)) {
+        let (k, _) = i?;
+        if let Some(event) = get_event(db, u8_slice_to_u32(&k[8..12]))? {
+            events.push(event);
+        }
+    }
+    Ok(events)
+}
+
+/// url params: `.ics` calendars
+#[derive(Deserialize)]
+pub(crate) struct ParamsIcs {
+    /// feed token of the user, unlocks private inns
+    token: Option<String>,
+}
+
+/// get the viewer of calendars by the feed token or the cookie
+fn get_ics_uid(
+    db: &Db,
+    cookie: Option<TypedHeader<Cookie>>,
+    token: Option<&str>,
+) -> Result<Option<u32>, AppError> {
+    if let Some(uid) = get_uid_by_feed_token(db, token)? {
+        return Ok(Some(uid));
+    }
+    let site_config = get_site_config(db)?;
+    Ok(cookie
+        .and_then(|cookie| Claim::get(db, &cookie, &site_config))
+        .map(|claim| claim.uid))
+}
+
+/// `GET /post/:iid/:pid/event.ics` iCalendar of an event
+pub(crate) async fn event_ics(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid)): Path<(u32, u32)>,
+    Query(params): Query<ParamsIcs>,
+) -> Result<impl IntoResponse, AppError> {
+    let uid = get_ics_uid(&db, cookie, params.token.as_deref())?;
+    let (event, post) = get_event(&db, pid)?.ok_or(AppError::NotFound)?;
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if event.iid != iid || !can_view_inn(&db, &inn, uid)? {
+        return Err(AppError::NotFound);
+    }
+
+    let site_config = get_site_config(&db)?;
+    let name = post.title.clone();
+    Ok(render_ics(&name, &[(event, post)], &site_config.domain))
+}
+
+/// `GET /inn/:iid/events.ics` iCalendar of all events in the inn
+pub(crate) async fn inn_ics(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+    Query(params): Query<ParamsIcs>,
+) -> Result<impl IntoResponse, AppError> {
+    let uid = get_ics_uid(&db, cookie, params.token.as_deref())?;
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if !can_view_inn(&db, &inn, uid)? {
+        return Err(AppError::NotFound);
+    }
+
+    let events = get_inn_events(&db, iid)?;
+    let site_config = get_site_config(&db)?;
+    Ok(render_ics(&inn.inn_name, &events, &site_config.domain))
+}
+
+/// render events to iCalendar, see [RFC 5545](https://www.rfc-editor.org/rfc/rfc5545)
+fn render_ics(name: &str, events: &[(Event, Post)], domain: &str) -> Response {
+    // TEXT values escape `\`, `;`, `,` and newlines
+    let escape = |s: &str| {
+        s.replace('\\', "\\\\")
+            .replace(';', "\\;")
+            .replace(',', "\\,")
+            .replace("\r\n", "\\n")
+            .replace('\n', "\\n")
+    };
+    let time = |timestamp: i64| {
+        NaiveDateTime::from_timestamp_opt(timestamp, 0)
+            .unwrap()
+            .format("%Y%m%dT%H%M%SZ")
+            .to_string()
+    };
+    let domain = domain.trim_end_matches('/');
+    let host = domain.split("://").last().unwrap_or_default();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//freedit//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+        format!("X-WR-CALNAME:{}", escape(name)),
+    ];
+    for (event, post) in events {
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:event-{}@{host}", event.pid));
+        lines.push(format!("DTSTAMP:{}", time(post.created_at)));
+        lines.push(format!("DTSTART:{}", time(event.start)));
+        lines.push(format!("DTEND:{}", time(event.end)));
+        lines.push(format!("SUMMARY:{}", escape(&post.title)));
+        if !event.location.is_empty() {
+            lines.push(format!("LOCATION:{}", escape(&event.location)));
+        }
+        lines.push(format!("URL:{domain}/post/{}/{}", event.iid, event.pid));
+        lines.push("END:VEVENT".to_owned());
+    }
+    lines.push("END:VCALENDAR".to_owned());
+
+    // lines longer than 75 octets are folded
+    let mut body = String::new();
+    for line in lines {
+        let mut len = 0;
+        for c in line.chars() {
+            if len + c.len_utf8() > 75 {
+                body.push_str("\r\n ");
+                len = 1;
+            }
+            body.push(c);
+            len += c.len_utf8();
+        }
+        body.push_str("\r\n");
+    }
+
+    ([(CONTENT_TYPE, "text/calendar; charset=utf-8")], body).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::tests::{body_string, new_post};
+
+    #[tokio::test]
+    async fn test_render_ics() {
+        let event = |pid, location: &str| Event {
+            pid,
+            iid: 1,
+            start: 3600,
+            end: 7200,
+            location: location.into(),
+        };
+        let events = [
+            (
+                event(1, "Room 1\nFloor 2"),
+                new_post(1, 1, "Meetup; Rust, Web"),
+            ),
+            (event(2, ""), new_post(2, 1, &"long ".repeat(20))),
+        ];
+        let body = body_string(render_ics("inn", &events, "https://example.com/")).await;
+
+        // lines are folded at 75 octets, and continued with a space
+        assert!(body.split("\r\n").all(|line| line.len() <= 75));
+        assert!(body.split("\r\n").any(|line| line.starts_with(' ')));
+        let unfolded = body.replace("\r\n ", "");
+
+        let first_event: Vec<&str> = unfolded
+            .split("\r\n")
+            .skip_while(|line| *line != "BEGIN:VEVENT")
+            .take_while(|line| *line != "END:VEVENT")
+            .collect();
+        assert_eq!(
+            first_event,
+            [
+                "BEGIN:VEVENT",
+                "UID:event-1@example.com",
+                "DTSTAMP:19700101T000000Z",
+                "DTSTART:19700101T010000Z",
+                "DTEND:19700101T020000Z",
+                "SUMMARY:Meetup\\; Rust\\, Web",
+                "LOCATION:Room 1\\nFloor 2",
+                "URL:https://example.com/post/1/1",
+            ]
+        );
+        assert!(unfolded.contains(&format!("SUMMARY:{}\r\n", "long ".repeat(20))));
+        assert!(unfolded.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(unfolded.ends_with("END:VCALENDAR\r\n"));
+    }
+}
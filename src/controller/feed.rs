@@ -1,6 +1,11 @@
 use super::{
-    get_ids_by_prefix, get_one, get_range, get_referer, get_site_config, into_response,
-    timestamp_to_date, u32_to_ivec, u8_slice_to_u32, Claim, PageData, ParamsPage, SourceItem, User,
+    date_to_u32, decode_cursor, encode_cursor, get_ids_by_prefix, get_inn_role, get_one, get_page,
+    get_referer, get_site_config, get_uid_by_name,
+    inn::{get_item_posts, publish_feed_post},
+    into_response, is_mod, mod_log,
+    solo::get_item_solos,
+    timestamp_to_date, u32_to_ivec, u8_slice_to_u32, Claim, Inn, InnFeed, PageData, ParamsPage,
+    SourceItem, User, ValidatedForm,
 };
 use crate::{
     controller::{incr_id, ivec_to_u32, Feed, Item},
@@ -34,6 +39,7 @@ struct PageFeed<'a> {
     filter: Option<String>,
     filter_value: Option<String>,
     anchor: usize,
+    cursor: String,
     n: usize,
     is_desc: bool,
     uid: u32,
@@ -78,6 +84,7 @@ struct OutItem {
 #[derive(Deserialize)]
 pub(crate) struct ParamsFeed {
     anchor: Option<usize>,
+    cursor: Option<String>,
     is_desc: Option<bool>,
     filter: Option<String>,
     filter_value: Option<String>,
@@ -249,16 +256,24 @@ pub(crate) async fn feed(
         let mut ids = get_item_ids_and_ts(&db, "feed_items", id)?;
         item_ids.append(&mut ids);
     }
-    item_ids.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+    item_ids.sort_unstable_by_key(|(id, ts)| (*ts, *id));
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
-    let page_params = ParamsPage { anchor, n, is_desc };
-    let (start, end) = get_range(item_ids.len(), &page_params);
-    item_ids = item_ids[start - 1..end].to_vec();
-    if is_desc {
-        item_ids.reverse();
-    }
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor,
+    };
+    // cursor: timestamp#item_id
+    let key = |(id, ts): &(u32, i64)| [&ts.to_be_bytes()[..], &u32_to_ivec(*id)].concat();
+    let item_ids = get_page(&item_ids, key, &page_params);
+    let cursor = item_ids
+        .last()
+        .map(|i| encode_cursor(&key(i)))
+        .unwrap_or_default();
     let mut items = Vec::with_capacity(n);
     let star_tree = db.open_tree("star")?;
     let read_tree = db.open_tree("read")?;
@@ -295,6 +310,7 @@ pub(crate) async fn feed(
         filter_value: params.filter_value,
         n,
         anchor,
+        cursor,
         is_desc,
         uid,
         username,
@@ -331,6 +347,9 @@ struct PageFeedRead<'a> {
     page_data: PageData<'a>,
     item: OutItemRead,
     allow_img: bool,
+    /// the posts shared from the item: (iid, pid, title)
+    posts: Vec<(u32, u32, String)>,
+    solos: Vec<u32>,
 }
 
 /// url params: `feed_read.html`
@@ -371,17 +390,54 @@ pub(crate) async fn feed_read(
         db.open_tree("read")?.insert(k, &[])?;
     }
 
+    let claim_uid = claim.as_ref().map(|claim| claim.uid);
+    let posts = get_item_posts(&db, item_id, claim_uid)?;
+    let solos = get_item_solos(&db, item_id, claim_uid)?;
+
     let allow_img = params.allow_img.unwrap_or_default();
     let page_data = PageData::new("Feed", &site_config, claim, false);
     let page_feed_read = PageFeedRead {
         page_data,
         item: out_item_read,
         allow_img,
+        posts,
+        solos,
     };
 
     Ok(into_response(&page_feed_read, "html"))
 }
 
+/// get the plain text excerpt of the item for sharing, with at most `max` chars
+pub(super) fn item_excerpt(item: &Item, max: usize) -> String {
+    let text = strip_tags(&item.content);
+    if text.chars().count() > max {
+        let mut excerpt: String = text.chars().take(max).collect();
+        excerpt.push('…');
+        excerpt
+    } else {
+        text
+    }
+}
+
+/// strip the html tags and collapse the whitespace
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                text.push(' ');
+            }
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 /// Page data: `feed_add.html`
 #[derive(Template)]
 #[template(path = "feed_add.html")]
@@ -448,36 +504,16 @@ pub(crate) async fn feed_add_post(
     let cookie = cookie.ok_or(AppError::NonLogin)?;
     let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
 
-    let (feed, item_ids) = update(&form.url, &db).await?;
-    let feed_links_tree = db.open_tree("feed_links")?;
+    let (feed_id, _) = add_feed(&db, &form.url).await?;
     let user_folders_tree = db.open_tree("user_folders")?;
-    let feed_id = if let Some(v) = feed_links_tree.get(&feed.link)? {
-        let id = ivec_to_u32(&v);
-        // change folder(remove the old record)
-        for i in user_folders_tree.scan_prefix(u32_to_ivec(claim.uid)) {
-            let (k, _) = i?;
-            if u8_slice_to_u32(&k[k.len() - 4..]) == id {
-                user_folders_tree.remove(k)?;
-            }
+    // change folder(remove the old record)
+    for i in user_folders_tree.scan_prefix(u32_to_ivec(claim.uid)) {
+        let (k, _) = i?;
+        if u8_slice_to_u32(&k[k.len() - 4..]) == feed_id {
+            user_folders_tree.remove(k)?;
         }
-        ivec_to_u32(&v)
-    } else {
-        incr_id(&db, "feeds_count")?
-    };
-
-    let feed_items_tree = db.open_tree("feed_items")?;
-    let feed_id_ivec = u32_to_ivec(feed_id);
-    for (id, ts) in item_ids {
-        let k = [&feed_id_ivec, &u32_to_ivec(id)].concat();
-        feed_items_tree.insert(k, i64_to_ivec(ts))?;
     }
 
-    feed_links_tree.insert(&feed.link, u32_to_ivec(feed_id))?;
-
-    let feeds_tree = db.open_tree("feeds")?;
-    let feed_encode = bincode::encode_to_vec(&feed, standard())?;
-    feeds_tree.insert(u32_to_ivec(feed_id), feed_encode)?;
-
     let folder = if form.folder.as_str() != "New" {
         form.folder
     } else if !form.new_folder.is_empty() {
@@ -498,6 +534,32 @@ pub(crate) async fn feed_add_post(
     Ok(Redirect::to(&format!("/feed/{}", claim.uid)))
 }
 
+/// fetch the feed and save it with its items. Returns the feed id and the feed.
+async fn add_feed(db: &Db, url: &str) -> Result<(u32, Feed), AppError> {
+    let (feed, item_ids) = update(url, db).await?;
+    let feed_links_tree = db.open_tree("feed_links")?;
+    let feed_id = if let Some(v) = feed_links_tree.get(&feed.link)? {
+        ivec_to_u32(&v)
+    } else {
+        incr_id(db, "feeds_count")?
+    };
+
+    let feed_items_tree = db.open_tree("feed_items")?;
+    let feed_id_ivec = u32_to_ivec(feed_id);
+    for (id, ts) in item_ids {
+        let k = [&feed_id_ivec, &u32_to_ivec(id)].concat();
+        feed_items_tree.insert(k, i64_to_ivec(ts))?;
+    }
+
+    feed_links_tree.insert(&feed.link, u32_to_ivec(feed_id))?;
+
+    let feeds_tree = db.open_tree("feeds")?;
+    let feed_encode = bincode::encode_to_vec(&feed, standard())?;
+    feeds_tree.insert(u32_to_ivec(feed_id), feed_encode)?;
+
+    Ok((feed_id, feed))
+}
+
 /// `GET /feed/update`
 pub(crate) async fn feed_update(
     State(db): State<Db>,
@@ -637,6 +699,10 @@ pub(crate) async fn cron_feed(db: &Db) -> Result<(), AppError> {
         let feed_id = u8_slice_to_u32(&k[(k.len() - 4)..]);
         set.insert(feed_id);
     }
+    for i in &db.open_tree("inn_feeds")? {
+        let (k, _) = i?;
+        set.insert(u8_slice_to_u32(&k[4..8]));
+    }
 
     let feed_items_tree = db.open_tree("feed_items")?;
     let feed_errs_tree = db.open_tree("feed_errs")?;
@@ -658,9 +724,307 @@ pub(crate) async fn cron_feed(db: &Db) -> Result<(), AppError> {
         };
     }
 
+    post_inn_feeds(db)
+}
+
+impl InnFeed {
+    /// whether the item passes the keyword filters
+    fn is_match(&self, item: &Item) -> bool {
+        let text = format!("{} {}", item.title, item.content).to_lowercase();
+        let mut has_include = false;
+        let mut is_included = false;
+        for keyword in self.keywords.split(',') {
+            let keyword = keyword.trim().to_lowercase();
+            if keyword.is_empty() {
+                continue;
+            }
+            if let Some(excluded) = keyword.strip_prefix('-') {
+                if text.contains(excluded) {
+                    return false;
+                }
+            } else {
+                has_include = true;
+                is_included |= text.contains(&keyword);
+            }
+        }
+        !has_include || is_included
+    }
+}
+
+/// post the new items of the inn feeds by the bot users, the oldest first.
+///
+/// Items over the daily limit wait for the next day. If the bot can't post, the items wait until
+/// mods fix it.
+fn post_inn_feeds(db: &Db) -> Result<(), AppError> {
+    let inn_feeds_tree = db.open_tree("inn_feeds")?;
+    let inn_feed_items_tree = db.open_tree("inn_feed_items")?;
+    let item_posts_tree = db.open_tree("item_posts")?;
+    let today = date_to_u32(Utc::now().date_naive());
+
+    for i in &inn_feeds_tree {
+        let (k, v) = i?;
+        let (mut inn_feed, _): (InnFeed, usize) = bincode::decode_from_slice(&v, standard())?;
+        if inn_feed.posted_date != today {
+            inn_feed.posted_date = today;
+            inn_feed.posted_today = 0;
+        }
+        let Ok(feed) = get_one::<Feed>(db, "feeds", inn_feed.feed_id) else {
+            continue;
+        };
+
+        let mut items = get_item_ids_and_ts(db, "feed_items", inn_feed.feed_id)?;
+        items.sort_unstable_by_key(|(_, ts)| *ts);
+        let iid_ivec = u32_to_ivec(inn_feed.iid);
+        for (item_id, _) in items {
+            if inn_feed.max_per_day > 0 && inn_feed.posted_today >= inn_feed.max_per_day {
+                break;
+            }
+            let seen_k = [&iid_ivec, &u32_to_ivec(item_id)].concat();
+            if inn_feed_items_tree.contains_key(&seen_k)? {
+                continue;
+            }
+
+            let item: Item = get_one(db, "items", item_id)?;
+            let mut pid = 0;
+            if inn_feed.is_match(&item) {
+                // titles are rendered as html, feeds can't inject markup
+                let title = inn_feed
+                    .title_template
+                    .replace("{title}", &item.title)
+                    .replace("{feed}", &feed.title);
+                let title: String = strip_tags(&title).chars().take(256).collect();
+                let tags = inn_feed.tags.replace("{feed}", &feed.title);
+                let content = format!("> {}\n\n<{}>", item_excerpt(&item, 500), item.link);
+                match publish_feed_post(
+                    db,
+                    inn_feed.iid,
+                    inn_feed.uid,
+                    title,
+                    &tags,
+                    content,
+                    &item.link,
+                ) {
+                    Ok(Some(id)) => {
+                        pid = id;
+                        inn_feed.posted_today += 1;
+                        let k = [&u32_to_ivec(item_id), &u32_to_ivec(pid)].concat();
+                        item_posts_tree.insert(k, &[])?;
+                    }
+                    // the link has been posted in the inn
+                    Ok(None) => {}
+                    Err(e) => {
+                        error!(
+                            "inn {} feed {} failed, error: {e}",
+                            inn_feed.iid, feed.title
+                        );
+                        break;
+                    }
+                }
+            }
+            // kv_pair: iid#item_id = pid, 0 means skipped
+            inn_feed_items_tree.insert(seen_k, u32_to_ivec(pid))?;
+        }
+
+        let inn_feed_encoded = bincode::encode_to_vec(&inn_feed, standard())?;
+        inn_feeds_tree.insert(k, inn_feed_encoded)?;
+    }
+
     Ok(())
 }
 
+/// Page data: `mod_feeds.html`
+#[derive(Template)]
+#[template(path = "mod_feeds.html")]
+struct PageModFeeds<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    feeds: Vec<OutInnFeed>,
+}
+
+/// Vec data: inn feed
+struct OutInnFeed {
+    feed_id: u32,
+    title: String,
+    link: String,
+    uid: u32,
+    username: String,
+    title_template: String,
+    tags: String,
+    keywords: String,
+    max_per_day: u32,
+    posted_today: u32,
+    err: Option<String>,
+}
+
+/// `GET /mod/:iid/feeds` feeds posted into the inn by bots
+pub(crate) async fn mod_feeds(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+    let inn: Inn = get_one(&db, "inns", iid)?;
+
+    let today = date_to_u32(Utc::now().date_naive());
+    let feed_errs_tree = db.open_tree("feed_errs")?;
+    let mut feeds = Vec::new();
+    for i in db.open_tree("inn_feeds")?.scan_prefix(u32_to_ivec(iid)) {
+        let (_, v) = i?;
+        let (inn_feed, _): (InnFeed, usize) = bincode::decode_from_slice(&v, standard())?;
+        let feed: Feed = get_one(&db, "feeds", inn_feed.feed_id)?;
+        let user: User = get_one(&db, "users", inn_feed.uid)?;
+        let err = feed_errs_tree
+            .get(u32_to_ivec(inn_feed.feed_id))?
+            .map(|v| String::from_utf8_lossy(&v).into_owned());
+        feeds.push(OutInnFeed {
+            feed_id: inn_feed.feed_id,
+            title: feed.title,
+            link: feed.link,
+            uid: inn_feed.uid,
+            username: user.username,
+            title_template: inn_feed.title_template,
+            tags: inn_feed.tags,
+            keywords: inn_feed.keywords,
+            max_per_day: inn_feed.max_per_day,
+            posted_today: if inn_feed.posted_date == today {
+                inn_feed.posted_today
+            } else {
+                0
+            },
+            err,
+        });
+    }
+
+    let page_data = PageData::new("feeds", &site_config, Some(claim), false);
+    let page_mod_feeds = PageModFeeds {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        feeds,
+    };
+
+    Ok(into_response(&page_mod_feeds, "html"))
+}
+
+/// Form data: `/mod/:iid/feeds` attach a feed
+#[derive(Deserialize, Validate)]
+pub(crate) struct FormInnFeed {
+    #[validate(length(max = 256))]
+    url: String,
+    #[validate(length(min = 1, max = 32))]
+    username: String,
+    #[validate(length(max = 256))]
+    title_template: String,
+    #[validate(length(max = 128))]
+    tags: String,
+    #[validate(length(max = 256))]
+    keywords: String,
+    max_per_day: u32,
+}
+
+/// `POST /mod/:iid/feeds` attach a feed
+pub(crate) async fn mod_feeds_post(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+    ValidatedForm(input): ValidatedForm<FormInnFeed>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+
+    // mods can only name themselves or a bot account set by admin
+    let uid = get_uid_by_name(&db, input.username.trim())?.ok_or(AppError::NotFound)?;
+    let bot: User = get_one(&db, "users", uid)?;
+    if (uid != claim.uid && bot.role != 20) || get_inn_role(&db, iid, uid)? < Some(4) {
+        return Err(AppError::InvalidFeedBot);
+    }
+
+    let (feed_id, feed) = add_feed(&db, input.url.trim()).await?;
+    let iid_ivec = u32_to_ivec(iid);
+
+    // the items already in the feed are not posted
+    let inn_feed_items_tree = db.open_tree("inn_feed_items")?;
+    for (item_id, _) in get_item_ids_and_ts(&db, "feed_items", feed_id)? {
+        let k = [&iid_ivec, &u32_to_ivec(item_id)].concat();
+        if !inn_feed_items_tree.contains_key(&k)? {
+            inn_feed_items_tree.insert(k, u32_to_ivec(0))?;
+        }
+    }
+
+    let title_template = if input.title_template.trim().is_empty() {
+        "{title}".to_owned()
+    } else {
+        input.title_template.trim().to_owned()
+    };
+    let inn_feed = InnFeed {
+        iid,
+        feed_id,
+        uid,
+        title_template,
+        tags: input.tags,
+        keywords: input.keywords,
+        max_per_day: input.max_per_day,
+        posted_date: 0,
+        posted_today: 0,
+        created_at: Utc::now().timestamp(),
+    };
+    let k = [&iid_ivec, &u32_to_ivec(feed_id)].concat();
+    let inn_feed_encoded = bincode::encode_to_vec(&inn_feed, standard())?;
+    db.open_tree("inn_feeds")?.insert(k, inn_feed_encoded)?;
+
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        format!("attach feed {}", feed.title),
+        format!("/inn/{iid}"),
+        String::new(),
+    )?;
+
+    let target = format!("/mod/{iid}/feeds");
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /mod/:iid/feeds/:feed_id/delete` detach a feed
+pub(crate) async fn mod_feed_delete(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, feed_id)): Path<(u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(feed_id)].concat();
+    if db.open_tree("inn_feeds")?.remove(&k)?.is_some() {
+        let feed: Feed = get_one(&db, "feeds", feed_id)?;
+        mod_log(
+            &db,
+            iid,
+            claim.uid,
+            format!("detach feed {}", feed.title),
+            format!("/inn/{iid}"),
+            String::new(),
+        )?;
+    }
+
+    let target = format!("/mod/{iid}/feeds");
+    Ok(Redirect::to(&target))
+}
+
 /// `GET /feed/star`
 pub(crate) async fn feed_star(
     State(db): State<Db>,
@@ -729,3 +1093,150 @@ pub(crate) async fn feed_subscribe(
 fn i64_to_ivec(number: i64) -> IVec {
     IVec::from(number.to_be_bytes().to_vec())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_item(title: &str, content: &str) -> Item {
+        Item {
+            link: format!("https://example.com/{}", title.replace(' ', "-")),
+            title: title.into(),
+            feed_title: "blog".into(),
+            updated: 0,
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn test_item_excerpt() {
+        let item = new_item("a", "<p>Hello,\n  <b>world</b>!</p><img src=\"x.png\">");
+        assert_eq!(item_excerpt(&item, 100), "Hello, world !");
+        assert_eq!(item_excerpt(&item, 5), "Hello…");
+
+        let item = new_item("b", "<p>你好世界</p>");
+        assert_eq!(item_excerpt(&item, 2), "你好…");
+        assert_eq!(item_excerpt(&item, 4), "你好世界");
+    }
+
+    fn new_inn_feed(keywords: &str, max_per_day: u32) -> InnFeed {
+        InnFeed {
+            iid: 1,
+            feed_id: 1,
+            uid: 2,
+            title_template: "{title}".into(),
+            tags: "{feed}".into(),
+            keywords: keywords.into(),
+            max_per_day,
+            posted_date: 0,
+            posted_today: 0,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_inn_feed_is_match() {
+        let item = new_item("Rust 1.70 released", "<p>The Rust team is happy</p>");
+        assert!(new_inn_feed("", 0).is_match(&item));
+        assert!(new_inn_feed(" , ", 0).is_match(&item));
+        // one of the keywords, case insensitive
+        assert!(new_inn_feed("go, RUST", 0).is_match(&item));
+        assert!(!new_inn_feed("go,zig", 0).is_match(&item));
+        // none of the excluded ones, in the title or the content
+        assert!(new_inn_feed("-job", 0).is_match(&item));
+        assert!(!new_inn_feed("-team", 0).is_match(&item));
+        assert!(!new_inn_feed("rust, -Released", 0).is_match(&item));
+    }
+
+    #[test]
+    fn test_post_inn_feeds() {
+        use crate::controller::{Inn, InnType};
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let inn = Inn {
+            iid: 1,
+            inn_name: "inn".into(),
+            about: String::new(),
+            description: String::new(),
+            topics: vec![],
+            inn_type: InnType::Public,
+            early_birds: 0,
+            created_at: 0,
+        };
+        let inn_encoded = bincode::encode_to_vec(&inn, standard()).unwrap();
+        db.open_tree("inns")
+            .unwrap()
+            .insert(u32_to_ivec(1), inn_encoded)
+            .unwrap();
+        // the bot is a Fellow of the inn
+        db.open_tree("inn_users")
+            .unwrap()
+            .insert([u32_to_ivec(1), u32_to_ivec(2)].concat(), &[5])
+            .unwrap();
+        let feed = Feed {
+            link: "https://example.com/feed".into(),
+            title: "blog".into(),
+        };
+        let feed_encoded = bincode::encode_to_vec(&feed, standard()).unwrap();
+        db.open_tree("feeds")
+            .unwrap()
+            .insert(u32_to_ivec(1), feed_encoded)
+            .unwrap();
+
+        let titles = ["rust 1", "rust jobs", "go news", "rust 2", "rust 3"];
+        for (idx, title) in titles.into_iter().enumerate() {
+            let item_id = idx as u32 + 1;
+            let item_encoded = bincode::encode_to_vec(new_item(title, ""), standard()).unwrap();
+            db.open_tree("items")
+                .unwrap()
+                .insert(u32_to_ivec(item_id), item_encoded)
+                .unwrap();
+            db.open_tree("feed_items")
+                .unwrap()
+                .insert(
+                    [u32_to_ivec(1), u32_to_ivec(item_id)].concat(),
+                    i64_to_ivec(idx as i64),
+                )
+                .unwrap();
+        }
+
+        let k = [u32_to_ivec(1), u32_to_ivec(1)].concat();
+        let inn_feeds_tree = db.open_tree("inn_feeds").unwrap();
+        let inn_feed_encoded =
+            bincode::encode_to_vec(new_inn_feed("rust, -job", 2), standard()).unwrap();
+        inn_feeds_tree.insert(&k, inn_feed_encoded).unwrap();
+
+        // item_id => pid, 0 means skipped
+        let posted = || -> Vec<(u32, u32)> {
+            db.open_tree("inn_feed_items")
+                .unwrap()
+                .scan_prefix(u32_to_ivec(1))
+                .map(|i| {
+                    let (k, v) = i.unwrap();
+                    (u8_slice_to_u32(&k[4..8]), ivec_to_u32(&v))
+                })
+                .collect()
+        };
+
+        // the oldest items first, at most 2 posts a day
+        post_inn_feeds(&db).unwrap();
+        assert_eq!(posted(), [(1, 1), (2, 0), (3, 0), (4, 2)]);
+        post_inn_feeds(&db).unwrap();
+        assert_eq!(posted().len(), 4);
+
+        // the rest waits for the next day
+        let v = inn_feeds_tree.get(&k).unwrap().unwrap();
+        let (mut inn_feed, _): (InnFeed, usize) =
+            bincode::decode_from_slice(&v, standard()).unwrap();
+        assert_eq!(inn_feed.posted_today, 2);
+        inn_feed.posted_date -= 1;
+        let inn_feed_encoded = bincode::encode_to_vec(&inn_feed, standard()).unwrap();
+        inn_feeds_tree.insert(&k, inn_feed_encoded).unwrap();
+        post_inn_feeds(&db).unwrap();
+        assert_eq!(posted(), [(1, 1), (2, 0), (3, 0), (4, 2), (5, 3)]);
+
+        let post: crate::controller::Post = get_one(&db, "posts", 3).unwrap();
+        assert_eq!(post.title, "rust 3");
+        assert_eq!(post.tags, ["blog"]);
+    }
+}
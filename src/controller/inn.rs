@@ -10,29 +10,59 @@
 //! | Fellow  | 5    | ✅      | ✅   | ✅              |           |           |           |                  |
 //! | Mod     | 8    | ✅      | ✅   | ✅              | ✅        | ✅        |           |                  |
 //! | Super   | 10   | ✅      | ✅   | ✅              | ✅        | ✅        | ✅        |                  |
+//!
+//! Interns can be promoted to Fellows automatically by the [PromotionRule] of the inn.
+//!
+//! ### Inn types
+//! | type         | join          | read    | post                 | comment |
+//! |--------------|---------------|---------|----------------------|---------|
+//! | Public       | directly      | anyone  | Intern               | Limited |
+//! | Apply        | mods approve  | anyone  | Intern               | Limited |
+//! | Private      | mods approve  | members | Intern               | Limited |
+//! | Announcement | directly      | anyone  | Mod                  | Limited |
+//! | Restricted   | as Limited    | anyone  | Intern, set by mods  | Limited |
+//! | Archive      | closed        | anyone  |                      |         |
+//!
+//! The min roles to post and comment can be raised by the [InnConfig] of the inn.
 
 use super::{
-    extract_element, get_batch, get_count_by_prefix, get_ids_by_prefix, get_ids_by_tag,
-    get_inn_role, get_one, get_range, get_site_config, get_uid_by_name, has_unread, incr_id,
-    into_response, is_mod, ivec_to_u32, mark_read, timestamp_to_date, u32_to_ivec, u8_slice_to_u32,
-    user_stats, utils::md2html, Claim, Comment, FormPost, Inn, PageData, ParamsPage, Post, User,
-    ValidatedForm,
+    canonical_url, date_to_u32, decode_cursor, encode_cursor,
+    event::{get_event, get_event_time, OutEvent},
+    extract_element,
+    feed::item_excerpt,
+    get_batch, get_count_by_prefix, get_ids_by_prefix, get_ids_by_tag, get_inn_role, get_one,
+    get_page, get_range, get_referer, get_site_config, get_uid_by_name, has_unread, incr_id,
+    inn_contribution, inn_event, into_response, is_mod, iter_by_cursor, iter_ids_after,
+    ivec_to_u32, mark_read, member_stats, mod_log,
+    pending::OutPendingComment,
+    poll::{get_poll_options, OutPoll},
+    solo::can_visit_solo,
+    timestamp_to_date, u32_to_ivec, u8_slice_to_u32, user_stats,
+    utils::md2html,
+    Claim, Comment, Draft, Event, FormPost, Inn, InnConfig, InnType, Item, ModLog, PageData,
+    ParamsPage, PendingComment, Poll, Post, PromotionRule, SiteConfig, Solo, User, ValidatedForm,
 };
 use crate::{
-    controller::{get_count, IterType},
+    controller::{
+        get_count,
+        user::{check_promotion, clear_role_change, expire_seconds, role_error},
+    },
     error::AppError,
 };
 use askama::Template;
+use atom_syndication::{Content, FixedDateTime, Person, Text};
 use axum::{
     extract::{Path, Query, State, TypedHeader},
-    headers::Cookie,
-    response::{IntoResponse, Redirect},
+    headers::{Cookie, Referer, UserAgent},
+    http::header::{CONTENT_TYPE, SET_COOKIE},
+    response::{IntoResponse, Redirect, Response},
 };
 use bincode::config::standard;
-use chrono::{DateTime, NaiveDateTime, Utc};
+use chrono::{DateTime, Days, NaiveDateTime, Utc};
+use nanoid::nanoid;
 use serde::Deserialize;
 use sled::{Batch, Db};
-use std::{collections::BTreeSet, path::PathBuf};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use validator::Validate;
 
 /// Page data: `inn_create.html`
@@ -48,6 +78,7 @@ struct PageInnCreate<'a> {
 struct PageInnEdit<'a> {
     page_data: PageData<'a>,
     inn: Inn,
+    inn_config: InnConfig,
 }
 
 /// `GET /mod/:iid` inn create/edit page
@@ -85,7 +116,12 @@ pub(crate) async fn mod_inn(
 
         let page_data = PageData::new("edit inn", &site_config, Some(claim), false);
         let inn: Inn = get_one(&db, "inns", iid)?;
-        let page_inn_edit = PageInnEdit { page_data, inn };
+        let inn_config = get_inn_config(&db, iid)?;
+        let page_inn_edit = PageInnEdit {
+            page_data,
+            inn,
+            inn_config,
+        };
         Ok(into_response(&page_inn_edit, "html"))
     }
 }
@@ -101,8 +137,32 @@ pub(crate) struct FormInn {
     description: String,
     #[validate(length(min = 1, max = 128))]
     topics: String,
-    inn_type: String,
+    inn_type: InnType,
     early_birds: u32,
+    is_log_public: Option<bool>,
+    premod_role: Option<u8>,
+    premod_comments: Option<bool>,
+    promote_posts: Option<u32>,
+    promote_comments: Option<u32>,
+    promote_days: Option<u32>,
+    promote_upvotes: Option<u32>,
+    #[validate(range(max = 3600))]
+    post_interval: Option<i64>,
+    #[validate(range(max = 3600))]
+    comment_interval: Option<i64>,
+    #[validate(range(max = 256))]
+    title_max_length: Option<usize>,
+    #[validate(range(max = 65535))]
+    article_max_length: Option<usize>,
+    #[validate(range(max = 100))]
+    per_page: Option<usize>,
+    post_role: Option<u8>,
+    comment_role: Option<u8>,
+    #[validate(range(max = 86400))]
+    slow_mode: Option<i64>,
+    sort_by_new: Option<bool>,
+    wiki_role: Option<u8>,
+    qa_mode: Option<bool>,
 }
 
 /// `POST /mod/:iid` inn create/edit page
@@ -136,7 +196,7 @@ pub(crate) async fn mod_inn_post(
         .filter(|s| !s.is_empty())
         .collect();
 
-    if input.inn_type.as_str() == "Private" {
+    if input.inn_type == InnType::Private {
         topics.insert("private".into());
     } else {
         topics.retain(|t| t != "private")
@@ -145,6 +205,11 @@ pub(crate) async fn mod_inn_post(
     let mut topics: Vec<_> = topics.into_iter().collect();
     topics.truncate(5);
 
+    let post_role = role_setting(input.post_role, 4)?;
+    let comment_role = role_setting(input.comment_role, 3)?;
+    let wiki_role = role_setting(input.wiki_role, 3)?;
+    let premod_role = role_setting(input.premod_role, 5)?;
+
     let inn_names_tree = db.open_tree("inn_names")?;
 
     let mut batch_topics = Batch::default();
@@ -169,10 +234,10 @@ pub(crate) async fn mod_inn_post(
         }
 
         let inn: Inn = get_one(&db, "inns", iid)?;
-        if inn.inn_type.as_str() == "Private" && input.inn_type != "Private" {
+        if inn.inn_type == InnType::Private && input.inn_type != InnType::Private {
             return Err(AppError::Unauthorized);
         }
-        if inn.inn_type.as_str() != "Private" && input.inn_type == "Private" {
+        if inn.inn_type != InnType::Private && input.inn_type == InnType::Private {
             return Err(AppError::Unauthorized);
         }
 
@@ -217,10 +282,41 @@ pub(crate) async fn mod_inn_post(
         created_at: Utc::now().timestamp(),
     };
 
-    if inn.inn_type.as_str() == "Private" {
+    if inn.inn_type == InnType::Private {
         db.open_tree("inns_private")?.insert(&iid_ivec, &[])?;
     }
 
+    let promotion_rule = PromotionRule {
+        posts: input.promote_posts.unwrap_or_default(),
+        comments: input.promote_comments.unwrap_or_default(),
+        days: input.promote_days.unwrap_or_default(),
+        upvotes: input.promote_upvotes.unwrap_or_default(),
+    };
+    let inn_config = InnConfig {
+        post_interval: input.post_interval.unwrap_or_default(),
+        comment_interval: input.comment_interval.unwrap_or_default(),
+        title_max_length: input.title_max_length.unwrap_or_default(),
+        article_max_length: input.article_max_length.unwrap_or_default(),
+        per_page: input.per_page.unwrap_or_default(),
+        post_role,
+        comment_role,
+        slow_mode: input.slow_mode.unwrap_or_default(),
+        sort_by_new: input.sort_by_new.unwrap_or_default(),
+        wiki_role,
+        qa_mode: input.qa_mode.unwrap_or_default(),
+        log_public: input.is_log_public.unwrap_or_default(),
+        premod_role,
+        premod_comments: premod_role > 0 && input.premod_comments.unwrap_or_default(),
+        promotion_rule,
+    };
+    if inn_config == InnConfig::default() {
+        db.open_tree("inn_configs")?.remove(&iid_ivec)?;
+    } else {
+        let config_encoded = bincode::encode_to_vec(&inn_config, standard())?;
+        db.open_tree("inn_configs")?
+            .insert(&iid_ivec, config_encoded)?;
+    }
+
     let inn_encoded = bincode::encode_to_vec(&inn, standard())?;
 
     db.open_tree("inns")?.insert(&iid_ivec, inn_encoded)?;
@@ -230,6 +326,393 @@ pub(crate) async fn mod_inn_post(
     Ok(Redirect::to(&target))
 }
 
+/// check a role setting of [InnConfig], 0 means the default
+fn role_setting(role: Option<u8>, min: u8) -> Result<u8, AppError> {
+    match role.unwrap_or_default() {
+        0 => Ok(0),
+        role if (min..=10).contains(&role) => Ok(role),
+        _ => Err(AppError::InvalidRole),
+    }
+}
+
+/// Vec data: mod log
+pub(super) struct OutModLog {
+    uid: u32,
+    username: String,
+    iid: u32,
+    action: String,
+    target: String,
+    reason: String,
+    created_at: String,
+}
+
+impl OutModLog {
+    pub(super) fn new(db: &Db, log: ModLog) -> Result<Self, AppError> {
+        // uid 0 is for the actions done by cron jobs
+        let username = if log.uid == 0 {
+            "system".to_owned()
+        } else {
+            get_one::<User>(db, "users", log.uid)?.username
+        };
+        Ok(OutModLog {
+            uid: log.uid,
+            username,
+            iid: log.iid,
+            action: log.action,
+            target: log.target,
+            reason: log.reason,
+            created_at: timestamp_to_date(log.created_at),
+        })
+    }
+}
+
+/// Page data: `mod_log.html`
+#[derive(Template)]
+#[template(path = "mod_log.html")]
+pub(super) struct PageModLog<'a> {
+    pub(super) page_data: PageData<'a>,
+    pub(super) logs: Vec<OutModLog>,
+    pub(super) iid: u32,
+    pub(super) inn_name: String,
+    pub(super) anchor: usize,
+    pub(super) n: usize,
+    pub(super) is_desc: bool,
+}
+
+/// url params: `mod_log.html`
+#[derive(Deserialize)]
+pub(crate) struct ParamsModLog {
+    pub(super) anchor: Option<usize>,
+    pub(super) is_desc: Option<bool>,
+}
+
+/// `GET /mod/:iid/log` inn mod log
+///
+/// Only mods can see it unless the inn makes its log public.
+pub(crate) async fn mod_log_view(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+    Query(params): Query<ParamsModLog>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if !get_inn_config(&db, iid)?.log_public {
+        match claim {
+            Some(ref claim) if is_mod(&db, claim.uid, iid)? => {}
+            _ => return Err(AppError::Unauthorized),
+        }
+    }
+
+    let n = site_config.per_page;
+    let anchor = params.anchor.unwrap_or(0);
+    let is_desc = params.is_desc.unwrap_or(true);
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor: None,
+    };
+
+    let mut logs = Vec::with_capacity(n);
+    for lid in get_ids_by_prefix(&db, "inn_mod_logs", u32_to_ivec(iid), Some(&page_params))? {
+        let log: ModLog = get_one(&db, "mod_logs", lid)?;
+        logs.push(OutModLog::new(&db, log)?);
+    }
+
+    let page_data = PageData::new("mod log", &site_config, claim, false);
+    let page_mod_log = PageModLog {
+        page_data,
+        logs,
+        iid,
+        inn_name: inn.inn_name,
+        anchor,
+        n,
+        is_desc,
+    };
+
+    Ok(into_response(&page_mod_log, "html"))
+}
+
+/// Page data: `mod_stats.html`
+#[derive(Template)]
+#[template(path = "mod_stats.html")]
+struct PageModStats<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    days: i64,
+    members: usize,
+    active_members: usize,
+    /// (date, posts, comments, joins, leaves)
+    daily: Vec<(String, u32, u32, u32, u32)>,
+    /// (uid, username, posts + comments)
+    top_contributors: Vec<(u32, String, u32)>,
+    post_votes: (u32, u32),
+    comment_votes: (u32, u32),
+}
+
+/// url params: `mod_stats.html`
+#[derive(Deserialize)]
+pub(crate) struct ParamsModStats {
+    days: Option<i64>,
+}
+
+/// `GET /mod/:iid/stats` inn stats of the last `days` (default 30, at most 365)
+///
+/// Everything is summed from the daily rollups of `inn_events` and `inn_contributors`, which are
+/// recorded when posts, comments, votes, joins and leaves happen.
+pub(crate) async fn mod_stats(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+    Query(params): Query<ParamsModStats>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+    let inn: Inn = get_one(&db, "inns", iid)?;
+
+    let days = params.days.unwrap_or(30).clamp(1, 365);
+    let today = Utc::now().date_naive();
+    let first_day = today.checked_sub_days(Days::new(days as u64 - 1)).unwrap();
+
+    // date => (posts, comments, joins, leaves)
+    let mut daily_map: BTreeMap<u32, (u32, u32, u32, u32)> = first_day
+        .iter_days()
+        .take(days as usize)
+        .map(|day| (date_to_u32(day), (0, 0, 0, 0)))
+        .collect();
+    let mut post_votes = (0, 0);
+    let mut comment_votes = (0, 0);
+
+    let iid_ivec = u32_to_ivec(iid);
+    let window_start = [&iid_ivec, &u32_to_ivec(date_to_u32(first_day))].concat();
+    // kv_pair: iid#yyyymmdd#event = N
+    for i in db.open_tree("inn_events")?.range(&window_start[..]..) {
+        let (k, v) = i?;
+        if !k.starts_with(&iid_ivec) {
+            break;
+        }
+        let count = ivec_to_u32(&v);
+        let Some(day) = daily_map.get_mut(&u8_slice_to_u32(&k[4..8])) else {
+            continue;
+        };
+        match &k[8..] {
+            b"post" => day.0 += count,
+            b"comment" => day.1 += count,
+            b"join" => day.2 += count,
+            b"leave" => day.3 += count,
+            b"post_upvote" => post_votes.0 += count,
+            b"post_downvote" => post_votes.1 += count,
+            b"comment_upvote" => comment_votes.0 += count,
+            b"comment_downvote" => comment_votes.1 += count,
+            _ => {}
+        }
+    }
+
+    let mut contributors: HashMap<u32, u32> = HashMap::new();
+    // kv_pair: iid#yyyymmdd#uid = N
+    for i in db.open_tree("inn_contributors")?.range(&window_start[..]..) {
+        let (k, v) = i?;
+        if !k.starts_with(&iid_ivec) {
+            break;
+        }
+        *contributors.entry(u8_slice_to_u32(&k[8..12])).or_default() += ivec_to_u32(&v);
+    }
+
+    let daily = daily_map
+        .into_iter()
+        .rev()
+        .map(|(date, (posts, comments, joins, leaves))| {
+            let date = format!("{}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100);
+            (date, posts, comments, joins, leaves)
+        })
+        .collect();
+
+    let active_members = contributors.len();
+    let mut contributors: Vec<(u32, u32)> = contributors.into_iter().collect();
+    contributors.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut top_contributors = Vec::with_capacity(10);
+    for (uid, count) in contributors.into_iter().take(10) {
+        let user: User = get_one(&db, "users", uid)?;
+        top_contributors.push((uid, user.username, count));
+    }
+
+    let mut members = 0;
+    for i in db.open_tree("inn_users")?.scan_prefix(&iid_ivec) {
+        let (_, v) = i?;
+        if v[0] >= 3 {
+            members += 1;
+        }
+    }
+
+    let page_data = PageData::new("inn stats", &site_config, Some(claim), false);
+    let page_mod_stats = PageModStats {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        days,
+        members,
+        active_members,
+        daily,
+        top_contributors,
+        post_votes,
+        comment_votes,
+    };
+
+    Ok(into_response(&page_mod_stats, "html"))
+}
+
+impl InnConfig {
+    /// override the limits of [SiteConfig] with the non-zero settings of the inn
+    fn apply(&self, site_config: &mut SiteConfig) {
+        if self.post_interval > 0 {
+            site_config.post_interval = self.post_interval;
+        }
+        if self.comment_interval > 0 {
+            site_config.comment_interval = self.comment_interval;
+        }
+        if self.title_max_length > 0 {
+            site_config.title_max_length = self.title_max_length;
+        }
+        if self.article_max_length > 0 {
+            site_config.article_max_length = self.article_max_length;
+        }
+        if self.per_page > 0 {
+            site_config.per_page = self.per_page;
+        }
+    }
+
+    fn post_role(&self) -> u8 {
+        self.post_role.max(4)
+    }
+
+    fn comment_role(&self) -> u8 {
+        self.comment_role.max(3)
+    }
+
+    pub(super) fn wiki_role(&self) -> u8 {
+        if self.wiki_role == 0 {
+            5
+        } else {
+            self.wiki_role.max(3)
+        }
+    }
+
+    /// check the slow mode of the inn and record the write time
+    fn check_slow_mode(&self, db: &Db, iid: u32, uid: u32, inn_role: u8) -> Result<(), AppError> {
+        if self.slow_mode == 0 || inn_role >= 8 {
+            return Ok(());
+        }
+        let now = Utc::now().timestamp();
+        let k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+        let tree = db.open_tree("inn_slow_mode")?;
+        if let Some(v) = tree.get(&k)? {
+            if now - i64::from(ivec_to_u32(&v)) < self.slow_mode {
+                return Err(AppError::WriteInterval);
+            }
+        }
+        tree.insert(k, u32_to_ivec(now as u32))?;
+        Ok(())
+    }
+}
+
+pub(super) fn get_inn_config(db: &Db, iid: u32) -> Result<InnConfig, AppError> {
+    Ok(match db.open_tree("inn_configs")?.get(u32_to_ivec(iid))? {
+        Some(v) => bincode::decode_from_slice(&v, standard())?.0,
+        None => InnConfig::default(),
+    })
+}
+
+/// give the comment its cid and save it, returns the cid
+pub(super) fn insert_comment(db: &Db, iid: u32, mut comment: Comment) -> Result<u32, AppError> {
+    let pid_ivec = u32_to_ivec(comment.pid);
+    let cid = incr_id(&db.open_tree("post_comments_count")?, &pid_ivec)?;
+    comment.cid = cid;
+
+    let k = [&u32_to_ivec(comment.uid), &pid_ivec, &u32_to_ivec(cid)].concat();
+    db.open_tree("user_comments")?.insert(k, &[])?;
+
+    let comment_encoded = bincode::encode_to_vec(&comment, standard())?;
+    let k = [&pid_ivec, &u32_to_ivec(cid)].concat();
+    db.open_tree("post_comments")?.insert(k, comment_encoded)?;
+    member_stats(db, iid, comment.uid, "comment", true)?;
+    inn_contribution(db, iid, comment.uid, "comment")?;
+    Ok(cid)
+}
+
+/// index an approved post by inn, user, tags and timeline, and its extras by [index_extras].
+pub(super) fn publish_post(db: &Db, post: &Post, item_id: Option<u32>) -> Result<(), AppError> {
+    let inn: Inn = get_one(db, "inns", post.iid)?;
+    let visibility = if inn.inn_type == InnType::Private {
+        10
+    } else {
+        0
+    };
+    let iid_ivec = u32_to_ivec(post.iid);
+    let pid_ivec = u32_to_ivec(post.pid);
+    let visibility_ivec = u32_to_ivec(visibility);
+
+    let k = [&iid_ivec, &pid_ivec].concat();
+    db.open_tree("inn_posts")?.insert(k, &[])?;
+
+    let k = [&u32_to_ivec(post.uid), &pid_ivec].concat();
+    let v = [&iid_ivec, &visibility_ivec].concat();
+    db.open_tree("user_posts")?.insert(k, v)?;
+    member_stats(db, post.iid, post.uid, "post", true)?;
+    inn_contribution(db, post.iid, post.uid, "post")?;
+
+    let mut batch = Batch::default();
+    for tag in &post.tags {
+        let k = [tag.as_bytes(), &pid_ivec].concat();
+        batch.insert(k, &[]);
+    }
+    db.open_tree("tags")?.apply_batch(batch)?;
+
+    // pending posts go to the timeline at the time they are approved
+    let timestamp_ivec = u32_to_ivec(Utc::now().timestamp() as u32);
+    let k = [&iid_ivec, &pid_ivec].concat();
+    db.open_tree("post_timeline_idx")?
+        .insert(k, &timestamp_ivec)?;
+    let k = [&timestamp_ivec, &iid_ivec, &pid_ivec].concat();
+    db.open_tree("post_timeline")?.insert(k, visibility_ivec)?;
+
+    index_extras(db, post, item_id)
+}
+
+/// index the link, the event and the shared feed item of a new post. Pending posts are indexed
+/// when approved.
+fn index_extras(db: &Db, post: &Post, item_id: Option<u32>) -> Result<(), AppError> {
+    let pid_ivec = u32_to_ivec(post.pid);
+    if let Some(link) = get_link(db, post.pid)? {
+        // kv_pair: link = pid#pid#..
+        db.open_tree("post_links")?.update_and_fetch(link, |old| {
+            Some([old.unwrap_or_default(), &pid_ivec].concat())
+        })?;
+    }
+    if let Some(v) = db.open_tree("events")?.get(&pid_ivec)? {
+        let (event, _): (Event, usize) = bincode::decode_from_slice(&v, standard())?;
+        let k = [
+            &u32_to_ivec(post.iid),
+            &u32_to_ivec(event.start as u32),
+            &pid_ivec,
+        ]
+        .concat();
+        db.open_tree("inn_calendar")?.insert(k, &[])?;
+    }
+    if let Some(item_id) = item_id {
+        let k = [&u32_to_ivec(item_id), &pid_ivec].concat();
+        db.open_tree("item_posts")?.insert(k, &[])?;
+    }
+    Ok(())
+}
+
 /// url params: `inn_list.html`
 #[derive(Deserialize)]
 pub(crate) struct ParamsInnList {
@@ -258,6 +741,7 @@ struct PageInnList<'a> {
     is_desc: bool,
     filter: Option<String>,
     topic: Option<String>,
+    is_following_topic: bool,
 }
 
 /// `GET /inn/list` inns list page
@@ -271,7 +755,12 @@ pub(crate) async fn inn_list(
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
-    let page_params = ParamsPage { anchor, n, is_desc };
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor: None,
+    };
 
     let mut inns: Vec<Inn> = Vec::with_capacity(n);
 
@@ -313,6 +802,12 @@ pub(crate) async fn inn_list(
         out_inns.push(out_inn);
     }
 
+    let mut is_following_topic = false;
+    if let (Some(claim), Some(topic)) = (&claim, &params.topic) {
+        let k = [&u32_to_ivec(claim.uid), topic.as_bytes()].concat();
+        is_following_topic = db.open_tree("user_topics")?.contains_key(k)?;
+    }
+
     let filter = if claim.is_none() { None } else { params.filter };
     let has_unread = if let Some(ref claim) = claim {
         has_unread(&db, claim.uid)?
@@ -328,6 +823,7 @@ pub(crate) async fn inn_list(
         is_desc,
         topic: params.topic,
         filter,
+        is_following_topic,
     };
 
     Ok(into_response(&page_inn_list, "html"))
@@ -341,7 +837,10 @@ struct PagePostCreate<'a> {
     joined: Vec<(String, u32)>,
     selected_iid: u32,
     draft: FormPost,
-    draft_titles: Vec<String>,
+    drafts: Vec<(u32, String)>,
+    history: Vec<(u32, String)>,
+    /// the post with the same link in the inn
+    duplicate: Option<OutPostList>,
 }
 
 /// Page data: `post_edit.html`
@@ -356,7 +855,11 @@ struct PagePostEdit<'a> {
 #[derive(Deserialize)]
 pub(crate) struct ParamsPostCreate {
     iid: Option<u32>,
-    from_draft: Option<String>,
+    draft_id: Option<u32>,
+    version: Option<u32>,
+    dup: Option<u32>,
+    /// share the feed item
+    item_id: Option<u32>,
 }
 
 /// `GET /post/edit/:pid` post create/edit page
@@ -392,19 +895,50 @@ pub(crate) async fn edit_post(
 
     if pid == 0 {
         let mut draft = FormPost::default();
-        let mut draft_titles = vec![];
+        let mut drafts = vec![];
         for i in db.open_tree("drafts")?.scan_prefix(u32_to_ivec(claim.uid)) {
-            let (k, _) = i?;
-            let draft_title = String::from_utf8_lossy(&k[4..]).to_string();
-            draft_titles.push(draft_title);
+            let (_, v) = i?;
+            let (one, _): (Draft, usize) = bincode::decode_from_slice(&v, standard())?;
+            drafts.push((one.did, one.post.title));
         }
 
-        if let Some(from_draft) = params.from_draft {
-            let k: Vec<u8> = [&u32_to_ivec(claim.uid), from_draft.as_bytes()].concat();
-            if let Some(v) = db.open_tree("drafts")?.get(k)? {
-                (draft, _) = bincode::decode_from_slice(&v, standard())?;
-            };
+        let mut history = vec![];
+        if let Some(did) = params.draft_id {
+            let one = get_draft(&db, claim.uid, did)?;
+            draft = one.post.into();
+
+            // kv_pair: did#timestamp = Draft
+            for i in db.open_tree("draft_history")?.scan_prefix(u32_to_ivec(did)) {
+                let (k, v) = i?;
+                let timestamp = u8_slice_to_u32(&k[4..8]);
+                if params.version == Some(timestamp) {
+                    let (old, _): (Draft, usize) = bincode::decode_from_slice(&v, standard())?;
+                    draft = old.post.into();
+                }
+                let date = NaiveDateTime::from_timestamp_opt(i64::from(timestamp), 0)
+                    .unwrap()
+                    .format("%Y-%m-%d %H:%M:%S")
+                    .to_string();
+                history.push((timestamp, date));
+            }
+            history.reverse();
+            draft.draft_id = Some(did);
+            // the drafts kept for a duplicate link are meant to be posted
+            draft.is_draft = Some(params.dup.is_none());
             selected_iid = draft.iid;
+        } else if let Some(item_id) = params.item_id {
+            let item: Item = get_one(&db, "items", item_id)?;
+            draft.content = format!("> {}\n\n", item_excerpt(&item, 500));
+            draft.title = item.title;
+            draft.link = Some(item.link);
+            draft.item_id = Some(item_id);
+        };
+
+        let duplicate = match params.dup {
+            Some(dup) => get_out_post_list(&db, &[dup])?
+                .pop()
+                .filter(|post| joined.iter().any(|(_, iid)| *iid == post.iid)),
+            None => None,
         };
 
         let page_data = PageData::new("new post", &site_config, Some(claim), false);
@@ -413,7 +947,9 @@ pub(crate) async fn edit_post(
             joined,
             draft,
             selected_iid,
-            draft_titles,
+            drafts,
+            history,
+            duplicate,
         };
 
         Ok(into_response(&page_post_create, "html"))
@@ -453,36 +989,94 @@ pub(crate) async fn edit_post_post(
     ValidatedForm(input): ValidatedForm<FormPost>,
 ) -> Result<impl IntoResponse, AppError> {
     let cookie = cookie.ok_or(AppError::NonLogin)?;
-    let site_config = get_site_config(&db)?;
+    let mut site_config = get_site_config(&db)?;
     let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
 
     let is_draft = input.is_draft.unwrap_or_default();
     let delete_draft = input.delete_draft.unwrap_or_default();
 
-    let k: Vec<u8> = [&u32_to_ivec(claim.uid), input.title.as_bytes()].concat();
-
     if delete_draft {
-        db.open_tree("drafts")?.remove(&k)?;
-        return Ok(Redirect::to("/post/edit/0"));
+        if let Some(did) = input.draft_id {
+            delete_draft_by_id(&db, claim.uid, did)?;
+        }
+        return Ok(Redirect::to("/user/drafts"));
     }
     if is_draft {
-        let post_encoded = bincode::encode_to_vec(&input, standard())?;
-        db.open_tree("drafts")?.insert(k, post_encoded)?;
-        return Ok(Redirect::to("/post/edit/0"));
+        let did = save_draft(&db, claim.uid, input)?;
+        let target = format!("/post/edit/0?draft_id={did}");
+        return Ok(Redirect::to(&target));
     }
 
     let iid = input.iid;
     let inn_role = get_inn_role(&db, iid, claim.uid)?.ok_or(AppError::Unauthorized)?;
     if inn_role <= 3 {
+        return Err(role_error(&db, iid, claim.uid));
+    }
+    let inn_config = get_inn_config(&db, iid)?;
+    if inn_role < inn_config.post_role() {
         return Err(AppError::Unauthorized);
     }
+    inn_config.apply(&mut site_config);
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    match inn.inn_type {
+        InnType::Archive => return Err(AppError::InnArchived),
+        InnType::Announcement if inn_role < 8 => return Err(AppError::Unauthorized),
+        _ => {}
+    }
+
+    // 0 means no limit
+    let is_too_long = |text: &str, max: usize| max > 0 && text.chars().count() > max;
+    if is_too_long(&input.title, site_config.title_max_length)
+        || is_too_long(&input.content, site_config.article_max_length)
+    {
+        return Err(AppError::ContentTooLong);
+    }
+    let poll_options = if old_pid == 0 {
+        get_poll_options(input.poll_options.as_deref())?
+    } else {
+        vec![]
+    };
+    let poll_close = match input
+        .poll_close
+        .as_deref()
+        .filter(|close| !close.is_empty())
+    {
+        Some(close) if !poll_options.is_empty() => {
+            Some(expire_seconds(close).ok_or(AppError::InvalidExpiry)?)
+        }
+        _ => None,
+    };
+    let event_time = if old_pid == 0 {
+        get_event_time(input.event_start.as_deref(), input.event_end.as_deref())?
+    } else {
+        None
+    };
+    let link = match input.link.as_deref().map(str::trim) {
+        Some(link) if old_pid == 0 && !link.is_empty() => {
+            Some(canonical_url(link).ok_or(AppError::InvalidLink)?)
+        }
+        _ => None,
+    };
+    if let Some(ref link) = link {
+        let dup = get_link_pids(&db, link, Some(iid))?.last().copied();
+        if let Some(dup) = dup {
+            if !input.link_anyway.unwrap_or_default() {
+                // keep it as a draft, and point to the existing discussion
+                let did = save_draft(&db, claim.uid, input)?;
+                let target = format!("/post/edit/0?draft_id={did}&dup={dup}");
+                return Ok(Redirect::to(&target));
+            }
+        }
+    }
 
     let mut created_at = Utc::now().timestamp();
     if created_at - claim.last_write < site_config.post_interval {
         return Err(AppError::WriteInterval);
     }
-
-    let inn: Inn = get_one(&db, "inns", iid)?;
+    if old_pid == 0 {
+        inn_config.check_slow_mode(&db, iid, claim.uid, inn_role)?;
+    }
 
     let pid = if old_pid == 0 {
         incr_id(&db, "posts_count")?
@@ -491,9 +1085,23 @@ pub(crate) async fn edit_post_post(
     };
     let pid_ivec = u32_to_ivec(pid);
 
+    // the draft has been published
+    if let Some(did) = input.draft_id {
+        delete_draft_by_id(&db, claim.uid, did)?;
+    }
+
+    // new posts from the roles below the threshold are held for review
+    let post_pending_k = [&u32_to_ivec(iid), &pid_ivec].concat();
+    let is_pending = if old_pid == 0 {
+        inn_role < inn_config.premod_role
+    } else {
+        db.open_tree("post_pending")?
+            .contains_key(&post_pending_k)?
+    };
+
     let mut tags = vec![];
     let mut visibility = 0;
-    if inn.inn_type.as_str() == "Private" {
+    if inn.inn_type == InnType::Private {
         visibility = 10;
     } else {
         let tags_set: BTreeSet<String> = input
@@ -536,7 +1144,9 @@ pub(crate) async fn edit_post_post(
             let k = [tag.as_bytes(), &pid_ivec].concat();
             batch.insert(k, &[]);
         }
-        db.open_tree("tags")?.apply_batch(batch)?;
+        if !is_pending {
+            db.open_tree("tags")?.apply_batch(batch)?;
+        }
     }
 
     let post = Post {
@@ -553,6 +1163,57 @@ pub(crate) async fn edit_post_post(
 
     let post_encoded = bincode::encode_to_vec(&post, standard())?;
     db.open_tree("posts")?.insert(&pid_ivec, post_encoded)?;
+    if old_pid > 0 {
+        // the related posts are refreshed by [refresh_related]
+        db.open_tree("post_edited")?.insert(&pid_ivec, &[])?;
+    }
+
+    if let Some(link) = link {
+        db.open_tree("post_url")?.insert(&pid_ivec, link.as_str())?;
+    }
+    let item_id = match input.item_id {
+        Some(item_id)
+            if old_pid == 0 && db.open_tree("items")?.contains_key(u32_to_ivec(item_id))? =>
+        {
+            Some(item_id)
+        }
+        _ => None,
+    };
+    if !poll_options.is_empty() {
+        let poll = Poll {
+            pid,
+            options: poll_options,
+            is_multiple: input.poll_multiple.unwrap_or_default(),
+            is_anonymous: input.poll_anonymous.unwrap_or_default(),
+            close_at: poll_close.map(|seconds| created_at + seconds),
+        };
+        let poll_encoded = bincode::encode_to_vec(&poll, standard())?;
+        db.open_tree("polls")?.insert(&pid_ivec, poll_encoded)?;
+    }
+    if let Some((start, end)) = event_time {
+        let event = Event {
+            pid,
+            iid,
+            start,
+            end,
+            location: input.event_location.unwrap_or_default().trim().to_owned(),
+        };
+        let event_encoded = bincode::encode_to_vec(&event, standard())?;
+        db.open_tree("events")?.insert(&pid_ivec, event_encoded)?;
+    }
+
+    // pending posts are indexed when approved, see [pending_action]
+    if is_pending {
+        if old_pid == 0 {
+            // kv_pair: iid#pid = item_id, empty if not shared from a feed item
+            let v = item_id.map(u32_to_ivec).unwrap_or_default();
+            db.open_tree("post_pending")?.insert(post_pending_k, v)?;
+        }
+        user_stats(&db, claim.uid, "post")?;
+        claim.update_last_write(&db)?;
+        let target = format!("/post/{iid}/{pid}");
+        return Ok(Redirect::to(&target));
+    }
 
     let iid_ivec = u32_to_ivec(iid);
     let visibility_ivec = u32_to_ivec(visibility);
@@ -563,6 +1224,8 @@ pub(crate) async fn edit_post_post(
         let k = [&u32_to_ivec(claim.uid), &pid_ivec].concat();
         let v = [&iid_ivec, &visibility_ivec].concat();
         db.open_tree("user_posts")?.insert(k, v)?;
+        member_stats(&db, iid, claim.uid, "post", true)?;
+        inn_contribution(&db, iid, claim.uid, "post")?;
     }
 
     let created_at_ivec = u32_to_ivec(created_at as u32);
@@ -583,6 +1246,13 @@ pub(crate) async fn edit_post_post(
     // kv_pair: timestamp#iid#pid = visibility
     db.open_tree("post_timeline")?.insert(k, visibility_ivec)?;
 
+    if old_pid == 0 {
+        index_extras(&db, &post, item_id)?;
+        if inn_role == 4 {
+            check_promotion(&db, iid, claim.uid)?;
+        }
+    }
+
     user_stats(&db, claim.uid, "post")?;
     claim.update_last_write(&db)?;
 
@@ -590,36 +1260,208 @@ pub(crate) async fn edit_post_post(
     Ok(Redirect::to(&target))
 }
 
-/// Vec data: post list
-struct OutPostList {
-    pid: u32,
+/// the max number of the previous versions kept for a draft
+const DRAFT_HISTORY_MAX: usize = 5;
+
+/// get the draft of the user
+fn get_draft(db: &Db, uid: u32, did: u32) -> Result<Draft, AppError> {
+    let k = [&u32_to_ivec(uid), &u32_to_ivec(did)].concat();
+    let v = db.open_tree("drafts")?.get(k)?.ok_or(AppError::NotFound)?;
+    let (draft, _): (Draft, usize) = bincode::decode_from_slice(&v, standard())?;
+    Ok(draft)
+}
+
+/// save the draft and return the draft id. The previous version goes to the history,
+/// and only the latest [DRAFT_HISTORY_MAX] versions are kept.
+fn save_draft(db: &Db, uid: u32, post: FormPost) -> Result<u32, AppError> {
+    let drafts_tree = db.open_tree("drafts")?;
+    let draft_history_tree = db.open_tree("draft_history")?;
+
+    let old = match post.draft_id {
+        Some(did) => drafts_tree.get([&u32_to_ivec(uid), &u32_to_ivec(did)].concat())?,
+        None => None,
+    };
+    let did = if let Some(old) = old {
+        let (old, _): (Draft, usize) = bincode::decode_from_slice(&old, standard())?;
+        let k = [&u32_to_ivec(old.did), &u32_to_ivec(old.updated_at as u32)].concat();
+        let old_encoded = bincode::encode_to_vec(&old, standard())?;
+        draft_history_tree.insert(k, old_encoded)?;
+
+        let versions = draft_history_tree
+            .scan_prefix(u32_to_ivec(old.did))
+            .keys()
+            .collect::<Result<Vec<_>, _>>()?;
+        if versions.len() > DRAFT_HISTORY_MAX {
+            for k in &versions[..versions.len() - DRAFT_HISTORY_MAX] {
+                draft_history_tree.remove(k)?;
+            }
+        }
+        old.did
+    } else {
+        incr_id(db, "drafts_count")?
+    };
+
+    let draft = Draft {
+        did,
+        post: post.into(),
+        updated_at: Utc::now().timestamp(),
+    };
+    let draft_encoded = bincode::encode_to_vec(&draft, standard())?;
+    let k = [&u32_to_ivec(uid), &u32_to_ivec(did)].concat();
+    drafts_tree.insert(k, draft_encoded)?;
+    Ok(did)
+}
+
+/// delete the draft of the user with its history
+fn delete_draft_by_id(db: &Db, uid: u32, did: u32) -> Result<(), AppError> {
+    let k = [&u32_to_ivec(uid), &u32_to_ivec(did)].concat();
+    if db.open_tree("drafts")?.remove(k)?.is_some() {
+        let draft_history_tree = db.open_tree("draft_history")?;
+        for k in draft_history_tree.scan_prefix(u32_to_ivec(did)).keys() {
+            draft_history_tree.remove(k?)?;
+        }
+    }
+    Ok(())
+}
+
+/// Vec data: draft
+struct OutDraft {
+    did: u32,
     iid: u32,
     inn_name: String,
-    uid: u32,
-    username: String,
     title: String,
-    created_at: String,
-    comment_count: u32,
-    is_hidden: bool,
+    updated_at: String,
 }
 
-/// Page data: `tag.html`
+/// Page data: `drafts.html`
 #[derive(Template)]
-#[template(path = "tag.html")]
-struct PageTag<'a> {
+#[template(path = "drafts.html")]
+struct PageDrafts<'a> {
     page_data: PageData<'a>,
-    posts: Vec<OutPostList>,
-    anchor: usize,
-    n: usize,
-    is_desc: bool,
+    drafts: Vec<OutDraft>,
+    iid: u32,
+}
+
+/// url params: `drafts.html`
+#[derive(Deserialize)]
+pub(crate) struct ParamsDrafts {
+    iid: Option<u32>,
+}
+
+/// `GET /user/drafts` drafts of the user, could be filtered by inn
+pub(crate) async fn draft_list(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Query(params): Query<ParamsDrafts>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let iid = params.iid.unwrap_or_default();
+    let mut drafts = Vec::new();
+    for i in db
+        .open_tree("drafts")?
+        .scan_prefix(u32_to_ivec(claim.uid))
+        .rev()
+    {
+        let (_, v) = i?;
+        let (draft, _): (Draft, usize) = bincode::decode_from_slice(&v, standard())?;
+        if iid > 0 && draft.post.iid != iid {
+            continue;
+        }
+        let inn_name = match get_one::<Inn>(&db, "inns", draft.post.iid) {
+            Ok(inn) => inn.inn_name,
+            Err(_) => String::new(),
+        };
+        drafts.push(OutDraft {
+            did: draft.did,
+            iid: draft.post.iid,
+            inn_name,
+            title: draft.post.title,
+            updated_at: timestamp_to_date(draft.updated_at),
+        });
+    }
+
+    let has_unread = has_unread(&db, claim.uid)?;
+    let page_data = PageData::new("Drafts", &site_config, Some(claim), has_unread);
+    let page_drafts = PageDrafts {
+        page_data,
+        drafts,
+        iid,
+    };
+    Ok(into_response(&page_drafts, "html"))
+}
+
+/// `GET /user/drafts/:did/delete`
+pub(crate) async fn draft_delete(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(did): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    delete_draft_by_id(&db, claim.uid, did)?;
+    Ok(Redirect::to("/user/drafts"))
+}
+
+/// `GET /user/drafts/:did/preview` preview the draft
+pub(crate) async fn draft_preview(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(did): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let draft = get_draft(&db, claim.uid, did)?;
+    let page_data = PageData::new("preview", &site_config, Some(claim), false);
+    let page_preview = PagePreview {
+        page_data,
+        content: md2html(&draft.post.content),
+    };
+
+    Ok(into_response(&page_preview, "html"))
+}
+
+/// Vec data: post list
+pub(super) struct OutPostList {
+    pub(super) pid: u32,
+    pub(super) iid: u32,
+    pub(super) inn_name: String,
+    pub(super) uid: u32,
+    pub(super) username: String,
+    pub(super) title: String,
+    pub(super) created_at: String,
+    pub(super) comment_count: u32,
+    pub(super) is_hidden: bool,
+}
+
+/// Page data: `tag.html`
+#[derive(Template)]
+#[template(path = "tag.html")]
+struct PageTag<'a> {
+    page_data: PageData<'a>,
+    posts: Vec<OutPostList>,
+    anchor: usize,
+    cursor: String,
+    n: usize,
+    is_desc: bool,
     tag: String,
+    is_following: bool,
 }
 
 /// url params: `tag.html`
 #[derive(Deserialize)]
 pub(crate) struct ParamsTag {
-    anchor: Option<usize>,
-    is_desc: Option<bool>,
+    pub(super) anchor: Option<usize>,
+    pub(super) cursor: Option<String>,
+    pub(super) is_desc: Option<bool>,
+    /// `cursor` is the first item of the next page, see [PidsPage]
+    pub(super) prev: Option<bool>,
 }
 
 /// `GET /inn/tag/:tag` tag page
@@ -635,24 +1477,134 @@ pub(crate) async fn tag(
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
-    let page_params = ParamsPage { anchor, n, is_desc };
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor,
+    };
 
     let index = get_ids_by_tag(&db, "tags", &tag, Some(&page_params))?;
+    let cursor = index
+        .last()
+        .map(|pid| encode_cursor(&[tag.as_bytes(), &u32_to_ivec(*pid)].concat()))
+        .unwrap_or_default();
     let out_post_list = get_out_post_list(&db, &index)?;
 
+    let mut is_following = false;
+    if let Some(ref claim) = claim {
+        let k = [&u32_to_ivec(claim.uid), tag.as_bytes()].concat();
+        is_following = db.open_tree("user_tags")?.contains_key(k)?;
+    }
+
     let page_data = PageData::new("inn", &site_config, claim, false);
     let page_tag = PageTag {
         page_data,
         posts: out_post_list,
         anchor,
+        cursor,
         n,
         is_desc,
         tag,
+        is_following,
     };
 
     Ok(into_response(&page_tag, "html"))
 }
 
+/// `GET /inn/tag/:tag/follow` follow or unfollow a tag
+pub(crate) async fn tag_follow(
+    State(db): State<Db>,
+    referer: Option<TypedHeader<Referer>>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(tag): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie
+        .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
+        .ok_or(AppError::NonLogin)?;
+
+    let user_tags_tree = db.open_tree("user_tags")?;
+    let k = [&u32_to_ivec(claim.uid), tag.as_bytes()].concat();
+    if user_tags_tree.contains_key(&k)? {
+        user_tags_tree.remove(&k)?;
+    } else {
+        user_tags_tree.insert(&k, &[])?;
+    }
+
+    let target = get_referer(referer).unwrap_or_else(|| "/inn/0?filter=tags".to_owned());
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /inn/topic/:topic/follow` follow or unfollow an inn topic
+pub(crate) async fn topic_follow(
+    State(db): State<Db>,
+    referer: Option<TypedHeader<Referer>>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(topic): Path<String>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie
+        .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
+        .ok_or(AppError::NonLogin)?;
+
+    let user_topics_tree = db.open_tree("user_topics")?;
+    let k = [&u32_to_ivec(claim.uid), topic.as_bytes()].concat();
+    if user_topics_tree.contains_key(&k)? {
+        user_topics_tree.remove(&k)?;
+    } else {
+        user_topics_tree.insert(&k, &[])?;
+    }
+
+    let target = get_referer(referer).unwrap_or_else(|| "/inn/0?filter=tags".to_owned());
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /inn/tag/:tag/feed` tag feed, private inns are skipped
+pub(crate) async fn tag_feed(
+    State(db): State<Db>,
+    Path(tag): Path<String>,
+    Query(params): Query<ParamsFeed>,
+) -> Result<impl IntoResponse, AppError> {
+    let page_params = ParamsPage {
+        anchor: 0,
+        n: FEED_SIZE,
+        is_desc: true,
+        cursor: decode_cursor(params.cursor.as_deref()),
+    };
+
+    let site_config = get_site_config(&db)?;
+    let domain = site_config.domain.trim_end_matches('/');
+    let link = format!("{domain}/inn/tag/{tag}");
+
+    let index = get_ids_by_tag(&db, "tags", &tag, Some(&page_params))?;
+    let cursor = index
+        .last()
+        .map(|pid| encode_cursor(&[tag.as_bytes(), &u32_to_ivec(*pid)].concat()))
+        .unwrap_or_default();
+
+    let inns_private_tree = db.open_tree("inns_private")?;
+    let mut entries = Vec::with_capacity(index.len());
+    for pid in &index {
+        let post: Post = get_one(&db, "posts", *pid)?;
+        if post.is_hidden || inns_private_tree.contains_key(u32_to_ivec(post.iid))? {
+            continue;
+        }
+        entries.push(FeedEntry::from_post(&db, domain, post)?);
+    }
+
+    let out_feed = OutFeed {
+        title: format!("{} - {tag}", site_config.site_name),
+        subtitle: site_config.description,
+        self_link: format!("{link}/feed"),
+        next_cursor: (index.len() == FEED_SIZE).then_some(cursor),
+        link,
+        entries,
+    };
+    Ok(out_feed.render(params.format.as_deref()))
+}
+
 /// Page data: `inn.html`
 #[derive(Template)]
 #[template(path = "inn.html", escape = "none")]
@@ -664,20 +1616,28 @@ struct PageInn<'a> {
     about: String,
     description: String,
     anchor: usize,
+    prev_cursor: String,
+    cursor: String,
     n: usize,
     is_desc: bool,
     inn_role: u8,
+    post_role: u8,
+    qa_mode: bool,
     filter: Option<String>,
     username: Option<String>,
     inn_users_count: usize,
     is_mod: bool,
+    is_log_public: bool,
 }
 
 /// url params: `inn.html`
 #[derive(Deserialize)]
 pub(crate) struct ParamsInn {
     anchor: Option<usize>,
+    cursor: Option<String>,
     is_desc: Option<bool>,
+    /// `cursor` is the first item of the next page, see [PidsPage]
+    prev: Option<bool>,
     filter: Option<String>,
 }
 
@@ -688,15 +1648,26 @@ pub(crate) async fn inn(
     Path(iid): Path<u32>,
     Query(params): Query<ParamsInn>,
 ) -> Result<impl IntoResponse, AppError> {
-    let site_config = get_site_config(&db)?;
+    let mut site_config = get_site_config(&db)?;
     let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+    let inn_config = get_inn_config(&db, iid)?;
+    inn_config.apply(&mut site_config);
 
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
-    let page_params = ParamsPage { anchor, n, is_desc };
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let is_prev = params.prev.unwrap_or_default() && cursor.is_some();
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc: is_desc != is_prev,
+        cursor,
+    };
 
     let mut index = Vec::with_capacity(n);
+    let mut prev_cursor = String::new();
+    let mut cursor = String::new();
     let mut joined_inns = &Vec::new();
     let mut user_iins: Result<Vec<u32>, AppError> = Err(AppError::NotFound);
     let mut username: Option<String> = None;
@@ -713,7 +1684,7 @@ pub(crate) async fn inn(
     match params.filter.as_deref() {
         Some("joined") if claim.is_some() => {
             if let Ok(ref iids) = user_iins {
-                index = get_pids_by_iids(&db, iids, &page_params)?;
+                (index, prev_cursor, cursor) = get_pids_by_iids(&db, iids, &page_params)?;
             };
         }
         Some("following") => {
@@ -721,32 +1692,56 @@ pub(crate) async fn inn(
                 let user_following: Vec<u32> =
                     get_ids_by_prefix(&db, "user_following", u32_to_ivec(claim.uid), None)
                         .unwrap_or_default();
-                index = get_pids_by_uids(&db, &user_following, joined_inns, &page_params)?;
+                (index, prev_cursor, cursor) =
+                    get_pids_by_uids(&db, &user_following, joined_inns, &page_params)?;
+            }
+        }
+        Some("tags") => {
+            if let Some(ref claim) = claim {
+                (index, prev_cursor, cursor) =
+                    get_pids_by_tags(&db, claim.uid, joined_inns, &page_params)?;
+            }
+        }
+        Some("unanswered") if iid > 0 && inn_config.qa_mode => {
+            if joined_inns.contains(&iid)
+                || !db
+                    .open_tree("inns_private")?
+                    .contains_key(u32_to_ivec(iid))?
+            {
+                (index, prev_cursor, cursor) = get_pids_unanswered(&db, iid, &page_params)?;
             }
         }
         Some(uid) => {
             if let Ok(uid) = uid.parse::<u32>() {
                 let user: User = get_one(&db, "users", uid)?;
                 username = Some(user.username);
-                index = get_pids_by_uids(&db, &[uid], joined_inns, &page_params)?;
+                (index, prev_cursor, cursor) =
+                    get_pids_by_uids(&db, &[uid], joined_inns, &page_params)?;
             };
         }
         _ => {
             if iid == 0 {
-                index = get_pids_all(&db, joined_inns, &page_params)?;
+                (index, prev_cursor, cursor) = get_pids_all(&db, joined_inns, &page_params)?;
             } else if db
                 .open_tree("inns_private")?
                 .contains_key(u32_to_ivec(iid))?
             {
                 if joined_inns.contains(&iid) {
-                    index = get_pids_by_iids(&db, &[iid], &page_params)?;
+                    (index, prev_cursor, cursor) =
+                        get_pids_by_inn(&db, iid, &inn_config, &page_params)?;
                 }
             } else {
-                index = get_pids_by_iids(&db, &[iid], &page_params)?;
+                (index, prev_cursor, cursor) =
+                    get_pids_by_inn(&db, iid, &inn_config, &page_params)?;
             }
         }
     }
 
+    if is_prev {
+        index.reverse();
+        std::mem::swap(&mut prev_cursor, &mut cursor);
+    }
+
     let out_post_list = get_out_post_list(&db, &index)?;
     let mut inn_role = 0;
     if let Some(ref claim) = claim {
@@ -762,8 +1757,12 @@ pub(crate) async fn inn(
     } else {
         0
     };
+    let is_log_public = inn_config.log_public;
 
-    let filter = if claim.is_none() { None } else { params.filter };
+    let filter = match params.filter {
+        Some(filter) if claim.is_some() || filter == "unanswered" => Some(filter),
+        _ => None,
+    };
     let has_unread = if let Some(ref claim) = claim {
         has_unread(&db, claim.uid)?
     } else {
@@ -793,109 +1792,560 @@ pub(crate) async fn inn(
         description,
         posts: out_post_list,
         anchor,
+        prev_cursor,
+        cursor,
         iid,
         n,
         is_desc,
         inn_role,
+        post_role: inn_config.post_role(),
+        qa_mode: inn_config.qa_mode,
         filter,
         username,
         inn_users_count,
         is_mod,
+        is_log_public,
     };
 
     Ok(into_response(&page_inn, "html"))
 }
 
-/// Page data: `inn_feed.html`
-#[derive(Template)]
-#[template(path = "inn_feed.html")]
-struct PageInnFeed {
-    title: String,
-    description: String,
-    link: String,
-    updated: String,
-    posts: Vec<FeedPost>,
+/// The number of entries per page in atom/rss feeds.
+const FEED_SIZE: usize = 30;
+
+/// url params: atom/rss feeds
+#[derive(Deserialize)]
+pub(crate) struct ParamsFeed {
+    cursor: Option<String>,
+    /// `rss` for rss 2.0, otherwise atom
+    format: Option<String>,
+    /// feed token of the user, unlocks private inns
+    token: Option<String>,
 }
 
-struct FeedPost {
-    pid: u32,
-    iid: u32,
-    username: String,
+/// An entry of atom/rss feeds
+struct FeedEntry {
+    link: String,
     title: String,
-    created_at: String,
+    author: String,
+    author_link: String,
+    tags: Vec<String>,
     content: String,
+    created_at: i64,
+}
+
+impl FeedEntry {
+    fn from_post(db: &Db, domain: &str, post: Post) -> Result<Self, AppError> {
+        let user: User = get_one(db, "users", post.uid)?;
+        Ok(FeedEntry {
+            link: format!("{domain}/post/{}/{}", post.iid, post.pid),
+            title: post.title,
+            author: user.username,
+            author_link: format!("{domain}/user/{}", post.uid),
+            tags: post.tags,
+            content: md2html(&post.content),
+            created_at: post.created_at,
+        })
+    }
+}
+
+/// atom/rss feed output
+struct OutFeed {
+    title: String,
+    subtitle: String,
+    /// the html page of the feed
+    link: String,
+    self_link: String,
+    next_cursor: Option<String>,
+    entries: Vec<FeedEntry>,
+}
+
+impl OutFeed {
+    /// the link of the next page, in the same `format`
+    fn next_link(&self, format: Option<&str>) -> Option<String> {
+        let cursor = self.next_cursor.as_ref()?;
+        let sep = if self.self_link.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        let format = format
+            .map(|format| format!("&format={format}"))
+            .unwrap_or_default();
+        Some(format!("{}{sep}cursor={cursor}{format}", self.self_link))
+    }
+
+    /// render atom feed, or rss 2.0 if `format` is `rss`.
+    fn render(self, format: Option<&str>) -> Response {
+        if format == Some("rss") {
+            return self.render_rss();
+        }
+
+        let to_datetime = |timestamp: i64| -> FixedDateTime {
+            let naivedatetime = NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap();
+            DateTime::<Utc>::from_utc(naivedatetime, Utc).into()
+        };
+        let updated = self
+            .entries
+            .iter()
+            .map(|entry| entry.created_at)
+            .max()
+            .unwrap_or_else(|| Utc::now().timestamp());
+
+        let mut links = vec![
+            atom_syndication::Link {
+                href: self.link.clone(),
+                rel: "alternate".into(),
+                mime_type: Some("text/html".into()),
+                ..Default::default()
+            },
+            atom_syndication::Link {
+                href: self.self_link.clone(),
+                rel: "self".into(),
+                mime_type: Some("application/atom+xml".into()),
+                ..Default::default()
+            },
+        ];
+        if let Some(href) = self.next_link(format) {
+            links.push(atom_syndication::Link {
+                href,
+                rel: "next".into(),
+                mime_type: Some("application/atom+xml".into()),
+                ..Default::default()
+            });
+        }
+
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for entry in self.entries {
+            let categories = entry
+                .tags
+                .into_iter()
+                .map(|term| atom_syndication::Category {
+                    term,
+                    ..Default::default()
+                })
+                .collect();
+            entries.push(atom_syndication::Entry {
+                title: Text::plain(entry.title),
+                id: entry.link.clone(),
+                updated: to_datetime(entry.created_at),
+                published: Some(to_datetime(entry.created_at)),
+                authors: vec![Person {
+                    name: entry.author,
+                    uri: Some(entry.author_link),
+                    ..Default::default()
+                }],
+                categories,
+                links: vec![atom_syndication::Link {
+                    href: entry.link,
+                    rel: "alternate".into(),
+                    mime_type: Some("text/html".into()),
+                    ..Default::default()
+                }],
+                content: Some(Content {
+                    value: Some(entry.content),
+                    content_type: Some("html".into()),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            });
+        }
+
+        let feed = atom_syndication::Feed {
+            title: Text::plain(self.title),
+            subtitle: Some(Text::plain(self.subtitle)),
+            id: self.self_link,
+            updated: to_datetime(updated),
+            links,
+            entries,
+            ..Default::default()
+        };
+
+        (
+            [(CONTENT_TYPE, "application/atom+xml; charset=utf-8")],
+            feed.to_string(),
+        )
+            .into_response()
+    }
+
+    fn render_rss(self) -> Response {
+        let next_link = self.next_link(Some("rss"));
+        let to_date = |timestamp: i64| {
+            let naivedatetime = NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap();
+            DateTime::<Utc>::from_utc(naivedatetime, Utc).to_rfc2822()
+        };
+
+        let mut items = Vec::with_capacity(self.entries.len());
+        for entry in self.entries {
+            let categories = entry
+                .tags
+                .into_iter()
+                .map(|name| rss::Category {
+                    name,
+                    ..Default::default()
+                })
+                .collect();
+            items.push(rss::Item {
+                title: Some(entry.title),
+                link: Some(entry.link.clone()),
+                guid: Some(rss::Guid {
+                    value: entry.link,
+                    permalink: true,
+                }),
+                author: Some(entry.author),
+                categories,
+                pub_date: Some(to_date(entry.created_at)),
+                description: Some(entry.content),
+                ..Default::default()
+            });
+        }
+
+        // rss 2.0 has no paging, so the next page goes in an atom:link
+        let mut extensions = BTreeMap::new();
+        let mut namespaces = BTreeMap::new();
+        if let Some(href) = next_link {
+            let next = rss::extension::Extension {
+                name: "atom:link".into(),
+                attrs: BTreeMap::from([
+                    ("href".into(), href),
+                    ("rel".into(), "next".into()),
+                    ("type".into(), "application/rss+xml".into()),
+                ]),
+                ..Default::default()
+            };
+            extensions.insert("atom".into(), BTreeMap::from([("link".into(), vec![next])]));
+            namespaces.insert("atom".into(), "http://www.w3.org/2005/Atom".into());
+        }
+
+        let channel = rss::Channel {
+            title: self.title,
+            link: self.link,
+            description: self.subtitle,
+            last_build_date: Some(Utc::now().to_rfc2822()),
+            items,
+            extensions,
+            namespaces,
+            ..Default::default()
+        };
+
+        (
+            [(CONTENT_TYPE, "application/rss+xml; charset=utf-8")],
+            channel.to_string(),
+        )
+            .into_response()
+    }
+}
+
+/// get the uid of a feed token. Tokens of banned users are ignored.
+pub(super) fn get_uid_by_feed_token(db: &Db, token: Option<&str>) -> Result<Option<u32>, AppError> {
+    let uid = match token {
+        Some(token) => db
+            .open_tree("feed_tokens")?
+            .get(token)?
+            .map(|v| ivec_to_u32(&v)),
+        None => None,
+    };
+    if let Some(uid) = uid {
+        let user: User = get_one(db, "users", uid)?;
+        if user.role > 0 {
+            return Ok(Some(uid));
+        }
+    }
+    Ok(None)
+}
+
+/// get the inns the user has joined and is still a member of (Limited or above)
+fn get_member_inns(db: &Db, uid: u32) -> Result<Vec<u32>, AppError> {
+    let mut iids = Vec::new();
+    for iid in get_ids_by_prefix(db, "user_inns", u32_to_ivec(uid), None)? {
+        if get_inn_role(db, iid, uid)? >= Some(3) {
+            iids.push(iid);
+        }
+    }
+    Ok(iids)
 }
 
-/// `GET /inn/:iid/feed` inn page
+/// `GET /inn/:iid/feed` inn feed
+///
+/// Private inns need `?token=` of a member, roles are checked at fetch time.
 pub(crate) async fn inn_feed(
     State(db): State<Db>,
     Path(iid): Path<u32>,
+    Query(params): Query<ParamsFeed>,
 ) -> Result<impl IntoResponse, AppError> {
     let page_params = ParamsPage {
         anchor: 0,
-        n: 30,
+        n: FEED_SIZE,
         is_desc: true,
+        cursor: decode_cursor(params.cursor.as_deref()),
     };
 
     let site_config = get_site_config(&db)?;
+    let domain = site_config.domain.trim_end_matches('/');
+    let link = format!("{domain}/inn/{iid}");
+    let token_uid = get_uid_by_feed_token(&db, params.token.as_deref())?;
+    let self_link = match params.token {
+        Some(ref token) if token_uid.is_some() => format!("{link}/feed?token={token}"),
+        _ => format!("{link}/feed"),
+    };
 
     let mut index = Vec::with_capacity(page_params.n);
+    let mut cursor = String::new();
     let title;
-    let description;
-    let link = PathBuf::from(&site_config.domain)
-        .join("inn")
-        .join(iid.to_string())
-        .display()
-        .to_string();
-
+    let subtitle;
     if iid == 0 {
-        index = get_pids_all(&db, &[], &page_params)?;
+        let joined_inns = match token_uid {
+            Some(uid) => get_member_inns(&db, uid)?,
+            None => vec![],
+        };
+        (index, _, cursor) = get_pids_all(&db, &joined_inns, &page_params)?;
         title = site_config.site_name;
-        description = site_config.description;
+        subtitle = site_config.description;
     } else {
         let inn: Inn = get_one(&db, "inns", iid)?;
         title = inn.inn_name;
-        description = md2html(&inn.description);
+        subtitle = inn.about;
 
-        if inn.inn_type != "Private" {
-            index = get_pids_by_iids(&db, &[iid], &page_params)?;
+        let is_member = match token_uid {
+            Some(uid) => get_inn_role(&db, iid, uid)? >= Some(3),
+            None => false,
+        };
+        if inn.inn_type != InnType::Private || is_member {
+            (index, _, cursor) = get_pids_by_iids(&db, &[iid], &page_params)?;
         }
     }
 
-    let mut feed_posts = Vec::with_capacity(index.len());
-    let mut updated = Utc::now().to_rfc3339();
-    for (idx, i) in index.into_iter().enumerate() {
-        let post: Post = get_one(&db, "posts", i)?;
-        let user: User = get_one(&db, "users", post.uid)?;
-        let date = timestamp_to_date(post.created_at);
-        if idx == 0 {
-            let naivedatetime = NaiveDateTime::from_timestamp_opt(post.created_at, 0).unwrap();
-            updated = DateTime::<Utc>::from_utc(naivedatetime, Utc).to_rfc3339();
+    let mut entries = Vec::with_capacity(index.len());
+    for pid in &index {
+        let post: Post = get_one(&db, "posts", *pid)?;
+        if post.is_hidden {
+            continue;
         }
+        entries.push(FeedEntry::from_post(&db, domain, post)?);
+    }
 
-        let feed_post = FeedPost {
-            pid: post.pid,
-            iid: post.iid,
-            username: user.username,
-            title: post.title,
-            created_at: date,
-            content: md2html(&post.content),
+    let out_feed = OutFeed {
+        title,
+        subtitle,
+        self_link,
+        next_cursor: (index.len() == FEED_SIZE).then_some(cursor),
+        link,
+        entries,
+    };
+    Ok(out_feed.render(params.format.as_deref()))
+}
+
+/// `GET /user/feed/:token` personal feed: the latest notifications and posts of joined inns
+pub(crate) async fn user_feed(
+    State(db): State<Db>,
+    Path(token): Path<String>,
+    Query(params): Query<ParamsFeed>,
+) -> Result<impl IntoResponse, AppError> {
+    let uid = get_uid_by_feed_token(&db, Some(&token))?.ok_or(AppError::NotFound)?;
+    let site_config = get_site_config(&db)?;
+    let domain = site_config.domain.trim_end_matches('/');
+    let user: User = get_one(&db, "users", uid)?;
+    let member_inns = get_member_inns(&db, uid)?;
+
+    let page_params = ParamsPage {
+        anchor: 0,
+        n: FEED_SIZE,
+        is_desc: true,
+        cursor: None,
+    };
+    let (index, ..) = get_pids_by_iids(&db, &member_inns, &page_params)?;
+    let mut entries = Vec::with_capacity(FEED_SIZE * 2);
+    for pid in &index {
+        let post: Post = get_one(&db, "posts", *pid)?;
+        if !post.is_hidden {
+            entries.push(FeedEntry::from_post(&db, domain, post)?);
+        }
+    }
+
+    let mut followers = get_ids_by_prefix(&db, "user_followers", u32_to_ivec(uid), None)?;
+    followers.push(uid);
+    let inns_private_tree = db.open_tree("inns_private")?;
+    let post_comments_tree = db.open_tree("post_comments")?;
+    let notifications_tree = db.open_tree("notifications")?;
+    // kv_pair: uid#pid#cid = notification_code
+    for i in notifications_tree
+        .scan_prefix(u32_to_ivec(uid))
+        .rev()
+        .take(FEED_SIZE)
+    {
+        let (key, value) = i?;
+        let pid = u8_slice_to_u32(&key[4..8]);
+        let cid = u8_slice_to_u32(&key[8..12]);
+
+        if pid == 0 {
+            if let Ok(solo) = get_one::<Solo>(&db, "solos", cid) {
+                if !can_visit_solo(solo.visibility, &followers, solo.uid, uid) {
+                    continue;
+                }
+                let solo_user: User = get_one(&db, "users", solo.uid)?;
+                entries.push(FeedEntry {
+                    link: format!("{domain}/solo/{cid}"),
+                    title: format!("{} mentioned you", solo_user.username),
+                    author: solo_user.username,
+                    author_link: format!("{domain}/user/{}", solo.uid),
+                    tags: solo.hashtags,
+                    content: solo.content,
+                    created_at: solo.created_at,
+                });
+            }
+            continue;
+        }
+
+        let Ok(post) = get_one::<Post>(&db, "posts", pid) else {
+            continue;
+        };
+        if post.is_hidden
+            || (inns_private_tree.contains_key(u32_to_ivec(post.iid))?
+                && !member_inns.contains(&post.iid))
+        {
+            continue;
+        }
+        let link = format!("{domain}/post/{}/{pid}", post.iid);
+
+        // rejected comments are dropped, the cid is the id they were held with
+        let comment = match post_comments_tree.get(&key[4..12])? {
+            Some(v) if value[0] % 100 != 4 => {
+                Some(bincode::decode_from_slice::<Comment, _>(&v, standard())?.0)
+            }
+            _ => None,
         };
-        feed_posts.push(feed_post);
+        match (value[0] % 100, comment) {
+            (code @ (3 | 4), comment) => {
+                let result = if code == 3 { "approved" } else { "rejected" };
+                let (link, title, content, created_at) = match comment {
+                    Some(comment) => (
+                        format!("{link}#{cid}"),
+                        format!("Your comment on {} was {result}", post.title),
+                        comment.content,
+                        comment.created_at,
+                    ),
+                    None if cid > 0 => (
+                        link,
+                        format!("Your comment on {} was {result}", post.title),
+                        String::new(),
+                        post.created_at,
+                    ),
+                    None => (
+                        link,
+                        format!("Your post {} was {result}", post.title),
+                        String::new(),
+                        post.created_at,
+                    ),
+                };
+                entries.push(FeedEntry {
+                    link,
+                    title,
+                    author: site_config.site_name.clone(),
+                    author_link: domain.to_owned(),
+                    tags: vec![],
+                    content,
+                    created_at,
+                });
+            }
+            (_, Some(comment)) if !comment.is_hidden => {
+                let comment_user: User = get_one(&db, "users", comment.uid)?;
+                entries.push(FeedEntry {
+                    link: format!("{link}#{cid}"),
+                    title: format!("{} on {}", comment_user.username, post.title),
+                    author: comment_user.username,
+                    author_link: format!("{domain}/user/{}", comment.uid),
+                    tags: vec![],
+                    content: comment.content,
+                    created_at: comment.created_at,
+                });
+            }
+            _ => {}
+        }
     }
 
-    let page_inn_feed = PageInnFeed {
-        title,
-        description,
+    entries.sort_unstable_by_key(|entry| std::cmp::Reverse(entry.created_at));
+    entries.truncate(FEED_SIZE);
+
+    let out_feed = OutFeed {
+        title: format!("{} - {}", site_config.site_name, user.username),
+        subtitle: "Notifications and joined inns".to_owned(),
+        link: format!("{domain}/notification"),
+        self_link: format!("{domain}/user/feed/{token}"),
+        next_cursor: None,
+        entries,
+    };
+    Ok(out_feed.render(params.format.as_deref()))
+}
+
+/// `GET /post/:iid/:pid/feed` comments feed of the post
+pub(crate) async fn post_feed(
+    State(db): State<Db>,
+    Path((iid, pid)): Path<(u32, u32)>,
+    Query(params): Query<ParamsFeed>,
+) -> Result<impl IntoResponse, AppError> {
+    let page_params = ParamsPage {
+        anchor: 0,
+        n: FEED_SIZE,
+        is_desc: true,
+        cursor: decode_cursor(params.cursor.as_deref()),
+    };
+
+    let site_config = get_site_config(&db)?;
+    let domain = site_config.domain.trim_end_matches('/');
+    let post: Post = get_one(&db, "posts", pid)?;
+    let iid_ivec = u32_to_ivec(iid);
+    let pid_ivec = u32_to_ivec(pid);
+    if post.iid != iid
+        || post.is_hidden
+        || db.open_tree("inns_private")?.contains_key(&iid_ivec)?
+        || db
+            .open_tree("post_pending")?
+            .contains_key([&iid_ivec, &pid_ivec].concat())?
+    {
+        return Err(AppError::NotFound);
+    }
+    let link = format!("{domain}/post/{iid}/{pid}");
+
+    let mut entries = Vec::with_capacity(page_params.n);
+    let mut cursor = String::new();
+    let mut count = 0;
+    // kv_pair: pid#cid = Comment
+    for i in iter_by_cursor(&db.open_tree("post_comments")?, &pid_ivec, &page_params) {
+        let (k, v) = i?;
+        if count == page_params.n {
+            break;
+        }
+        count += 1;
+        cursor = encode_cursor(&k);
+        let (comment, _): (Comment, usize) = bincode::decode_from_slice(&v, standard())?;
+        if comment.is_hidden {
+            continue;
+        }
+        let user: User = get_one(&db, "users", comment.uid)?;
+        entries.push(FeedEntry {
+            link: format!("{link}#{}", comment.cid),
+            title: format!("#{} {}", comment.cid, post.title),
+            author: user.username,
+            author_link: format!("{domain}/user/{}", comment.uid),
+            tags: vec![],
+            content: comment.content,
+            created_at: comment.created_at,
+        });
+    }
+
+    let out_feed = OutFeed {
+        title: post.title,
+        subtitle: format!("Comments on {}", site_config.site_name),
+        self_link: format!("{link}/feed"),
+        next_cursor: (count == FEED_SIZE).then_some(cursor),
         link,
-        updated,
-        posts: feed_posts,
+        entries,
     };
-    Ok(into_response(&page_inn_feed, "html"))
+    Ok(out_feed.render(params.format.as_deref()))
 }
 
 /// get [OutPostList] from pids
-fn get_out_post_list(db: &Db, index: &[u32]) -> Result<Vec<OutPostList>, AppError> {
+pub(super) fn get_out_post_list(db: &Db, index: &[u32]) -> Result<Vec<OutPostList>, AppError> {
     let mut post_lists = Vec::with_capacity(index.len());
     if !index.is_empty() {
         for pid in index {
@@ -922,20 +2372,27 @@ fn get_out_post_list(db: &Db, index: &[u32]) -> Result<Vec<OutPostList>, AppErro
     Ok(post_lists)
 }
 
-/// get pids all, controlled by `visibility`, sorted by timestamp
+/// a page of pids, with the cursors of its first and last items.
+///
+/// The last one is the cursor of the next page. The first one is the cursor of the previous page,
+/// which is fetched in the opposite order.
+pub(super) type PidsPage = (Vec<u32>, String, String);
+
+/// get pids all, controlled by `visibility`, sorted by timestamp.
+///
+/// The cursors are `timestamp#iid#pid`.
 fn get_pids_all(
     db: &Db,
     joined_inns: &[u32],
     page_params: &ParamsPage,
-) -> Result<Vec<u32>, AppError> {
+) -> Result<PidsPage, AppError> {
     let tree = db.open_tree("post_timeline")?;
     let mut count: usize = 0;
     let mut result = Vec::with_capacity(page_params.n);
-    let iter = if page_params.is_desc {
-        IterType::Rev(tree.iter().rev())
-    } else {
-        IterType::Iter(tree.iter())
-    };
+    let mut first = String::new();
+    let mut last = String::new();
+    let skip = page_params.skip();
+    let iter = iter_by_cursor(&tree, &[], page_params);
 
     // kvpaire: timestamp#iid#pid = visibility
     for i in iter {
@@ -945,11 +2402,15 @@ fn get_pids_all(
 
         let visibility = ivec_to_u32(&v);
         if visibility == 0 || (visibility == 10 && joined_inns.contains(&id)) {
-            if count < page_params.anchor {
+            if count < skip {
                 count += 1;
                 continue;
             }
             result.push(out_id);
+            last = encode_cursor(&k);
+            if first.is_empty() {
+                first = last.clone();
+            }
         }
 
         if result.len() == page_params.n {
@@ -957,41 +2418,115 @@ fn get_pids_all(
         }
     }
 
-    Ok(result)
+    Ok((result, first, last))
 }
 
-/// get pids by multi iids, sorted by timestamp
-fn get_pids_by_iids(db: &Db, iids: &[u32], page_params: &ParamsPage) -> Result<Vec<u32>, AppError> {
-    let mut pids = Vec::with_capacity(page_params.n);
+/// get pids of the inn, sorted by the latest activity or by pid if the inn sorts by new.
+fn get_pids_by_inn(
+    db: &Db,
+    iid: u32,
+    inn_config: &InnConfig,
+    page_params: &ParamsPage,
+) -> Result<PidsPage, AppError> {
+    if !inn_config.sort_by_new {
+        return get_pids_by_iids(db, &[iid], page_params);
+    }
+
+    let iid_ivec = u32_to_ivec(iid);
+    let pids = get_ids_by_prefix(db, "post_timeline_idx", &iid_ivec, Some(page_params))?;
+    let cursor = |pid: Option<&u32>| {
+        pid.map(|pid| encode_cursor(&[&iid_ivec, &u32_to_ivec(*pid)].concat()))
+            .unwrap_or_default()
+    };
+    let (first, last) = (cursor(pids.first()), cursor(pids.last()));
+    Ok((pids, first, last))
+}
+
+/// get pids of the questions without accepted answers in the inn, sorted by timestamp.
+fn get_pids_unanswered(db: &Db, iid: u32, page_params: &ParamsPage) -> Result<PidsPage, AppError> {
+    let post_accepted_tree = db.open_tree("post_accepted")?;
     let mut pairs = Vec::new();
-    for iid in iids {
-        let prefix = u32_to_ivec(*iid);
-        // kv_pair: iid#pid = timestamp#visibility
-        for i in db.open_tree("post_timeline_idx")?.scan_prefix(prefix) {
-            let (k, v) = i?;
-            let pid = u8_slice_to_u32(&k[4..8]);
-            let timestamp = ivec_to_u32(&v);
-            pairs.push((pid, timestamp));
+    // kv_pair: iid#pid = timestamp
+    for i in db
+        .open_tree("post_timeline_idx")?
+        .scan_prefix(u32_to_ivec(iid))
+    {
+        let (k, v) = i?;
+        if !post_accepted_tree.contains_key(&k[4..8])? {
+            pairs.push((u8_slice_to_u32(&k[4..8]), ivec_to_u32(&v)));
         }
     }
-    pairs.sort_unstable_by_key(|pair| pair.1);
-    pairs.iter().for_each(|pair| pids.push(pair.0));
-    let (start, end) = get_range(pids.len(), page_params);
-    pids = pids[start - 1..end].to_vec();
-    if page_params.is_desc {
-        pids.reverse();
+    Ok(get_page_by_timestamp(pairs, page_params))
+}
+
+/// get pids by multi iids, sorted by timestamp.
+///
+/// The cursors are `timestamp#iid#pid`, the same as [get_pids_all].
+fn get_pids_by_iids(db: &Db, iids: &[u32], page_params: &ParamsPage) -> Result<PidsPage, AppError> {
+    get_pids_by_timeline(db, page_params, |iid, _| Ok(iids.contains(&iid)))
+}
+
+/// get pids of the timeline filtered by `is_match(iid, pid)`, stopping when the page is full.
+///
+/// The cursors are `timestamp#iid#pid`, the same as [get_pids_all].
+pub(super) fn get_pids_by_timeline<F>(
+    db: &Db,
+    page_params: &ParamsPage,
+    mut is_match: F,
+) -> Result<PidsPage, AppError>
+where
+    F: FnMut(u32, u32) -> Result<bool, AppError>,
+{
+    let mut count: usize = 0;
+    let mut result = Vec::with_capacity(page_params.n);
+    let mut first = String::new();
+    let mut last = String::new();
+    let skip = page_params.skip();
+    let tree = db.open_tree("post_timeline")?;
+
+    // kv_pair: timestamp#iid#pid = visibility
+    for i in iter_by_cursor(&tree, &[], page_params) {
+        let (k, _) = i?;
+        let iid = u8_slice_to_u32(&k[4..8]);
+        if !is_match(iid, u8_slice_to_u32(&k[8..12]))? {
+            continue;
+        }
+        if count < skip {
+            count += 1;
+            continue;
+        }
+        result.push(u8_slice_to_u32(&k[8..12]));
+        last = encode_cursor(&k);
+        if first.is_empty() {
+            first = last.clone();
+        }
+        if result.len() == page_params.n {
+            break;
+        }
     }
-    Ok(pids)
+
+    Ok((result, first, last))
 }
 
-/// get pids by multi uids, controlled by `visibility`, sorted by timestamp
+/// sort `(pid, timestamp)` pairs by timestamp and get a page of pids, the cursors are `timestamp#pid`.
+fn get_page_by_timestamp(mut pairs: Vec<(u32, u32)>, page_params: &ParamsPage) -> PidsPage {
+    pairs.sort_unstable_by_key(|pair| (pair.1, pair.0));
+    let key = |pair: &(u32, u32)| [u32_to_ivec(pair.1), u32_to_ivec(pair.0)].concat();
+    let pairs = get_page(&pairs, key, page_params);
+    let cursor = |pair: Option<&(u32, u32)>| pair.map(|pair| encode_cursor(&key(pair)));
+    let (first, last) = (cursor(pairs.first()), cursor(pairs.last()));
+    let pids = pairs.iter().map(|pair| pair.0).collect();
+    (pids, first.unwrap_or_default(), last.unwrap_or_default())
+}
+
+/// get pids by multi uids, controlled by `visibility`, sorted by pid. The cursors are `pid`.
 fn get_pids_by_uids(
     db: &Db,
     uids: &[u32],
     joined_inns: &[u32],
     page_params: &ParamsPage,
-) -> Result<Vec<u32>, AppError> {
-    let mut pids = Vec::with_capacity(page_params.n);
+) -> Result<PidsPage, AppError> {
+    let mut pids = Vec::new();
     for uid in uids {
         let prefix = u32_to_ivec(*uid);
         // kv_pair: uid#pid = iid#visibility
@@ -1005,12 +2540,113 @@ fn get_pids_by_uids(
             }
         }
     }
-    let (start, end) = get_range(pids.len(), page_params);
-    pids = pids[start - 1..end].to_vec();
-    if page_params.is_desc {
-        pids.reverse();
+    pids.sort_unstable();
+    let pids = get_page(&pids, |pid| u32_to_ivec(*pid).to_vec(), page_params);
+    let (first, last) = (pid_cursor(pids.first()), pid_cursor(pids.last()));
+    Ok((pids, first, last))
+}
+
+/// the cursor of a page sorted by pid
+fn pid_cursor(pid: Option<&u32>) -> String {
+    pid.map(|pid| encode_cursor(&u32_to_ivec(*pid)))
+        .unwrap_or_default()
+}
+
+/// get pids of the followed tags and the inns of followed topics, controlled by `visibility`,
+/// sorted by pid. The cursors are `pid`.
+///
+/// Every tag and inn is iterated from the cursor, and they are merged until the page is full.
+fn get_pids_by_tags(
+    db: &Db,
+    uid: u32,
+    joined_inns: &[u32],
+    page_params: &ParamsPage,
+) -> Result<PidsPage, AppError> {
+    let cursor = page_params
+        .cursor
+        .as_deref()
+        .filter(|c| c.len() == 4)
+        .map(u8_slice_to_u32);
+    let is_desc = page_params.is_desc;
+
+    let uid_ivec = u32_to_ivec(uid);
+    let mut sources = Vec::new();
+    // kv_pair: uid#tag = &[]
+    let tags_tree = db.open_tree("tags")?;
+    for i in db.open_tree("user_tags")?.scan_prefix(&uid_ivec) {
+        let (k, _) = i?;
+        sources.push(iter_ids_after(&tags_tree, k[4..].to_vec(), cursor, is_desc));
     }
-    Ok(pids)
+    // kv_pair: uid#topic = &[]
+    let inn_posts_tree = db.open_tree("inn_posts")?;
+    for i in db.open_tree("user_topics")?.scan_prefix(&uid_ivec) {
+        let (k, _) = i?;
+        let topic = String::from_utf8_lossy(&k[4..]);
+        for iid in get_ids_by_tag(db, "topics", &topic, None)? {
+            let prefix = u32_to_ivec(iid).to_vec();
+            sources.push(iter_ids_after(&inn_posts_tree, prefix, cursor, is_desc));
+        }
+    }
+
+    // the head pid of every source
+    let mut heads = Vec::with_capacity(sources.len());
+    for mut source in sources {
+        if let Some(pid) = source.next() {
+            heads.push((pid?, source));
+        }
+    }
+
+    // the visibility is unknown until the post is loaded, so the page is filled one by one
+    let inns_private_tree = db.open_tree("inns_private")?;
+    let skip = page_params.skip();
+    let mut count = 0;
+    let mut result = Vec::with_capacity(page_params.n);
+    loop {
+        let heads_pids = heads.iter().map(|(pid, _)| *pid);
+        let next = if is_desc {
+            heads_pids.max()
+        } else {
+            heads_pids.min()
+        };
+        let Some(pid) = next else {
+            break;
+        };
+        // a post may be in several tags, so every source at this pid moves on
+        let mut idx = 0;
+        while idx < heads.len() {
+            if heads[idx].0 != pid {
+                idx += 1;
+                continue;
+            }
+            match heads[idx].1.next() {
+                Some(next) => {
+                    heads[idx].0 = next?;
+                    idx += 1;
+                }
+                None => drop(heads.swap_remove(idx)),
+            }
+        }
+
+        let Ok(post) = get_one::<Post>(db, "posts", pid) else {
+            continue;
+        };
+        if inns_private_tree.contains_key(u32_to_ivec(post.iid))?
+            && !joined_inns.contains(&post.iid)
+        {
+            continue;
+        }
+        if count < skip {
+            count += 1;
+            continue;
+        }
+        result.push(pid);
+        if result.len() == page_params.n {
+            break;
+        }
+    }
+
+    let (first, last) = (pid_cursor(result.first()), pid_cursor(result.last()));
+    Ok((result, first, last))
 }
 
 /// `GET /inn/:iid/join` join inn
@@ -1033,14 +2669,22 @@ pub(crate) async fn inn_join(
 
     match inn_users_tree.get(&inn_users_k)? {
         None => {
-            if inn.inn_type.as_str() != "Public" {
+            if inn.inn_type == InnType::Archive {
+                return Err(AppError::InnArchived);
+            } else if inn.inn_type.need_apply() {
                 // 1: applied, but pending
                 inn_users_tree.insert(&inn_users_k, &[1])?;
                 inn_apply_tree.insert(&inn_users_k, &[])?;
             } else {
                 user_inns_tree.insert(&user_inns_k, &[])?;
+                inn_event(&db, iid, "join")?;
+                let now = u32_to_ivec(Utc::now().timestamp() as u32);
+                db.open_tree("inn_joined")?.insert(&inn_users_k, now)?;
                 let count = get_count_by_prefix(&db, "inn_users", &u32_to_ivec(iid))? as u32;
-                if inn.early_birds > 0 && count <= inn.early_birds {
+                if inn.inn_type == InnType::Restricted {
+                    // only comment until mods approve
+                    inn_users_tree.insert(&inn_users_k, &[3])?;
+                } else if inn.early_birds > 0 && count <= inn.early_birds {
                     inn_users_tree.insert(&inn_users_k, &[5])?;
                 } else {
                     inn_users_tree.insert(&inn_users_k, &[4])?;
@@ -1048,9 +2692,13 @@ pub(crate) async fn inn_join(
             }
         }
         Some(_) => {
-            user_inns_tree.remove(&user_inns_k)?;
+            if user_inns_tree.remove(&user_inns_k)?.is_some() {
+                inn_event(&db, iid, "leave")?;
+            }
             inn_users_tree.remove(&inn_users_k)?;
             inn_apply_tree.remove(&inn_users_k)?;
+            db.open_tree("inn_joined")?.remove(&inn_users_k)?;
+            clear_role_change(&db, iid, claim.uid)?;
         }
     }
 
@@ -1075,6 +2723,8 @@ struct OutPost {
     is_hidden: bool,
     is_upvoted: bool,
     is_downvoted: bool,
+    is_saved: bool,
+    is_subscribed: bool,
     can_edit: bool,
 }
 
@@ -1091,6 +2741,19 @@ struct PagePost<'a> {
     is_desc: bool,
     has_joined: bool,
     is_mod: bool,
+    is_pending: bool,
+    /// comments held for review, shown to their authors and mods
+    pending_comments: Vec<OutPendingComment>,
+    related: Vec<OutPostList>,
+    more_from_inn: Vec<OutPostList>,
+    /// the accepted answer of a question, shown above the comments
+    accepted: Option<OutComment>,
+    can_accept: bool,
+    poll: Option<OutPoll>,
+    event: Option<OutEvent>,
+    link: Option<String>,
+    /// the number of the discussions of the link
+    link_count: usize,
 }
 
 /// Vec data: Comment
@@ -1104,7 +2767,241 @@ struct OutComment {
     downvotes: usize,
     is_upvoted: bool,
     is_downvoted: bool,
+    is_saved: bool,
     is_hidden: bool,
+    is_accepted: bool,
+}
+
+/// get the comment ready for the post page, with the votes of the viewer
+fn get_out_comment(
+    db: &Db,
+    claim_uid: Option<u32>,
+    pid: u32,
+    comment: Comment,
+    accepted_cid: Option<u32>,
+) -> Result<OutComment, AppError> {
+    let user: User = get_one(db, "users", comment.uid)?;
+    let date = timestamp_to_date(comment.created_at);
+
+    let mut is_upvoted = false;
+    let mut is_downvoted = false;
+    let mut is_saved = false;
+
+    if let Some(claim_uid) = claim_uid {
+        let k = [
+            &u32_to_ivec(pid),
+            &u32_to_ivec(comment.cid),
+            &u32_to_ivec(claim_uid),
+        ]
+        .concat();
+        is_upvoted = db.open_tree("comment_upvotes")?.contains_key(&k)?;
+        is_downvoted = db.open_tree("comment_downvotes")?.contains_key(&k)?;
+
+        let k = [
+            &u32_to_ivec(claim_uid),
+            &u32_to_ivec(pid),
+            &u32_to_ivec(comment.cid),
+        ]
+        .concat();
+        is_saved = db.open_tree("user_saved")?.contains_key(&k)?;
+    }
+
+    let prefix = [&u32_to_ivec(pid), &u32_to_ivec(comment.cid)].concat();
+    let upvotes = get_count_by_prefix(db, "comment_upvotes", &prefix).unwrap_or_default();
+    let downvotes = get_count_by_prefix(db, "comment_downvotes", &prefix).unwrap_or_default();
+
+    Ok(OutComment {
+        cid: comment.cid,
+        uid: comment.uid,
+        username: user.username,
+        content: comment.content,
+        created_at: date,
+        upvotes,
+        downvotes,
+        is_upvoted,
+        is_downvoted,
+        is_saved,
+        is_hidden: comment.is_hidden,
+        is_accepted: accepted_cid == Some(comment.cid),
+    })
+}
+
+/// publish a feed item as a post of the bot user, indexed by [publish_post]
+/// but without premoderation. Returns `None` if the link has been posted in the inn.
+pub(super) fn publish_feed_post(
+    db: &Db,
+    iid: u32,
+    uid: u32,
+    title: String,
+    tags: &str,
+    content: String,
+    link: &str,
+) -> Result<Option<u32>, AppError> {
+    let inn: Inn = get_one(db, "inns", iid)?;
+    let inn_role = get_inn_role(db, iid, uid)?.unwrap_or_default();
+    let inn_config = get_inn_config(db, iid)?;
+    if inn_role <= 3
+        || inn_role < inn_config.post_role()
+        || inn.inn_type == InnType::Archive
+        || (inn.inn_type == InnType::Announcement && inn_role < 8)
+    {
+        return Err(AppError::InvalidFeedBot);
+    }
+
+    let link = canonical_url(link);
+    if let Some(ref link) = link {
+        if !get_link_pids(db, link, Some(iid))?.is_empty() {
+            return Ok(None);
+        }
+    }
+
+    let mut post_tags = vec![];
+    if inn.inn_type != InnType::Private {
+        let tags_set: BTreeSet<String> = tags
+            .split('#')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+        post_tags = tags_set.into_iter().take(5).collect();
+    }
+
+    let pid = incr_id(db, "posts_count")?;
+    let post = Post {
+        pid,
+        uid,
+        iid,
+        title,
+        tags: post_tags,
+        content,
+        created_at: Utc::now().timestamp(),
+        is_locked: false,
+        is_hidden: false,
+    };
+    let post_encoded = bincode::encode_to_vec(&post, standard())?;
+    db.open_tree("posts")?
+        .insert(u32_to_ivec(pid), post_encoded)?;
+    if let Some(link) = link {
+        db.open_tree("post_url")?
+            .insert(u32_to_ivec(pid), link.as_str())?;
+    }
+    publish_post(db, &post, None)?;
+
+    user_stats(db, uid, "post")?;
+    Ok(Some(pid))
+}
+
+/// get the link of a link post
+fn get_link(db: &Db, pid: u32) -> Result<Option<String>, AppError> {
+    let v = db.open_tree("post_url")?.get(u32_to_ivec(pid))?;
+    Ok(v.map(|v| String::from_utf8_lossy(&v).into_owned()))
+}
+
+/// get the pids of the posts with the link, in the inn if `iid` is given.
+/// Hidden and pending posts are skipped.
+fn get_link_pids(db: &Db, link: &str, iid: Option<u32>) -> Result<Vec<u32>, AppError> {
+    let mut pids = vec![];
+    if let Some(v) = db.open_tree("post_links")?.get(link)? {
+        let post_pending_tree = db.open_tree("post_pending")?;
+        for pid in v.chunks_exact(4).map(u8_slice_to_u32) {
+            let post: Post = get_one(db, "posts", pid)?;
+            if post.is_hidden || matches!(iid, Some(iid) if iid != post.iid) {
+                continue;
+            }
+            let k = [&u32_to_ivec(post.iid), &u32_to_ivec(pid)].concat();
+            if !post_pending_tree.contains_key(k)? {
+                pids.push(pid);
+            }
+        }
+    }
+    Ok(pids)
+}
+
+/// Page data: `link.html`
+#[derive(Template)]
+#[template(path = "link.html")]
+struct PageLink<'a> {
+    page_data: PageData<'a>,
+    link: String,
+    posts: Vec<OutPostList>,
+}
+
+/// `GET /post/:iid/:pid/discussions` all the discussions of the link of the post
+pub(crate) async fn link_discussions(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid)): Path<(u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+    let claim_uid = claim.as_ref().map(|claim| claim.uid);
+
+    let post: Post = get_one(&db, "posts", pid)?;
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if post.iid != iid || !can_view_inn(&db, &inn, claim_uid)? {
+        return Err(AppError::NotFound);
+    }
+    let link = get_link(&db, pid)?.ok_or(AppError::NotFound)?;
+
+    let mut pids = vec![];
+    for pid in get_link_pids(&db, &link, None)?.into_iter().rev() {
+        let post: Post = get_one(&db, "posts", pid)?;
+        let inn: Inn = get_one(&db, "inns", post.iid)?;
+        if can_view_inn(&db, &inn, claim_uid)? {
+            pids.push(pid);
+        }
+    }
+    let posts = get_out_post_list(&db, &pids)?;
+
+    let page_data = PageData::new("discussions", &site_config, claim, false);
+    let page_link = PageLink {
+        page_data,
+        link,
+        posts,
+    };
+
+    Ok(into_response(&page_link, "html"))
+}
+
+/// get the posts shared from the feed item, which the user can see: (iid, pid, title)
+pub(super) fn get_item_posts(
+    db: &Db,
+    item_id: u32,
+    uid: Option<u32>,
+) -> Result<Vec<(u32, u32, String)>, AppError> {
+    let mut posts = vec![];
+    let post_pending_tree = db.open_tree("post_pending")?;
+    for pid in get_ids_by_prefix(db, "item_posts", u32_to_ivec(item_id), None)? {
+        let post: Post = get_one(db, "posts", pid)?;
+        let k = [&u32_to_ivec(post.iid), &u32_to_ivec(pid)].concat();
+        if post.is_hidden || post_pending_tree.contains_key(k)? {
+            continue;
+        }
+        let inn: Inn = get_one(db, "inns", post.iid)?;
+        if can_view_inn(db, &inn, uid)? {
+            posts.push((post.iid, pid, post.title));
+        }
+    }
+    Ok(posts)
+}
+
+/// archived inns are read only, nothing can be written to them
+pub(super) fn check_archived(db: &Db, iid: u32) -> Result<(), AppError> {
+    let inn: Inn = get_one(db, "inns", iid)?;
+    if inn.inn_type == InnType::Archive {
+        return Err(AppError::InnArchived);
+    }
+    Ok(())
+}
+
+/// whether the user can see the posts of the inn
+pub(super) fn can_view_inn(db: &Db, inn: &Inn, uid: Option<u32>) -> Result<bool, AppError> {
+    if inn.inn_type != InnType::Private {
+        return Ok(true);
+    }
+    Ok(match uid {
+        Some(uid) => get_inn_role(db, inn.iid, uid)? >= Some(3),
+        None => false,
+    })
 }
 
 /// url params: `post.html`
@@ -1119,18 +3016,23 @@ pub(crate) struct ParamsPost {
 pub(crate) async fn post(
     State(db): State<Db>,
     cookie: Option<TypedHeader<Cookie>>,
+    user_agent: Option<TypedHeader<UserAgent>>,
     Path((iid, pid)): Path<(u32, u32)>,
     Query(params): Query<ParamsPost>,
 ) -> Result<impl IntoResponse, AppError> {
-    let site_config = get_site_config(&db)?;
-    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+    let mut site_config = get_site_config(&db)?;
+    let claim = cookie
+        .as_ref()
+        .and_then(|cookie| Claim::get(&db, cookie, &site_config));
+    let inn_config = get_inn_config(&db, iid)?;
+    inn_config.apply(&mut site_config);
 
     let post: Post = get_one(&db, "posts", pid)?;
     let user: User = get_one(&db, "users", post.uid)?;
     let date = timestamp_to_date(post.created_at);
     let inn: Inn = get_one(&db, "inns", post.iid)?;
 
-    if inn.inn_type.as_str() == "Private" {
+    if inn.inn_type == InnType::Private {
         match claim.as_ref() {
             Some(claim) => {
                 let k = [&u32_to_ivec(claim.uid), &u32_to_ivec(iid)].concat();
@@ -1150,6 +3052,8 @@ pub(crate) async fn post(
     let mut is_upvoted = false;
     let mut is_downvoted = false;
     let mut is_mod = false;
+    let mut is_saved = false;
+    let mut is_subscribed = false;
     let mut can_edit = false;
     let upvotes = get_count_by_prefix(&db, "post_upvotes", &u32_to_ivec(pid)).unwrap_or_default();
     let downvotes =
@@ -1163,6 +3067,18 @@ pub(crate) async fn post(
             is_downvoted = true;
         }
 
+        let k = [&u32_to_ivec(claim.uid), &u32_to_ivec(pid), &u32_to_ivec(0)].concat();
+        if db.open_tree("user_saved")?.contains_key(&k)? {
+            is_saved = true;
+        }
+
+        let k = [&u32_to_ivec(pid), &u32_to_ivec(claim.uid)].concat();
+        if db.open_tree("post_subscribers")?.contains_key(&k)? {
+            is_subscribed = true;
+            let k = [&u32_to_ivec(claim.uid), &u32_to_ivec(pid)].concat();
+            db.open_tree("thread_notifications")?.remove(k)?;
+        }
+
         if post.created_at + 30 * 60 >= Utc::now().timestamp() {
             can_edit = true;
         }
@@ -1187,6 +3103,16 @@ pub(crate) async fn post(
         }
     }
 
+    // pending posts are only visible to the author and mods
+    let iid_ivec = u32_to_ivec(iid);
+    let claim_uid = claim.as_ref().map(|claim| claim.uid);
+    let is_pending = db
+        .open_tree("post_pending")?
+        .contains_key([&iid_ivec, &u32_to_ivec(pid)].concat())?;
+    if is_pending && !is_mod && claim_uid != Some(post.uid) {
+        return Err(AppError::NotFound);
+    }
+
     let content = if post.is_hidden && !is_mod {
         "<p><i>Hidden by mod.</i></p>".into()
     } else {
@@ -1209,89 +3135,443 @@ pub(crate) async fn post(
         downvotes,
         is_upvoted,
         is_downvoted,
+        is_saved,
+        is_subscribed,
         can_edit,
     };
 
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(false);
-    let page_params = ParamsPage { anchor, n, is_desc };
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor: None,
+    };
+
+    let mut accepted = None;
+    let mut accepted_cid = None;
+    if inn_config.qa_mode {
+        accepted_cid = get_accepted_cid(&db, pid)?;
+        if let Some(cid) = accepted_cid {
+            let k = [&u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
+            if let Some(v) = db.open_tree("post_comments")?.get(k)? {
+                let (comment, _): (Comment, usize) = bincode::decode_from_slice(&v, standard())?;
+                accepted = Some(get_out_comment(&db, claim_uid, pid, comment, accepted_cid)?);
+            }
+        }
+    }
+    let can_accept = inn_config.qa_mode
+        && (is_mod
+            || claim_uid == Some(post.uid)
+            || claim.as_ref().map(|claim| claim.role) == Some(u8::MAX));
 
     let mut out_comments = Vec::with_capacity(n);
     let count = get_count(&db, "post_comments_count", u32_to_ivec(pid))?;
     if count > 0 {
         let (start, end) = get_range(count, &page_params);
         let post_comments_tree = db.open_tree("post_comments")?;
-        let comment_upvotes_tree = db.open_tree("comment_upvotes")?;
-        let comment_downvotes_tree = db.open_tree("comment_downvotes")?;
         for i in start..=end {
             let k = [&u32_to_ivec(pid), &u32_to_ivec(i as u32)].concat();
             let v = &post_comments_tree.get(k)?;
             if let Some(v) = v {
                 let (comment, _): (Comment, usize) = bincode::decode_from_slice(v, standard())?;
-                let user: User = get_one(&db, "users", comment.uid)?;
-                let date = timestamp_to_date(comment.created_at);
-
-                let mut is_upvoted = false;
-                let mut is_downvoted = false;
-
-                if let Some(ref claim) = claim {
-                    let k = [
-                        &u32_to_ivec(pid),
-                        &u32_to_ivec(comment.cid),
-                        &u32_to_ivec(claim.uid),
-                    ]
-                    .concat();
-                    is_upvoted = comment_upvotes_tree.contains_key(&k)?;
-                    is_downvoted = comment_downvotes_tree.contains_key(&k)?;
-                }
+                let out_comment = get_out_comment(&db, claim_uid, pid, comment, accepted_cid)?;
+                out_comments.push(out_comment);
+            }
+        }
+        if is_desc {
+            out_comments.reverse();
+        }
+    }
+
+    let mut pending_comments = Vec::new();
+    if claim_uid.is_some() {
+        let prefix = [&iid_ivec, &u32_to_ivec(pid)].concat();
+        for i in db.open_tree("comment_pending")?.scan_prefix(prefix) {
+            let (k, v) = i?;
+            let (pending, _): (PendingComment, usize) = bincode::decode_from_slice(&v, standard())?;
+            let comment = pending.comment;
+            if !is_mod && claim_uid != Some(comment.uid) {
+                continue;
+            }
+            let user: User = get_one(&db, "users", comment.uid)?;
+            pending_comments.push(OutPendingComment {
+                pid,
+                cid: u8_slice_to_u32(&k[8..12]),
+                uid: comment.uid,
+                username: user.username,
+                post_title: out_post.title.clone(),
+                content: comment.content,
+            });
+        }
+    }
+
+    let event = match get_event(&db, pid)? {
+        Some((event, post)) => Some(OutEvent::new(&db, event, post, claim_uid)?),
+        None => None,
+    };
+
+    let link = get_link(&db, pid)?;
+    let link_count = match link {
+        Some(ref link) => get_link_pids(&db, link, None)?.len(),
+        None => 0,
+    };
+
+    let related = get_related(&db, pid)?;
+    let mut related = get_out_post_list(&db, &related)?;
+    related.retain(|post| !post.is_hidden);
+
+    let mut more_from_inn = Vec::with_capacity(RELATED_MAX);
+    for i in db.open_tree("inn_posts")?.scan_prefix(&iid_ivec).rev() {
+        let (k, _) = i?;
+        let id = u8_slice_to_u32(&k[4..8]);
+        if id != pid {
+            more_from_inn.push(id);
+        }
+        if more_from_inn.len() >= RELATED_MAX {
+            break;
+        }
+    }
+    let mut more_from_inn = get_out_post_list(&db, &more_from_inn)?;
+    more_from_inn.retain(|post| !post.is_hidden);
+
+    let viewer_id = cookie.as_ref().and_then(|cookie| cookie.get(VIEWER_COOKIE));
+    let user_agent = user_agent.as_ref().map(|user_agent| user_agent.as_str());
+    let (pageview, viewer_cookie) =
+        count_view(&db, pid, post.uid, claim.as_ref(), viewer_id, user_agent)?;
+    let has_unread = if let Some(ref claim) = claim {
+        has_unread(&db, claim.uid)?
+    } else {
+        false
+    };
+    let page_data = PageData::new("post", &site_config, claim, has_unread);
+    let page_post = PagePost {
+        page_data,
+        post: out_post,
+        comments: out_comments,
+        pageview,
+        anchor,
+        n,
+        is_desc,
+        has_joined,
+        is_mod,
+        is_pending,
+        pending_comments,
+        related,
+        more_from_inn,
+        accepted,
+        can_accept,
+        poll: OutPoll::get(&db, pid, claim_uid)?,
+        event,
+        link,
+        link_count,
+    };
+
+    let mut res = into_response(&page_post, "html");
+    if let Some(viewer_cookie) = viewer_cookie {
+        res.headers_mut()
+            .insert(SET_COOKIE, viewer_cookie.parse().unwrap());
+    }
+    Ok(res)
+}
+
+/// The cookie of anonymous viewers, which expires at the end of the day.
+const VIEWER_COOKIE: &str = "__Host-viewer";
+
+/// Check the user agent of common crawlers and scripts. Requests without user agent are ignored too.
+fn is_bot(user_agent: Option<&str>) -> bool {
+    let Some(user_agent) = user_agent else {
+        return true;
+    };
+    let user_agent = user_agent.to_lowercase();
+    [
+        "bot", "spider", "crawl", "slurp", "curl", "wget", "python", "go-http", "headless",
+    ]
+    .iter()
+    .any(|i| user_agent.contains(i))
+}
+
+/// Count the view of a post at most once a day per signed-in user or anonymous viewer.
+/// Views of bots and the author are not counted.
+///
+/// For privacy, no ip is stored. Anonymous viewers are distinguished by a random cookie
+/// which expires at the end of the day. The first request only issues the cookie, and the view
+/// is counted when the cookie comes back, so clients can't make up viewers.
+///
+/// Returns the pageviews and the cookie for a new anonymous viewer.
+fn count_view(
+    db: &Db,
+    pid: u32,
+    author_uid: u32,
+    claim: Option<&Claim>,
+    viewer_id: Option<&str>,
+    user_agent: Option<&str>,
+) -> Result<(u32, Option<String>), AppError> {
+    let pid_ivec = u32_to_ivec(pid);
+    let pageview = get_count(db, "post_pageviews", &pid_ivec)? as u32;
+    if is_bot(user_agent) || claim.map(|claim| claim.uid) == Some(author_uid) {
+        return Ok((pageview, None));
+    }
+
+    let now = Utc::now();
+    let today = now.date_naive();
+    let expire = today
+        .checked_add_days(Days::new(1))
+        .unwrap()
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .timestamp();
+
+    let viewer = if let Some(claim) = claim {
+        format!("u{}", claim.uid)
+    } else {
+        // kv_pair: timestamp_viewer = &[], the ids issued today
+        let viewer_ids_tree = db.open_tree("viewer_ids")?;
+        let issued = match viewer_id {
+            Some(viewer_id)
+                if viewer_id.len() == 21
+                    && viewer_id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') =>
+            {
+                viewer_ids_tree.contains_key(format!("{expire:x}_{viewer_id}"))?
+            }
+            _ => false,
+        };
+        if !issued {
+            let viewer_id = nanoid!();
+            viewer_ids_tree.insert(format!("{expire:x}_{viewer_id}"), &[])?;
+            let seconds = expire - now.timestamp();
+            let viewer_cookie = format!(
+                "{VIEWER_COOKIE}={viewer_id}; SameSite=Lax; Path=/; Secure; HttpOnly; Max-Age={seconds}"
+            );
+            return Ok((pageview, Some(viewer_cookie)));
+        }
+        format!("a{}", viewer_id.unwrap_or_default())
+    };
+
+    let k = format!("{expire:x}_{pid}_{viewer}");
+    let post_viewers_tree = db.open_tree("post_viewers")?;
+    if post_viewers_tree.contains_key(&k)? {
+        return Ok((pageview, None));
+    }
+    post_viewers_tree.insert(k, &[])?;
+
+    let k = [&pid_ivec, &u32_to_ivec(date_to_u32(today))].concat();
+    incr_id(&db.open_tree("post_pageviews_daily")?, k)?;
+    let pageview = incr_id(&db.open_tree("post_pageviews")?, pid_ivec)?;
+
+    Ok((pageview, None))
+}
+
+/// The max number of related posts and posts from the same inn on the post page.
+const RELATED_MAX: usize = 5;
+
+/// get related posts from cache. If not cached, compute it without co-voting.
+fn get_related(db: &Db, pid: u32) -> Result<Vec<u32>, AppError> {
+    let pid_ivec = u32_to_ivec(pid);
+    let post_related_tree = db.open_tree("post_related")?;
+    if let Some(v) = post_related_tree.get(&pid_ivec)? {
+        return Ok(v.chunks_exact(4).map(u8_slice_to_u32).collect());
+    }
+
+    let post: Post = get_one(db, "posts", pid)?;
+    let related = compute_related(db, &post, &HashMap::new())?;
+    let v: Vec<u8> = related.iter().flat_map(|pid| pid.to_be_bytes()).collect();
+    post_related_tree.insert(pid_ivec, v)?;
+    Ok(related)
+}
+
+/// compute related posts by score:
+///
+/// * 3 for each shared tag
+/// * 2 for each user who upvoted both posts, `upvoted` is `uid => pids`
+/// * 1 for the recent posts in the same inn
+///
+/// Hidden, pending and private posts from other inns are skipped.
+fn compute_related(
+    db: &Db,
+    post: &Post,
+    upvoted: &HashMap<u32, Vec<u32>>,
+) -> Result<Vec<u32>, AppError> {
+    let mut scores: HashMap<u32, u32> = HashMap::new();
+    for tag in &post.tags {
+        for pid in get_ids_by_tag(db, "tags", tag, None)? {
+            *scores.entry(pid).or_default() += 3;
+        }
+    }
+
+    for i in db
+        .open_tree("post_upvotes")?
+        .scan_prefix(u32_to_ivec(post.pid))
+    {
+        let (k, _) = i?;
+        let uid = u8_slice_to_u32(&k[4..8]);
+        if let Some(pids) = upvoted.get(&uid) {
+            for pid in pids {
+                *scores.entry(*pid).or_default() += 2;
+            }
+        }
+    }
+
+    for i in db
+        .open_tree("inn_posts")?
+        .scan_prefix(u32_to_ivec(post.iid))
+        .rev()
+        .take(30)
+    {
+        let (k, _) = i?;
+        let pid = u8_slice_to_u32(&k[4..8]);
+        *scores.entry(pid).or_default() += 1;
+    }
+
+    scores.remove(&post.pid);
+    let mut scores: Vec<(u32, u32)> = scores.into_iter().collect();
+    scores.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(b.0.cmp(&a.0)));
+
+    let inns_private_tree = db.open_tree("inns_private")?;
+    let post_timeline_idx_tree = db.open_tree("post_timeline_idx")?;
+    let mut related = Vec::with_capacity(RELATED_MAX);
+    for (pid, _) in scores {
+        let Ok(one) = get_one::<Post>(db, "posts", pid) else {
+            continue;
+        };
+        if one.is_hidden {
+            continue;
+        }
+        let iid_ivec = u32_to_ivec(one.iid);
+        // pending posts are not in the timeline yet
+        let k = [&iid_ivec, &u32_to_ivec(pid)].concat();
+        if !post_timeline_idx_tree.contains_key(k)? {
+            continue;
+        }
+        if one.iid != post.iid && inns_private_tree.contains_key(&iid_ivec)? {
+            continue;
+        }
+        related.push(pid);
+        if related.len() >= RELATED_MAX {
+            break;
+        }
+    }
+
+    Ok(related)
+}
+
+/// Cron job: refresh the cache of related posts.
+///
+/// Only the posts created, approved, bumped or edited since the last run are recomputed, and then
+/// the posts related to them, which may rank them now.
+pub(crate) async fn refresh_related(db: &Db) -> Result<(), AppError> {
+    let now = u32_to_ivec(Utc::now().timestamp() as u32);
+    let since = db.get("related_refreshed_at")?.unwrap_or_default();
+
+    let mut pids = BTreeSet::new();
+    // kv_pair: timestamp#iid#pid = visibility
+    for i in db.open_tree("post_timeline")?.range(since..) {
+        let (k, _) = i?;
+        pids.insert(u8_slice_to_u32(&k[8..12]));
+    }
+    let post_edited_tree = db.open_tree("post_edited")?;
+    let edited: Vec<_> = post_edited_tree.iter().keys().collect::<Result<_, _>>()?;
+    for k in edited {
+        post_edited_tree.remove(&k)?;
+        pids.insert(ivec_to_u32(&k));
+    }
+
+    if !pids.is_empty() {
+        // uid => upvoted pids
+        let mut upvoted: HashMap<u32, Vec<u32>> = HashMap::new();
+        for i in db.open_tree("post_upvotes")?.iter() {
+            let (k, _) = i?;
+            let pid = u8_slice_to_u32(&k[0..4]);
+            let uid = u8_slice_to_u32(&k[4..8]);
+            upvoted.entry(uid).or_default().push(pid);
+        }
+
+        let post_related_tree = db.open_tree("post_related")?;
+        let refresh = |pid: u32| -> Result<Vec<u32>, AppError> {
+            let Ok(post) = get_one::<Post>(db, "posts", pid) else {
+                return Ok(vec![]);
+            };
+            let related = compute_related(db, &post, &upvoted)?;
+            let v: Vec<u8> = related.iter().flat_map(|pid| pid.to_be_bytes()).collect();
+            post_related_tree.insert(u32_to_ivec(pid), v)?;
+            Ok(related)
+        };
+
+        let mut affected = BTreeSet::new();
+        for pid in &pids {
+            affected.extend(refresh(*pid)?);
+        }
+        for pid in affected.difference(&pids) {
+            refresh(*pid)?;
+        }
+    }
+
+    db.insert("related_refreshed_at", now)?;
+    Ok(())
+}
+
+/// Page data: `post_views.html`
+#[derive(Template)]
+#[template(path = "post_views.html")]
+struct PagePostViews<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    pid: u32,
+    title: String,
+    pageview: usize,
+    views: Vec<(String, u32)>,
+}
+
+/// `GET /post/:iid/:pid/views` daily views of the post, for the author and mods
+pub(crate) async fn post_views(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid)): Path<(u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie
+        .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
+        .ok_or(AppError::NonLogin)?;
 
-                let prefix = [&u32_to_ivec(pid), &u32_to_ivec(comment.cid)].concat();
-                let upvotes =
-                    get_count_by_prefix(&db, "comment_upvotes", &prefix).unwrap_or_default();
-                let downvotes =
-                    get_count_by_prefix(&db, "comment_downvotes", &prefix).unwrap_or_default();
+    let post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid {
+        return Err(AppError::NotFound);
+    }
+    if post.uid != claim.uid && !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
 
-                let out_comment = OutComment {
-                    cid: comment.cid,
-                    uid: comment.uid,
-                    username: user.username,
-                    content: comment.content,
-                    created_at: date,
-                    upvotes,
-                    downvotes,
-                    is_upvoted,
-                    is_downvoted,
-                    is_hidden: comment.is_hidden,
-                };
-                out_comments.push(out_comment);
-            }
-        }
-        if is_desc {
-            out_comments.reverse();
-        }
+    let pid_ivec = u32_to_ivec(pid);
+    let mut views = Vec::with_capacity(30);
+    // kv_pair: pid#date = N
+    for i in db
+        .open_tree("post_pageviews_daily")?
+        .scan_prefix(&pid_ivec)
+        .rev()
+        .take(30)
+    {
+        let (k, v) = i?;
+        let date = u8_slice_to_u32(&k[4..8]);
+        let date = format!("{}-{:02}-{:02}", date / 10000, date / 100 % 100, date % 100);
+        views.push((date, ivec_to_u32(&v)));
     }
+    let pageview = get_count(&db, "post_pageviews", &pid_ivec)?;
 
-    let pageview = incr_id(&db.open_tree("post_pageviews")?, u32_to_ivec(pid))?;
-    let has_unread = if let Some(ref claim) = claim {
-        has_unread(&db, claim.uid)?
-    } else {
-        false
-    };
-    let page_data = PageData::new("post", &site_config, claim, has_unread);
-    let page_post = PagePost {
+    let page_data = PageData::new("Views", &site_config, Some(claim), false);
+    let page_post_views = PagePostViews {
         page_data,
-        post: out_post,
-        comments: out_comments,
+        iid,
+        pid,
+        title: post.title,
         pageview,
-        anchor,
-        n,
-        is_desc,
-        has_joined,
-        is_mod,
+        views,
     };
 
-    Ok(into_response(&page_post, "html"))
+    Ok(into_response(&page_post_views, "html"))
 }
 
 /// Form data: `/inn/:iid/:pid/` comment create
@@ -1308,24 +3588,32 @@ pub(crate) async fn comment_post(
     Path((iid, pid)): Path<(u32, u32)>,
     ValidatedForm(input): ValidatedForm<FormComment>,
 ) -> Result<impl IntoResponse, AppError> {
-    let site_config = get_site_config(&db)?;
+    let mut site_config = get_site_config(&db)?;
     let claim = cookie
         .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
         .ok_or(AppError::NonLogin)?;
 
     let inn_role = get_inn_role(&db, iid, claim.uid)?.ok_or(AppError::Unauthorized)?;
     if inn_role < 3 {
-        return Err(AppError::Unauthorized);
+        return Err(role_error(&db, iid, claim.uid));
     }
 
-    if !db.open_tree("inns")?.contains_key(u32_to_ivec(iid))? {
-        return Err(AppError::NotFound);
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if inn.inn_type == InnType::Archive {
+        return Err(AppError::InnArchived);
     }
 
+    let inn_config = get_inn_config(&db, iid)?;
+    if inn_role < inn_config.comment_role() {
+        return Err(AppError::Unauthorized);
+    }
+    inn_config.apply(&mut site_config);
+
     let created_at = Utc::now().timestamp();
     if created_at - claim.last_write < site_config.comment_interval {
         return Err(AppError::WriteInterval);
     }
+    inn_config.check_slow_mode(&db, iid, claim.uid, inn_role)?;
 
     let post: Post = get_one(&db, "posts", pid)?;
     if post.iid != iid {
@@ -1335,14 +3623,24 @@ pub(crate) async fn comment_post(
         return Err(AppError::Locked);
     }
 
+    // pending posts are only visible to the author and mods, and stay off the timeline
     let pid_ivec = u32_to_ivec(pid);
-    let cid = incr_id(&db.open_tree("post_comments_count")?, &pid_ivec)?;
+    let iid_ivec = u32_to_ivec(iid);
+    let is_pending = db
+        .open_tree("post_pending")?
+        .contains_key([&iid_ivec, &pid_ivec].concat())?;
+    if is_pending && post.uid != claim.uid && inn_role < 8 {
+        return Err(AppError::NotFound);
+    }
+
+    let comment_count = get_count(&db, "post_comments_count", &pid_ivec)? as u32;
 
     let mut content = input.content;
 
     // extract @username or @uid notificaiton
     let notifications = extract_element(&content, 5, '@');
     let notification_tree = db.open_tree("notifications")?;
+    let mut mentioned = Vec::with_capacity(notifications.len());
     for notification in &notifications {
         let (uid, username) = match notification.parse::<u32>() {
             Ok(uid) => {
@@ -1364,17 +3662,14 @@ pub(crate) async fn comment_post(
         let from = format!("@{notification}");
         let to = format!("@{notification_link}");
         content = content.replace(&from, &to);
-
-        // notify user to be mentioned in comment
-        let notify_key = [&u32_to_ivec(uid), &pid_ivec, &u32_to_ivec(cid)].concat();
-        notification_tree.insert(notify_key, vec![0])?;
+        mentioned.push(uid);
     }
 
     let reply_to = extract_element(&content, 1, '#');
     let mut reply_to_cid = None;
     if !reply_to.is_empty() {
         if let Ok(reply_cid) = reply_to[0].parse::<u32>() {
-            if reply_cid < cid {
+            if reply_cid <= comment_count {
                 let reply_link = format!("[{}](/post/{}/{}#{})", reply_to[0], iid, pid, reply_cid);
                 let from = format!("#{reply_cid}");
                 let to = format!("#{reply_link}");
@@ -1385,7 +3680,7 @@ pub(crate) async fn comment_post(
     }
 
     let comment = Comment {
-        cid,
+        cid: 0,
         pid,
         uid: claim.uid,
         reply_to: reply_to_cid,
@@ -1393,15 +3688,39 @@ pub(crate) async fn comment_post(
         created_at,
         is_hidden: false,
     };
-    let comment_encoded = bincode::encode_to_vec(&comment, standard())?;
-    let k = [&pid_ivec, &u32_to_ivec(cid)].concat();
-    db.open_tree("post_comments")?.insert(k, comment_encoded)?;
 
-    let k = [&u32_to_ivec(claim.uid), &pid_ivec, &u32_to_ivec(cid)].concat();
-    db.open_tree("user_comments")?.insert(k, &[])?;
+    if !db
+        .open_tree("auto_subscribe_off")?
+        .contains_key(u32_to_ivec(claim.uid))?
+    {
+        let k = [&pid_ivec, &u32_to_ivec(claim.uid)].concat();
+        db.open_tree("post_subscribers")?.insert(k, &[])?;
+    }
+
+    // comments from the roles below the threshold are held for review, they are not counted
+    // and the mentioned users will not be notified until approved
+    if inn_config.premod_comments && inn_role < inn_config.premod_role {
+        let id = incr_id(&db, "comment_pending_count")?;
+        let k = [&iid_ivec, &pid_ivec, &u32_to_ivec(id)].concat();
+        let pending = PendingComment { comment, mentioned };
+        let pending_encoded = bincode::encode_to_vec(&pending, standard())?;
+        db.open_tree("comment_pending")?
+            .insert(k, pending_encoded)?;
+
+        claim.update_last_write(&db)?;
+        let target = format!("/post/{iid}/{pid}");
+        return Ok(Redirect::to(&target));
+    }
+
+    let cid = insert_comment(&db, iid, comment)?;
+
+    // notify user to be mentioned in comment
+    for &uid in &mentioned {
+        let notify_key = [&u32_to_ivec(uid), &pid_ivec, &u32_to_ivec(cid)].concat();
+        notification_tree.insert(notify_key, vec![0])?;
+    }
 
     let created_at_ivec = u32_to_ivec(created_at as u32);
-    let iid_ivec = u32_to_ivec(iid);
     let k = [&iid_ivec, &pid_ivec].concat();
 
     let old_timestamp = db.open_tree("post_timeline_idx")?.get(&k)?;
@@ -1414,7 +3733,7 @@ pub(crate) async fn comment_post(
     }
 
     // only the fellow could update the timeline by adding comment
-    if inn_role >= 5 {
+    if inn_role >= 5 && !is_pending {
         // kv_pair: iid#pid = timestamp
         db.open_tree("post_timeline_idx")?
             .insert(k, &created_at_ivec)?;
@@ -1431,6 +3750,14 @@ pub(crate) async fn comment_post(
         notification_tree.insert(notify_key, vec![1])?;
     }
 
+    mentioned.push(claim.uid);
+    mentioned.push(post.uid);
+    notify_subscribers(&db, iid, pid, cid, &mentioned)?;
+
+    if inn_role == 4 {
+        check_promotion(&db, iid, claim.uid)?;
+    }
+
     user_stats(&db, claim.uid, "comment")?;
     claim.update_last_write(&db)?;
 
@@ -1482,19 +3809,84 @@ pub(crate) async fn comment_delete(
     if !db.open_tree("user_comments")?.contains_key(k)? {
         return Err(AppError::Unauthorized);
     }
+    let post: Post = get_one(&db, "posts", pid)?;
+    check_archived(&db, post.iid)?;
 
     let k = [&u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
-    db.open_tree("post_comments")?.remove(k)?;
+    if db.open_tree("post_comments")?.remove(k)?.is_some() {
+        member_stats(&db, post.iid, claim.uid, "comment", false)?;
+    }
+    if get_accepted_cid(&db, pid)? == Some(cid) {
+        db.open_tree("post_accepted")?.remove(u32_to_ivec(pid))?;
+    }
 
     let target = format!("/post/{iid}/{pid}");
     Ok(Redirect::to(&target))
 }
 
+/// get the accepted answer of a question
+fn get_accepted_cid(db: &Db, pid: u32) -> Result<Option<u32>, AppError> {
+    Ok(db
+        .open_tree("post_accepted")?
+        .get(u32_to_ivec(pid))?
+        .map(|v| ivec_to_u32(&v)))
+}
+
+/// `GET /post/:iid/:pid/:cid/accept` accept the comment as the answer of a question,
+/// or unaccept it if it has been accepted.
+///
+/// Only for the post author and mods of Q&A inns.
+pub(crate) async fn comment_accept(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid, cid)): Path<(u32, u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie
+        .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
+        .ok_or(AppError::NonLogin)?;
+
+    let post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid || !get_inn_config(&db, iid)?.qa_mode {
+        return Err(AppError::NotFound);
+    }
+    if post.uid != claim.uid && !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+    check_archived(&db, iid)?;
+
+    let k = [&u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
+    let comment: Comment = match db.open_tree("post_comments")?.get(k)? {
+        Some(v) => bincode::decode_from_slice(&v, standard())?.0,
+        None => return Err(AppError::NotFound),
+    };
+    if comment.is_hidden {
+        return Err(AppError::Hidden);
+    }
+
+    let post_accepted_tree = db.open_tree("post_accepted")?;
+    if get_accepted_cid(&db, pid)? == Some(cid) {
+        post_accepted_tree.remove(u32_to_ivec(pid))?;
+    } else {
+        post_accepted_tree.insert(u32_to_ivec(pid), u32_to_ivec(cid))?;
+    }
+
+    let target = format!("/post/{iid}/{pid}#{cid}");
+    Ok(Redirect::to(&target))
+}
+
+/// url params: reason of a mod action
+#[derive(Deserialize)]
+pub(crate) struct ParamsReason {
+    pub(super) reason: Option<String>,
+}
+
 /// `GET /post/:iid/:pid/:cid/hide` comment hide
 pub(crate) async fn comment_hide(
     State(db): State<Db>,
     cookie: Option<TypedHeader<Cookie>>,
     Path((iid, pid, cid)): Path<(u32, u32, u32)>,
+    Query(params): Query<ParamsReason>,
 ) -> Result<impl IntoResponse, AppError> {
     let site_config = get_site_config(&db)?;
     let claim = cookie
@@ -1505,6 +3897,11 @@ pub(crate) async fn comment_hide(
     if !db.open_tree("mod_inns")?.contains_key(k)? {
         return Err(AppError::Unauthorized);
     }
+    let post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid {
+        return Err(AppError::NotFound);
+    }
+    check_archived(&db, iid)?;
 
     let k = [&u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
     let v = db
@@ -1517,10 +3914,171 @@ pub(crate) async fn comment_hide(
     let comment_encode = bincode::encode_to_vec(&comment, standard())?;
     db.open_tree("post_comments")?.insert(&k, comment_encode)?;
 
+    let action = if comment.is_hidden {
+        "hide comment"
+    } else {
+        "open comment"
+    };
+    let target = format!("/post/{iid}/{pid}#{cid}");
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        action.into(),
+        target,
+        params.reason.unwrap_or_default(),
+    )?;
+
     let target = format!("/post/{iid}/{pid}");
     Ok(Redirect::to(&target))
 }
 
+/// add a new comment to the thread notifications of the post subscribers,
+/// except the users in `skipped` who have been notified in other ways.
+///
+/// One notification per thread: `count#last_cid`.
+pub(super) fn notify_subscribers(
+    db: &Db,
+    iid: u32,
+    pid: u32,
+    cid: u32,
+    skipped: &[u32],
+) -> Result<(), AppError> {
+    let pid_ivec = u32_to_ivec(pid);
+    let iid_ivec = u32_to_ivec(iid);
+    let is_private = db.open_tree("inns_private")?.contains_key(&iid_ivec)?;
+    let user_inns_tree = db.open_tree("user_inns")?;
+    let thread_notifications_tree = db.open_tree("thread_notifications")?;
+    for i in db.open_tree("post_subscribers")?.scan_prefix(&pid_ivec) {
+        let (k, _) = i?;
+        let uid = u8_slice_to_u32(&k[4..8]);
+        if skipped.contains(&uid) {
+            continue;
+        }
+        // members who left a private inn are not notified
+        if is_private && !user_inns_tree.contains_key([&k[4..8], &iid_ivec].concat())? {
+            continue;
+        }
+
+        let k = [&k[4..8], &pid_ivec[..]].concat();
+        let count = match thread_notifications_tree.get(&k)? {
+            Some(v) => u8_slice_to_u32(&v[0..4]) + 1,
+            None => 1,
+        };
+        let v = [&u32_to_ivec(count), &u32_to_ivec(cid)].concat();
+        thread_notifications_tree.insert(k, v)?;
+    }
+    Ok(())
+}
+
+/// `GET /post/:iid/:pid/subscribe` subscribe or unsubscribe the post
+pub(crate) async fn post_subscribe(
+    State(db): State<Db>,
+    referer: Option<TypedHeader<Referer>>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid)): Path<(u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie
+        .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
+        .ok_or(AppError::NonLogin)?;
+
+    let post_subscribers_tree = db.open_tree("post_subscribers")?;
+    let k = [&u32_to_ivec(pid), &u32_to_ivec(claim.uid)].concat();
+    if post_subscribers_tree.contains_key(&k)? {
+        post_subscribers_tree.remove(&k)?;
+        let k = [&u32_to_ivec(claim.uid), &u32_to_ivec(pid)].concat();
+        db.open_tree("thread_notifications")?.remove(k)?;
+    } else {
+        let post: Post = get_one(&db, "posts", pid)?;
+        if post.iid != iid {
+            return Err(AppError::NotFound);
+        }
+        check_archived(&db, iid)?;
+
+        // the same visibility as the post page
+        let iid_ivec = u32_to_ivec(iid);
+        let user_inns_k = [&u32_to_ivec(claim.uid), &iid_ivec].concat();
+        if db.open_tree("inns_private")?.contains_key(&iid_ivec)?
+            && !db.open_tree("user_inns")?.contains_key(user_inns_k)?
+        {
+            return Err(AppError::NotFound);
+        }
+        let post_pending_k = [&iid_ivec, &u32_to_ivec(pid)].concat();
+        if db.open_tree("post_pending")?.contains_key(post_pending_k)?
+            && post.uid != claim.uid
+            && !is_mod(&db, claim.uid, iid)?
+        {
+            return Err(AppError::NotFound);
+        }
+
+        post_subscribers_tree.insert(&k, &[])?;
+    }
+
+    let target = if let Some(referer) = get_referer(referer) {
+        referer
+    } else {
+        format!("/post/{iid}/{pid}")
+    };
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /post/:iid/:pid/:cid/save` save or unsave a post or comment, cid 0 is for the post
+pub(crate) async fn post_save(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid, cid)): Path<(u32, u32, u32)>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie
+        .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
+        .ok_or(AppError::NonLogin)?;
+
+    let user_saved_tree = db.open_tree("user_saved")?;
+    let k = [
+        &u32_to_ivec(claim.uid),
+        &u32_to_ivec(pid),
+        &u32_to_ivec(cid),
+    ]
+    .concat();
+    if user_saved_tree.contains_key(&k)? {
+        user_saved_tree.remove(&k)?;
+    } else {
+        let post: Post = get_one(&db, "posts", pid)?;
+        if post.iid != iid {
+            return Err(AppError::NotFound);
+        }
+        if cid > 0 {
+            let comment_k = [&u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
+            if !db.open_tree("post_comments")?.contains_key(comment_k)? {
+                return Err(AppError::NotFound);
+            }
+        }
+        let inn: Inn = get_one(&db, "inns", iid)?;
+        if inn.inn_type == InnType::Private {
+            let user_inns_k = [&u32_to_ivec(claim.uid), &u32_to_ivec(iid)].concat();
+            if !db.open_tree("user_inns")?.contains_key(user_inns_k)? {
+                return Err(AppError::NotFound);
+            }
+        }
+        if inn.inn_type == InnType::Archive {
+            return Err(AppError::InnArchived);
+        }
+
+        // kv_pair: uid#pid#cid = iid#timestamp
+        let now = Utc::now().timestamp() as u32;
+        let v = [&u32_to_ivec(iid), &u32_to_ivec(now)].concat();
+        user_saved_tree.insert(&k, v)?;
+    }
+
+    let target = if cid > 0 {
+        format!("/post/{iid}/{pid}#{cid}")
+    } else {
+        format!("/post/{iid}/{pid}")
+    };
+    Ok(Redirect::to(&target))
+}
+
 /// `GET /inn/:iid/:pid/upvote` post upvote
 pub(crate) async fn post_upvote(
     State(db): State<Db>,
@@ -1532,12 +4090,20 @@ pub(crate) async fn post_upvote(
         .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
         .ok_or(AppError::NonLogin)?;
 
+    let post: Post = get_one(&db, "posts", pid)?;
+    check_archived(&db, post.iid)?;
     let post_upvotes_tree = db.open_tree("post_upvotes")?;
     let k = [&u32_to_ivec(pid), &u32_to_ivec(claim.uid)].concat();
-    if post_upvotes_tree.contains_key(&k)? {
+    let is_upvoted = if post_upvotes_tree.contains_key(&k)? {
         post_upvotes_tree.remove(&k)?;
+        false
     } else {
         post_upvotes_tree.insert(&k, &[])?;
+        true
+    };
+    member_stats(&db, post.iid, post.uid, "upvote", is_upvoted)?;
+    if is_upvoted {
+        inn_event(&db, post.iid, "post_upvote")?;
     }
 
     let target = format!("/post/{iid}/{pid}");
@@ -1560,13 +4126,18 @@ pub(crate) async fn comment_upvote(
         &u32_to_ivec(claim.uid),
     ]
     .concat();
+    let post: Post = get_one(&db, "posts", pid)?;
+    check_archived(&db, post.iid)?;
 
     let comment_upvotes_tree = db.open_tree("comment_upvotes")?;
-    if comment_upvotes_tree.contains_key(&k)? {
+    let is_upvoted = if comment_upvotes_tree.contains_key(&k)? {
         comment_upvotes_tree.remove(&k)?;
+        false
     } else {
         comment_upvotes_tree.insert(&k, &[])?;
-    }
+        true
+    };
+    comment_vote_stats(&db, pid, cid, "upvote", is_upvoted)?;
 
     let target = format!("/post/{iid}/{pid}");
     Ok(Redirect::to(&target))
@@ -1583,12 +4154,20 @@ pub(crate) async fn post_downvote(
         .and_then(|cookie| Claim::get(&db, &cookie, &site_config))
         .ok_or(AppError::NonLogin)?;
 
+    let post: Post = get_one(&db, "posts", pid)?;
+    check_archived(&db, post.iid)?;
     let post_downvotes_tree = db.open_tree("post_downvotes")?;
     let k = [&u32_to_ivec(pid), &u32_to_ivec(claim.uid)].concat();
-    if post_downvotes_tree.contains_key(&k)? {
+    let is_downvoted = if post_downvotes_tree.contains_key(&k)? {
         post_downvotes_tree.remove(&k)?;
+        false
     } else {
         post_downvotes_tree.insert(&k, &[])?;
+        true
+    };
+    member_stats(&db, post.iid, post.uid, "downvote", is_downvoted)?;
+    if is_downvoted {
+        inn_event(&db, post.iid, "post_downvote")?;
     }
 
     let target = format!("/post/{iid}/{pid}");
@@ -1611,23 +4190,49 @@ pub(crate) async fn comment_downvote(
         &u32_to_ivec(claim.uid),
     ]
     .concat();
+    let post: Post = get_one(&db, "posts", pid)?;
+    check_archived(&db, post.iid)?;
 
     let comment_downvotes_tree = db.open_tree("comment_downvotes")?;
-    if comment_downvotes_tree.contains_key(&k)? {
+    let is_downvoted = if comment_downvotes_tree.contains_key(&k)? {
         comment_downvotes_tree.remove(&k)?;
+        false
     } else {
         comment_downvotes_tree.insert(&k, &[])?;
-    }
+        true
+    };
+    comment_vote_stats(&db, pid, cid, "downvote", is_downvoted)?;
 
     let target = format!("/post/{iid}/{pid}");
     Ok(Redirect::to(&target))
 }
 
+/// count the vote for the author of the comment, see [member_stats]
+fn comment_vote_stats(
+    db: &Db,
+    pid: u32,
+    cid: u32,
+    stat_type: &str,
+    is_add: bool,
+) -> Result<(), AppError> {
+    let post: Post = get_one(db, "posts", pid)?;
+    let k = [&u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
+    if let Some(v) = db.open_tree("post_comments")?.get(k)? {
+        let (comment, _): (Comment, usize) = bincode::decode_from_slice(&v, standard())?;
+        member_stats(db, post.iid, comment.uid, stat_type, is_add)?;
+        if is_add {
+            inn_event(db, post.iid, &format!("comment_{stat_type}"))?;
+        }
+    }
+    Ok(())
+}
+
 /// `GET /inn/:iid/:pid/post_lock` post lock
 pub(crate) async fn post_lock(
     State(db): State<Db>,
     cookie: Option<TypedHeader<Cookie>>,
     Path((iid, pid)): Path<(u32, u32)>,
+    Query(params): Query<ParamsReason>,
 ) -> Result<impl IntoResponse, AppError> {
     let site_config = get_site_config(&db)?;
     let claim = cookie
@@ -1640,13 +4245,31 @@ pub(crate) async fn post_lock(
     }
 
     let mut post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid {
+        return Err(AppError::NotFound);
+    }
+    check_archived(&db, iid)?;
     post.is_locked = !post.is_locked;
 
     let post_encoded = bincode::encode_to_vec(&post, standard())?;
     db.open_tree("posts")?
         .insert(u32_to_ivec(pid), post_encoded)?;
 
+    let action = if post.is_locked {
+        "lock post"
+    } else {
+        "unlock post"
+    };
     let target = format!("/post/{iid}/{pid}");
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        action.into(),
+        target.clone(),
+        params.reason.unwrap_or_default(),
+    )?;
+
     Ok(Redirect::to(&target))
 }
 
@@ -1655,6 +4278,7 @@ pub(crate) async fn post_hide(
     State(db): State<Db>,
     cookie: Option<TypedHeader<Cookie>>,
     Path((iid, pid)): Path<(u32, u32)>,
+    Query(params): Query<ParamsReason>,
 ) -> Result<impl IntoResponse, AppError> {
     let site_config = get_site_config(&db)?;
     let claim = cookie
@@ -1667,12 +4291,360 @@ pub(crate) async fn post_hide(
     }
 
     let mut post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid {
+        return Err(AppError::NotFound);
+    }
+    check_archived(&db, iid)?;
     post.is_hidden = !post.is_hidden;
 
     let post_encoded = bincode::encode_to_vec(&post, standard())?;
     db.open_tree("posts")?
         .insert(u32_to_ivec(pid), post_encoded)?;
 
+    let action = if post.is_hidden {
+        "hide post"
+    } else {
+        "open post"
+    };
     let target = format!("/post/{iid}/{pid}");
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        action.into(),
+        target.clone(),
+        params.reason.unwrap_or_default(),
+    )?;
+
     Ok(Redirect::to(&target))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controller::tests::{body_string, new_post};
+
+    /// the next page of `page`, fetched by its last cursor
+    fn next_page(page_params: &mut ParamsPage, page: &PidsPage) {
+        page_params.anchor = 0;
+        page_params.cursor = decode_cursor(Some(&page.2));
+    }
+
+    #[test]
+    fn test_get_pids_by_iids() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("post_timeline").unwrap();
+        // (timestamp, iid, pid)
+        for (timestamp, iid, pid) in [(10, 1, 1), (20, 2, 2), (30, 1, 3), (40, 1, 4), (50, 3, 5)] {
+            let k = [u32_to_ivec(timestamp), u32_to_ivec(iid), u32_to_ivec(pid)].concat();
+            tree.insert(k, u32_to_ivec(0)).unwrap();
+        }
+
+        let mut page_params = ParamsPage {
+            anchor: 0,
+            n: 2,
+            is_desc: true,
+            cursor: None,
+        };
+        let page = get_pids_by_iids(&db, &[1, 2], &page_params).unwrap();
+        assert_eq!(page.0, [4, 3]);
+        next_page(&mut page_params, &page);
+        let page = get_pids_by_iids(&db, &[1, 2], &page_params).unwrap();
+        assert_eq!(page.0, [2, 1]);
+        next_page(&mut page_params, &page);
+        assert!(get_pids_by_iids(&db, &[1, 2], &page_params)
+            .unwrap()
+            .0
+            .is_empty());
+
+        // a new post does not shift the pages after the cursor
+        page_params.is_desc = false;
+        page_params.cursor = None;
+        let page = get_pids_by_iids(&db, &[1, 2], &page_params).unwrap();
+        assert_eq!(page.0, [1, 2]);
+        let k = [u32_to_ivec(5), u32_to_ivec(1), u32_to_ivec(6)].concat();
+        tree.insert(k, u32_to_ivec(0)).unwrap();
+        next_page(&mut page_params, &page);
+        assert_eq!(
+            get_pids_by_iids(&db, &[1, 2], &page_params).unwrap().0,
+            [3, 4]
+        );
+    }
+
+    #[test]
+    fn test_get_pids_unanswered() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("post_timeline_idx").unwrap();
+        // (iid, pid, timestamp)
+        for (iid, pid, timestamp) in [(1, 1, 30), (1, 2, 10), (1, 3, 20), (1, 4, 40), (2, 5, 50)] {
+            let k = [u32_to_ivec(iid), u32_to_ivec(pid)].concat();
+            tree.insert(k, u32_to_ivec(timestamp)).unwrap();
+        }
+        let post_accepted_tree = db.open_tree("post_accepted").unwrap();
+        post_accepted_tree
+            .insert(u32_to_ivec(4), u32_to_ivec(1))
+            .unwrap();
+        assert_eq!(get_accepted_cid(&db, 4).unwrap(), Some(1));
+        assert_eq!(get_accepted_cid(&db, 1).unwrap(), None);
+
+        let mut page_params = ParamsPage {
+            anchor: 0,
+            n: 2,
+            is_desc: true,
+            cursor: None,
+        };
+        let page = get_pids_unanswered(&db, 1, &page_params).unwrap();
+        assert_eq!(page.0, [1, 3]);
+        next_page(&mut page_params, &page);
+        assert_eq!(get_pids_unanswered(&db, 1, &page_params).unwrap().0, [2]);
+
+        // unaccepting puts the question back
+        post_accepted_tree.remove(u32_to_ivec(4)).unwrap();
+        page_params.cursor = None;
+        assert_eq!(get_pids_unanswered(&db, 1, &page_params).unwrap().0, [4, 1]);
+    }
+
+    #[test]
+    fn test_get_pids_by_tags() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let posts_tree = db.open_tree("posts").unwrap();
+        // (pid, iid), inn 3 is private
+        for (pid, iid) in [(1, 1), (2, 1), (3, 2), (4, 2), (5, 3), (6, 2)] {
+            let post_encoded = bincode::encode_to_vec(new_post(pid, iid, ""), standard()).unwrap();
+            posts_tree.insert(u32_to_ivec(pid), post_encoded).unwrap();
+        }
+        db.open_tree("inns_private")
+            .unwrap()
+            .insert(u32_to_ivec(3), &[])
+            .unwrap();
+
+        // user 1 follows the tag `rust` and the topic `web` of inn 2
+        let uid_ivec = u32_to_ivec(1);
+        db.open_tree("user_tags")
+            .unwrap()
+            .insert([&uid_ivec, "rust".as_bytes()].concat(), &[])
+            .unwrap();
+        db.open_tree("user_topics")
+            .unwrap()
+            .insert([&uid_ivec, "web".as_bytes()].concat(), &[])
+            .unwrap();
+        db.open_tree("topics")
+            .unwrap()
+            .insert([b"web", &u32_to_ivec(2)[..]].concat(), &[])
+            .unwrap();
+        let tags_tree = db.open_tree("tags").unwrap();
+        for (tag, pid) in [("rust", 1), ("rust", 3), ("rust", 5), ("rusty", 2)] {
+            tags_tree
+                .insert([tag.as_bytes(), &u32_to_ivec(pid)].concat(), &[])
+                .unwrap();
+        }
+        let inn_posts_tree = db.open_tree("inn_posts").unwrap();
+        for pid in [3, 4, 6] {
+            inn_posts_tree
+                .insert([u32_to_ivec(2), u32_to_ivec(pid)].concat(), &[])
+                .unwrap();
+        }
+
+        // post 3 is in both, post 5 is private and post 2 is of another tag
+        let mut page_params = ParamsPage {
+            anchor: 0,
+            n: 2,
+            is_desc: true,
+            cursor: None,
+        };
+        let page = get_pids_by_tags(&db, 1, &[], &page_params).unwrap();
+        assert_eq!(page.0, [6, 4]);
+        next_page(&mut page_params, &page);
+        let page = get_pids_by_tags(&db, 1, &[], &page_params).unwrap();
+        assert_eq!(page.0, [3, 1]);
+
+        page_params.is_desc = false;
+        page_params.cursor = None;
+        let page = get_pids_by_tags(&db, 1, &[3], &page_params).unwrap();
+        assert_eq!(page.0, [1, 3]);
+        next_page(&mut page_params, &page);
+        let page = get_pids_by_tags(&db, 1, &[3], &page_params).unwrap();
+        assert_eq!(page.0, [4, 5]);
+    }
+
+    #[tokio::test]
+    async fn test_feed_render() {
+        let out_feed = || OutFeed {
+            title: "inn".into(),
+            subtitle: "about".into(),
+            link: "https://example.com/inn/1".into(),
+            self_link: "https://example.com/inn/1/feed".into(),
+            next_cursor: Some("0a".into()),
+            entries: [(2, 200), (1, 100)]
+                .into_iter()
+                .map(|(pid, created_at)| FeedEntry {
+                    link: format!("https://example.com/post/1/{pid}"),
+                    title: format!("post {pid}"),
+                    author: "alice".into(),
+                    author_link: "https://example.com/user/1".into(),
+                    tags: vec!["rust".into()],
+                    content: "<p>hi</p>".into(),
+                    created_at,
+                })
+                .collect(),
+        };
+
+        let atom = body_string(out_feed().render(None)).await;
+        let feed: atom_syndication::Feed = atom.parse().unwrap();
+        assert_eq!(feed.id, "https://example.com/inn/1/feed");
+        assert_eq!(feed.updated.timestamp(), 200);
+        let next = feed.links.iter().find(|link| link.rel == "next").unwrap();
+        assert_eq!(next.href, "https://example.com/inn/1/feed?cursor=0a");
+        let entry = &feed.entries[0];
+        assert_eq!(entry.id, "https://example.com/post/1/2");
+        assert_eq!(entry.published.unwrap().timestamp(), 200);
+        assert_eq!(
+            entry.authors[0].uri.as_deref(),
+            Some("https://example.com/user/1")
+        );
+        assert_eq!(entry.categories[0].term, "rust");
+        assert_eq!(
+            entry.content.as_ref().unwrap().value.as_deref(),
+            Some("<p>hi</p>")
+        );
+
+        let rss = body_string(out_feed().render(Some("rss"))).await;
+        let channel: rss::Channel = rss.parse().unwrap();
+        assert_eq!(channel.items.len(), 2);
+        assert_eq!(
+            channel.items[1].link.as_deref(),
+            Some("https://example.com/post/1/1")
+        );
+        let next = &channel.extensions["atom"]["link"][0];
+        assert_eq!(
+            next.attrs["href"],
+            "https://example.com/inn/1/feed?cursor=0a&format=rss"
+        );
+    }
+
+    #[test]
+    fn test_inn_config() {
+        let mut site_config = SiteConfig::default();
+        let (comment_interval, per_page) = (site_config.comment_interval, site_config.per_page);
+        let inn_config = InnConfig {
+            post_interval: 600,
+            title_max_length: 10,
+            post_role: 1,
+            comment_role: 1,
+            slow_mode: 60,
+            ..Default::default()
+        };
+        inn_config.apply(&mut site_config);
+        assert_eq!(site_config.post_interval, 600);
+        assert_eq!(site_config.title_max_length, 10);
+        assert_eq!(site_config.comment_interval, comment_interval);
+        assert_eq!(site_config.per_page, per_page);
+
+        // the roles can't go below Intern to post and Limited to comment
+        assert_eq!(inn_config.post_role(), 4);
+        assert_eq!(inn_config.comment_role(), 3);
+        assert_eq!(inn_config.wiki_role(), 5);
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        assert!(inn_config.check_slow_mode(&db, 1, 2, 5).is_ok());
+        assert!(matches!(
+            inn_config.check_slow_mode(&db, 1, 2, 5),
+            Err(AppError::WriteInterval)
+        ));
+        // other users, other inns and mods are not limited
+        assert!(inn_config.check_slow_mode(&db, 1, 3, 5).is_ok());
+        assert!(inn_config.check_slow_mode(&db, 2, 2, 5).is_ok());
+        assert!(inn_config.check_slow_mode(&db, 1, 2, 8).is_ok());
+
+        let k = [&u32_to_ivec(1), &u32_to_ivec(2)].concat();
+        let last_write = Utc::now().timestamp() - 60;
+        db.open_tree("inn_slow_mode")
+            .unwrap()
+            .insert(k, u32_to_ivec(last_write as u32))
+            .unwrap();
+        assert!(inn_config.check_slow_mode(&db, 1, 2, 5).is_ok());
+
+        let inn_config = InnConfig::default();
+        assert!(inn_config.check_slow_mode(&db, 1, 2, 5).is_ok());
+        assert!(inn_config.check_slow_mode(&db, 1, 2, 5).is_ok());
+    }
+
+    #[test]
+    fn test_save_draft() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let form = |title: &str, draft_id| FormPost {
+            title: title.into(),
+            draft_id,
+            ..Default::default()
+        };
+        let did = save_draft(&db, 1, form("v0", None)).unwrap();
+
+        let drafts_tree = db.open_tree("drafts").unwrap();
+        let k = [&u32_to_ivec(1), &u32_to_ivec(did)].concat();
+        for i in 1..=7 {
+            // the versions are kept by the time they were saved, one per second
+            let mut draft = get_draft(&db, 1, did).unwrap();
+            draft.updated_at = i;
+            let draft_encoded = bincode::encode_to_vec(&draft, standard()).unwrap();
+            drafts_tree.insert(&k, draft_encoded).unwrap();
+
+            let title = format!("v{i}");
+            assert_eq!(save_draft(&db, 1, form(&title, Some(did))).unwrap(), did);
+        }
+        assert_eq!(get_draft(&db, 1, did).unwrap().post.title, "v7");
+
+        let history: Vec<String> = db
+            .open_tree("draft_history")
+            .unwrap()
+            .scan_prefix(u32_to_ivec(did))
+            .values()
+            .map(|v| {
+                let (draft, _): (Draft, usize) =
+                    bincode::decode_from_slice(&v.unwrap(), standard()).unwrap();
+                draft.post.title
+            })
+            .collect();
+        assert_eq!(history.len(), DRAFT_HISTORY_MAX);
+        assert_eq!(history, ["v2", "v3", "v4", "v5", "v6"]);
+
+        // a draft of another user is not overwritten
+        assert_ne!(save_draft(&db, 2, form("v0", Some(did))).unwrap(), did);
+        assert_eq!(get_draft(&db, 1, did).unwrap().post.title, "v7");
+    }
+
+    #[test]
+    fn test_count_view() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let ua = Some("Mozilla/5.0");
+
+        // the first request only issues the cookie
+        let (pageview, cookie) = count_view(&db, 1, 2, None, None, ua).unwrap();
+        assert_eq!(pageview, 0);
+        let cookie = cookie.unwrap();
+        let viewer_id = &cookie[VIEWER_COOKIE.len() + 1..cookie.find(';').unwrap()];
+
+        // the view is counted when the cookie comes back, and only once a day
+        assert_eq!(
+            count_view(&db, 1, 2, None, Some(viewer_id), ua).unwrap(),
+            (1, None)
+        );
+        assert_eq!(
+            count_view(&db, 1, 2, None, Some(viewer_id), ua).unwrap(),
+            (1, None)
+        );
+        assert_eq!(
+            count_view(&db, 3, 2, None, Some(viewer_id), ua).unwrap(),
+            (1, None)
+        );
+
+        // made up ids are not counted
+        let (pageview, cookie) =
+            count_view(&db, 1, 2, None, Some("aaaaaaaaaaaaaaaaaaaaa"), ua).unwrap();
+        assert_eq!(pageview, 1);
+        assert!(cookie.is_some());
+        assert_eq!(
+            count_view(&db, 1, 2, None, None, Some("curl/8.0")).unwrap(),
+            (1, None)
+        );
+    }
+}
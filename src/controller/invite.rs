@@ -0,0 +1,296 @@
+//! ## Invites
+//!
+//! Mods of an inn create invite links to let users join without applying. An invite grants the
+//! role of Limited, Intern or Fellow, and it can be limited by the max uses and the expiry.
+//! Users denied by mods can't join by invite, and the links of an archived inn can't be used.
+
+use super::{
+    get_inn_role, get_one, get_site_config, inn::check_archived, inn_event, into_response, is_mod,
+    mod_log, timestamp_to_date, u32_to_ivec, user::expire_seconds, Claim, Inn, InnType, Invite,
+    PageData, User,
+};
+use crate::error::AppError;
+use askama::Template;
+use axum::{
+    extract::{Form, Path, State, TypedHeader},
+    headers::Cookie,
+    response::{IntoResponse, Redirect},
+};
+use bincode::config::standard;
+use chrono::Utc;
+use nanoid::nanoid;
+use serde::Deserialize;
+use sled::Db;
+
+/// Vec data: invite
+struct OutInvite {
+    code: String,
+    uid: u32,
+    username: String,
+    role: &'static str,
+    uses: u32,
+    max_uses: u32,
+    expires_at: String,
+    created_at: String,
+    is_valid: bool,
+}
+
+/// Page data: `mod_invites.html`
+#[derive(Template)]
+#[template(path = "mod_invites.html")]
+struct PageModInvites<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    domain: String,
+    invites: Vec<OutInvite>,
+}
+
+impl Invite {
+    fn is_valid(&self, now: i64) -> bool {
+        !matches!(self.expires_at, Some(expires_at) if expires_at <= now)
+            && (self.max_uses == 0 || self.uses < self.max_uses)
+    }
+}
+
+/// `GET /mod/:iid/invites` invite links of the inn
+pub(crate) async fn mod_invites(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+    let inn: Inn = get_one(&db, "inns", iid)?;
+
+    let now = Utc::now().timestamp();
+    let mut invites = Vec::new();
+    // kv_pair: iid#code = Invite
+    for i in db.open_tree("inn_invites")?.scan_prefix(u32_to_ivec(iid)) {
+        let (_, v) = i?;
+        let (invite, _): (Invite, usize) = bincode::decode_from_slice(&v, standard())?;
+        let user: User = get_one(&db, "users", invite.uid)?;
+        let role = match invite.role {
+            3 => "Limited",
+            4 => "Intern",
+            _ => "Fellow",
+        };
+        invites.push(OutInvite {
+            is_valid: invite.is_valid(now),
+            code: invite.code,
+            uid: invite.uid,
+            username: user.username,
+            role,
+            uses: invite.uses,
+            max_uses: invite.max_uses,
+            expires_at: invite
+                .expires_at
+                .map(timestamp_to_date)
+                .unwrap_or_else(|| "Never".to_owned()),
+            created_at: timestamp_to_date(invite.created_at),
+        });
+    }
+    invites.sort_unstable_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    let page_data = PageData::new("invites", &site_config, Some(claim), false);
+    let page_mod_invites = PageModInvites {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        domain: site_config.domain.trim_end_matches('/').to_owned(),
+        invites,
+    };
+
+    Ok(into_response(&page_mod_invites, "html"))
+}
+
+/// Form data: `/mod/:iid/invites` create invite link
+#[derive(Deserialize)]
+pub(crate) struct FormInvite {
+    role: String,
+    max_uses: u32,
+    expiry: Option<String>,
+}
+
+/// `POST /mod/:iid/invites` create invite link
+pub(crate) async fn mod_invites_post(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+    Form(form): Form<FormInvite>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+    check_archived(&db, iid)?;
+
+    let role = match form.role.as_str() {
+        "Limited" => 3,
+        "Intern" => 4,
+        "Fellow" => 5,
+        _ => return Err(AppError::InvalidRole),
+    };
+    let now = Utc::now().timestamp();
+    let expires_at = match form.expiry.filter(|e| !e.is_empty()) {
+        Some(expiry) => Some(now + expire_seconds(&expiry).ok_or(AppError::InvalidExpiry)?),
+        None => None,
+    };
+
+    let invite = Invite {
+        code: nanoid!(16),
+        iid,
+        uid: claim.uid,
+        role,
+        max_uses: form.max_uses,
+        uses: 0,
+        expires_at,
+        created_at: now,
+    };
+    let k = [&u32_to_ivec(iid), invite.code.as_bytes()].concat();
+    let invite_encoded = bincode::encode_to_vec(&invite, standard())?;
+    db.open_tree("inn_invites")?.insert(k, invite_encoded)?;
+
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        format!("create invite {}", invite.code),
+        format!("/inn/{iid}"),
+        String::new(),
+    )?;
+
+    let target = format!("/mod/{iid}/invites");
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /mod/:iid/invites/:code/revoke` revoke invite link
+pub(crate) async fn invite_revoke(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, code)): Path<(u32, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+
+    let k = [&u32_to_ivec(iid), code.as_bytes()].concat();
+    if db.open_tree("inn_invites")?.remove(&k)?.is_some() {
+        mod_log(
+            &db,
+            iid,
+            claim.uid,
+            format!("revoke invite {code}"),
+            format!("/inn/{iid}"),
+            String::new(),
+        )?;
+    }
+
+    let target = format!("/mod/{iid}/invites");
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /inn/:iid/invite/:code` join the inn by invite link
+///
+/// Members are redirected to the inn, users denied by mods can't join.
+pub(crate) async fn invite(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, code)): Path<(u32, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let k = [&u32_to_ivec(iid), code.as_bytes()].concat();
+    let inn_invites_tree = db.open_tree("inn_invites")?;
+    let v = inn_invites_tree.get(&k)?.ok_or(AppError::NotFound)?;
+    let (mut invite, _): (Invite, usize) = bincode::decode_from_slice(&v, standard())?;
+
+    let target = format!("/inn/{iid}");
+    match get_inn_role(&db, iid, claim.uid)? {
+        Some(role) if role >= 3 => return Ok(Redirect::to(&target)),
+        Some(2) => return Err(AppError::Unauthorized),
+        _ => {}
+    }
+
+    let now = Utc::now().timestamp();
+    if !invite.is_valid(now) {
+        return Err(AppError::InvalidInvite);
+    }
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if inn.inn_type == InnType::Archive {
+        return Err(AppError::InnArchived);
+    }
+
+    let user_inns_k = [&u32_to_ivec(claim.uid), &u32_to_ivec(iid)].concat();
+    let inn_users_k = [&u32_to_ivec(iid), &u32_to_ivec(claim.uid)].concat();
+    db.open_tree("user_inns")?.insert(&user_inns_k, &[])?;
+    inn_event(&db, iid, "join")?;
+    db.open_tree("inn_joined")?
+        .insert(&inn_users_k, u32_to_ivec(now as u32))?;
+    db.open_tree("inn_users")?
+        .insert(&inn_users_k, &[invite.role])?;
+    db.open_tree("inn_apply")?.remove(&inn_users_k)?;
+
+    invite.uses += 1;
+    let invite_encoded = bincode::encode_to_vec(&invite, standard())?;
+    inn_invites_tree.insert(&k, invite_encoded)?;
+    db.open_tree("invite_uses")?.insert(
+        [&k[..], &u32_to_ivec(claim.uid)].concat(),
+        u32_to_ivec(now as u32),
+    )?;
+
+    // the mod who created the invite lets the user in
+    mod_log(
+        &db,
+        iid,
+        invite.uid,
+        format!("invite {code}"),
+        format!("/user/{}", claim.uid),
+        String::new(),
+    )?;
+
+    Ok(Redirect::to(&target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_invite_is_valid() {
+        let mut invite = Invite {
+            code: "code".into(),
+            iid: 1,
+            uid: 1,
+            role: 4,
+            max_uses: 0,
+            uses: 100,
+            expires_at: None,
+            created_at: 0,
+        };
+        // no limits
+        assert!(invite.is_valid(1000));
+
+        invite.max_uses = 2;
+        invite.uses = 1;
+        assert!(invite.is_valid(1000));
+        invite.uses = 2;
+        assert!(!invite.is_valid(1000));
+
+        invite.uses = 0;
+        invite.expires_at = Some(1000);
+        assert!(invite.is_valid(999));
+        assert!(!invite.is_valid(1000));
+    }
+}
@@ -4,20 +4,26 @@
 //! to record the total number (we use **N** to refer this kind of value, and their type is [u32]).
 //!
 //! ### user
-//! | tree             | key                  | value      | set       | get                   |
-//! |------------------|----------------------|------------|-----------|-----------------------|
-//! | default          | "users_count"        | N          | [incr_id] | [get_count]           |
-//! | "users"          | `uid`                | [`User`]   |           | [get_one]/[get_batch] |
-//! | "usernames"      | `username`           | `uid`      |           | [get_uid_by_name]     |
-//! | "user_following" | `uid#uid`            | `&[]`      |           | [get_ids_by_prefix]   |
-//! | "user_followers" | `uid#uid`            | `&[]`      |           | [get_ids_by_prefix]   |
-//! | "user_stats"     | `timestamp_uid_type` | N          |           |                       |
-//! | "user_uploads"   | `uid#image_hash.ext` | `&[]`      |           |                       |
+//! | tree               | key                  | value           | set       | get                   |
+//! |--------------------|----------------------|-----------------|-----------|-----------------------|
+//! | default            | "users_count"        | N               | [incr_id] | [get_count]           |
+//! | "users"            | `uid`                | [`User`]        |           | [get_one]/[get_batch] |
+//! | "usernames"        | `username`           | `uid`           |           | [get_uid_by_name]     |
+//! | "user_following"   | `uid#uid`            | `&[]`           |           | [get_ids_by_prefix]   |
+//! | "user_followers"   | `uid#uid`            | `&[]`           |           | [get_ids_by_prefix]   |
+//! | "user_stats"       | `timestamp_uid_type` | N               |           |                       |
+//! | "user_uploads"     | `uid#image_hash.ext` | `&[]`           |           |                       |
+//! | "user_saved"       | `uid#pid#cid`        | `iid#timestamp` |           |                       |
+//! | "feed_tokens"      | `token`              | `uid`           |           |                       |
+//! | "user_feed_tokens" | `uid`                | `token`         |           |                       |
 //!
 //! ### notification
-//! | tree            | key           | value                             |
-//! |-----------------|---------------|-----------------------------------|
-//! | "notifications" | `uid#pid#cid` | [notification_code][Notification] |
+//! | tree                   | key           | value                             |
+//! |------------------------|---------------|-----------------------------------|
+//! | "notifications"        | `uid#pid#cid` | [notification_code][Notification] |
+//! | "post_subscribers"     | `pid#uid`     | `&[]`                             |
+//! | "thread_notifications" | `uid#pid`     | `count#last_cid`                  |
+//! | "auto_subscribe_off"   | `uid`         | `&[]`                             |
 //!
 //! ### captcha
 //! About key `timestamp_nanoid`, see [generate_nanoid_expire].
@@ -50,41 +56,83 @@
 //! | default   | "site_config" | [`SiteConfig`] |           | [get_site_config] |
 //!
 //! ### inn
-//! | tree            | key           | value               |
-//! |-----------------|---------------|---------------------|
-//! | default         | "inns_count"  | N                   |
-//! | "inns"          | `iid`         | [`Inn`]             |
-//! | "inn_names"     | `inn_name`    | `iid`               |
-//! | "topics"        | `topic#iid`   | `&[]`               |
-//! | "mod_inns"      | `uid#iid`     | `&[]`               |
-//! | "user_inns"     | `uid#iid`     | `&[]`               |
-//! | "inn_apply"     | `iid#uid`     | `&[]`               |
-//! | "inn_users"     | `iid#uid`     | `&[1/2/3/4/5/8/10]` |
-//! | "inns_private"  | `iid`         | `&[]`               |
-//! | "drafts"        | `uid`         | [`FormPost`]        |
+//! | tree               | key                  | value               |
+//! |--------------------|----------------------|---------------------|
+//! | default            | "inns_count"         | N                   |
+//! | "inns"             | `iid`                | [`Inn`]             |
+//! | "inn_names"        | `inn_name`           | `iid`               |
+//! | "topics"           | `topic#iid`          | `&[]`               |
+//! | "user_topics"      | `uid#topic`          | `&[]`               |
+//! | "mod_inns"         | `uid#iid`            | `&[]`               |
+//! | "user_inns"        | `uid#iid`            | `&[]`               |
+//! | "inn_apply"        | `iid#uid`            | `&[]`               |
+//! | "inn_users"        | `iid#uid`            | `&[1/2/3/4/5/8/10]` |
+//! | "inns_private"     | `iid`                | `&[]`               |
+//! | "role_changes"     | `iid#uid`            | [`RoleChange`]      |
+//! | "role_expiry"      | `timestamp#iid#uid`  | `&[]`               |
+//! | "inn_joined"       | `iid#uid`            | `timestamp`         |
+//! | "inn_events"       | `iid#yyyymmdd#event` | N                   |
+//! | "inn_contributors" | `iid#yyyymmdd#uid`   | N                   |
+//! | "inn_invites"      | `iid#code`           | [`Invite`]          |
+//! | "invite_uses"      | `iid#code#uid`       | `timestamp`         |
+//! | "inn_configs"      | `iid`                | [`InnConfig`]       |
+//! | "inn_slow_mode"    | `iid#uid`            | `timestamp`         |
+//! | "member_stats"     | `iid#uid#type`       | N                   |
+//! | default            | "drafts_count"       | N                   |
+//! | "drafts"           | `uid#did`            | [`Draft`]           |
+//! | "draft_history"    | `did#timestamp`      | [`Draft`]           |
+//! | default            | "collections_count"  | N                   |
+//! | "collections"      | `id`                 | [`Collection`]      |
+//! | "user_collections" | `uid#id`             | `&[]`               |
+//!
+//! ### wiki
+//! | tree           | key          | value        |
+//! |----------------|--------------|--------------|
+//! | default        | "wiki_count" | N            |
+//! | "wiki_pages"   | `wid`        | [`WikiPage`] |
+//! | "inn_wiki"     | `iid#title`  | `wid`        |
+//! | "wiki_history" | `wid#rev`    | [`WikiPage`] |
 //!
 //! ### post
-//! | tree                | key                 | value                |
-//! |-------------------- |---------------------|----------------------|
-//! | default             | "posts_count"       | N                    |
-//! | "posts"             | `pid`               | [`Post`]             |
-//! | "inn_posts"         | `iid#pid`           | `&[]`                |
-//! | "user_posts"        | `uid#pid`           | `iid#visibility`     |
-//! | "tags"              | `tag#pid`           | `&[]`                |
-//! | "post_upvotes"      | `pid#uid`           | `&[]`                |
-//! | "post_downvotes"    | `pid#uid`           | `&[]`                |
-//! | "post_timeline_idx" | `iid#pid`           | `timestamp`          |
-//! | "post_timeline"     | `timestamp#iid#pid` | `visibility`         |
-//! | "post_pageviews"    | `pid`               | N                    |
+//! | tree                   | key                    | value              |
+//! |------------------------|------------------------|--------------------|
+//! | default                | "posts_count"          | N                  |
+//! | "posts"                | `pid`                  | [`Post`]           |
+//! | "inn_posts"            | `iid#pid`              | `&[]`              |
+//! | "user_posts"           | `uid#pid`              | `iid#visibility`   |
+//! | "tags"                 | `tag#pid`              | `&[]`              |
+//! | "user_tags"            | `uid#tag`              | `&[]`              |
+//! | "post_upvotes"         | `pid#uid`              | `&[]`              |
+//! | "post_downvotes"       | `pid#uid`              | `&[]`              |
+//! | "post_timeline_idx"    | `iid#pid`              | `timestamp`        |
+//! | "post_timeline"        | `timestamp#iid#pid`    | `visibility`       |
+//! | "post_pageviews"       | `pid`                  | N                  |
+//! | "post_pageviews_daily" | `pid#yyyymmdd`         | N                  |
+//! | "post_viewers"         | `timestamp_pid_viewer` | `&[]`              |
+//! | "viewer_ids"           | `timestamp_viewer`     | `&[]`              |
+//! | default                | "related_refreshed_at" | `timestamp`        |
+//! | "post_related"         | `pid`                  | `pid#pid#..`       |
+//! | "post_edited"          | `pid`                  | `&[]`              |
+//! | "post_pending"         | `iid#pid`              | `item_id` or `&[]` |
+//! | "polls"                | `pid`                  | [`Poll`]           |
+//! | "poll_votes"           | `pid#uid`              | `&[option..]`      |
+//! | "events"               | `pid`                  | [`Event`]          |
+//! | "inn_calendar"         | `iid#start#pid`        | `&[]`              |
+//! | "event_rsvps"          | `pid#uid`              | `&[1/2/3]`         |
+//! | "post_links"           | `link`                 | `pid#pid#..`       |
+//! | "post_url"             | `pid`                  | `link`             |
 //!
 //! ### comment
-//! | tree                  | key                  | value       |
-//! |-----------------------|----------------------|-------------|
-//! | "post_comments_count" | `pid`                | N           |
-//! | "post_comments"       | `pid#cid`            | [`Comment`] |
-//! | "user_comments"       | `uid#pid#cid`        | `&[]`       |
-//! | "comment_upvotes"     | `pid#cid#uid`        | `&[]`       |
-//! | "comment_downvotes"   | `pid#cid#uid`        | `&[]`       |
+//! | tree                  | key                     | value              |
+//! |-----------------------|-------------------------|--------------------|
+//! | default               | "comment_pending_count" | N                  |
+//! | "post_comments_count" | `pid`                   | N                  |
+//! | "post_comments"       | `pid#cid`               | [`Comment`]        |
+//! | "user_comments"       | `uid#pid#cid`           | `&[]`              |
+//! | "comment_upvotes"     | `pid#cid#uid`           | `&[]`              |
+//! | "comment_downvotes"   | `pid#cid#uid`           | `&[]`              |
+//! | "comment_pending"     | `iid#pid#id`            | [`PendingComment`] |
+//! | "post_accepted"       | `pid`                   | `cid`              |
 //!
 //! ### rss
 //! | tree                  | key                  | value       |
@@ -100,6 +148,19 @@
 //! | "items"               | `item_id`            | [`Item`]    |
 //! | "read"                | `uid#item_id`        | `&[]`       |
 //! | "star"                | `uid#item_id`        | `timestamp` |
+//! | "item_posts"          | `item_id#pid`        | `&[]`       |
+//! | "item_solos"          | `item_id#sid`        | `&[]`       |
+//! | "inn_feeds"           | `iid#feed_id`        | [`InnFeed`] |
+//! | "inn_feed_items"      | `iid#item_id`        | `pid`       |
+//!
+//! ### mod log
+//! Site-wide actions are logged with iid 0.
+//!
+//! | tree           | key              | value      |
+//! |----------------|------------------|------------|
+//! | default        | "mod_logs_count" | N          |
+//! | "mod_logs"     | `lid`            | [`ModLog`] |
+//! | "inn_mod_logs" | `iid#lid`        | `&[]`      |
 
 /// user
 ///
@@ -108,6 +169,7 @@
 /// |----------|------|:---------:|:---------:|:----------:|
 /// | Banned   | 0    |           |           |            |
 /// | Standard | 10   | ✅        |           |            |
+/// | Bot      | 20   | ✅        |           |            |
 /// | Senior   | 100  | ✅        | ✅        |            |
 /// | Admin    | 255  | ✅        | ✅        | ✅         |
 
@@ -149,11 +211,120 @@ struct Inn {
     about: String,
     description: String,
     topics: Vec<String>,
-    inn_type: String,
+    inn_type: InnType,
     early_birds: u32,
     created_at: i64,
 }
 
+/// The type of an inn. See [inn](inn/index.html) for who can join, post and comment.
+#[derive(Encode, Decode, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+enum InnType {
+    Public,
+    Apply,
+    Private,
+    Announcement,
+    Restricted,
+    Archive,
+}
+
+impl InnType {
+    fn as_str(&self) -> &'static str {
+        match self {
+            InnType::Public => "Public",
+            InnType::Apply => "Apply",
+            InnType::Private => "Private",
+            InnType::Announcement => "Announcement",
+            InnType::Restricted => "Restricted",
+            InnType::Archive => "Archive",
+        }
+    }
+
+    /// whether users must apply and wait for mods to approve
+    fn need_apply(&self) -> bool {
+        matches!(self, InnType::Apply | InnType::Private)
+    }
+}
+
+/// The reason and the optional expiry of a role change. iid 0 is for site roles.
+///
+/// When it expires, the role will be restored to `old_role`.
+/// If `old_role` is None, the user will be removed from the inn.
+#[derive(Encode, Decode, Debug)]
+struct RoleChange {
+    old_role: Option<u8>,
+    reason: String,
+    expires_at: Option<i64>,
+}
+
+/// A named collection of inns, tags and users, browsed as a combined timeline.
+#[derive(Encode, Decode, Debug)]
+struct Collection {
+    id: u32,
+    uid: u32,
+    name: String,
+    iids: Vec<u32>,
+    tags: Vec<String>,
+    uids: Vec<u32>,
+    /// anyone with the link can see it
+    is_public: bool,
+    created_at: i64,
+}
+
+/// A wiki page of an inn. Every revision is also kept in "wiki_history".
+#[derive(Encode, Decode, Debug)]
+struct WikiPage {
+    wid: u32,
+    iid: u32,
+    title: String,
+    content: String,
+    /// the last editor
+    uid: u32,
+    rev: u32,
+    updated_at: i64,
+}
+
+/// Settings of an inn overriding the limits of [SiteConfig]. Zero means following the site config,
+/// and the config is not saved if it is the default.
+#[derive(Encode, Decode, Debug, Default, PartialEq)]
+struct InnConfig {
+    post_interval: i64,
+    comment_interval: i64,
+    title_max_length: usize,
+    article_max_length: usize,
+    per_page: usize,
+    /// the min inn role to post, at least Intern
+    post_role: u8,
+    /// the min inn role to comment, at least Limited
+    comment_role: u8,
+    /// seconds between two writes of the same user in the inn. Mods are not limited.
+    slow_mode: i64,
+    /// sort the inn page by the newest posts instead of the latest activity
+    sort_by_new: bool,
+    /// the min inn role to edit the wiki, Fellow by default
+    wiki_role: u8,
+    /// posts are questions and comments are answers, one of which can be accepted
+    qa_mode: bool,
+    /// everyone can see the mod log, not only mods
+    log_public: bool,
+    /// new posts from the roles below it are held for review. 0 means off.
+    premod_role: u8,
+    /// hold the comments from the roles below `premod_role` too
+    premod_comments: bool,
+    promotion_rule: PromotionRule,
+}
+
+/// Rule to promote Interns to Fellows automatically. Zero means no requirement,
+/// and the rule is not saved if all of them are zero.
+///
+/// See [promote_members](user/fn.promote_members.html).
+#[derive(Encode, Decode, Debug, Default, PartialEq)]
+struct PromotionRule {
+    posts: u32,
+    comments: u32,
+    days: u32,
+    upvotes: u32,
+}
+
 #[derive(Encode, Decode, Serialize, Debug)]
 struct Post {
     pid: u32,
@@ -168,7 +339,7 @@ struct Post {
 }
 
 /// Form data: `/inn/:iid/post/:pid` post create/edit page
-#[derive(Debug, Default, Deserialize, Validate, Encode, Decode)]
+#[derive(Debug, Default, Deserialize, Validate)]
 pub(crate) struct FormPost {
     iid: u32,
     #[validate(length(min = 1, max = 256))]
@@ -179,6 +350,116 @@ pub(crate) struct FormPost {
     content: String,
     is_draft: Option<bool>,
     delete_draft: Option<bool>,
+    draft_id: Option<u32>,
+    /// one option per line, no poll if empty
+    #[validate(length(max = 2048))]
+    poll_options: Option<String>,
+    poll_multiple: Option<bool>,
+    poll_anonymous: Option<bool>,
+    /// "1day", "1week".., never close if empty
+    poll_close: Option<String>,
+    /// `%Y-%m-%dT%H:%M` in UTC, no event if empty
+    event_start: Option<String>,
+    /// one hour after the start if empty
+    event_end: Option<String>,
+    #[validate(length(max = 256))]
+    event_location: Option<String>,
+    /// the url of a link post, no link if empty
+    #[validate(length(max = 1024))]
+    link: Option<String>,
+    /// post the link even if it has been posted in the inn
+    link_anyway: Option<bool>,
+    /// the feed item shared as the post
+    item_id: Option<u32>,
+}
+
+/// An event attached to a post. Only the author can create it with the post.
+#[derive(Encode, Decode, Debug)]
+struct Event {
+    pid: u32,
+    iid: u32,
+    start: i64,
+    end: i64,
+    location: String,
+}
+
+/// A poll attached to a post. Only the author can create it with the post.
+#[derive(Encode, Decode, Debug)]
+struct Poll {
+    pid: u32,
+    options: Vec<String>,
+    is_multiple: bool,
+    /// show the counts only, not the voters
+    is_anonymous: bool,
+    close_at: Option<i64>,
+}
+
+/// A draft of post. The previous versions are kept in `draft_history`.
+#[derive(Encode, Decode, Debug)]
+struct Draft {
+    did: u32,
+    post: DraftPost,
+    updated_at: i64,
+}
+
+/// The post saved in a [Draft]. It has its own layout, so new fields of [FormPost]
+/// do not break the saved drafts.
+#[derive(Encode, Decode, Debug, Default)]
+struct DraftPost {
+    iid: u32,
+    title: String,
+    tags: String,
+    content: String,
+    poll_options: Option<String>,
+    poll_multiple: Option<bool>,
+    poll_anonymous: Option<bool>,
+    poll_close: Option<String>,
+    event_start: Option<String>,
+    event_end: Option<String>,
+    event_location: Option<String>,
+    link: Option<String>,
+    item_id: Option<u32>,
+}
+
+impl From<FormPost> for DraftPost {
+    fn from(post: FormPost) -> Self {
+        DraftPost {
+            iid: post.iid,
+            title: post.title,
+            tags: post.tags,
+            content: post.content,
+            poll_options: post.poll_options,
+            poll_multiple: post.poll_multiple,
+            poll_anonymous: post.poll_anonymous,
+            poll_close: post.poll_close,
+            event_start: post.event_start,
+            event_end: post.event_end,
+            event_location: post.event_location,
+            link: post.link,
+            item_id: post.item_id,
+        }
+    }
+}
+
+impl From<DraftPost> for FormPost {
+    fn from(post: DraftPost) -> Self {
+        FormPost {
+            iid: post.iid,
+            title: post.title,
+            tags: post.tags,
+            content: post.content,
+            poll_options: post.poll_options,
+            poll_multiple: post.poll_multiple,
+            poll_anonymous: post.poll_anonymous,
+            poll_close: post.poll_close,
+            event_start: post.event_start,
+            event_end: post.event_end,
+            event_location: post.event_location,
+            link: post.link,
+            item_id: post.item_id,
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Encode, Decode, Serialize, Debug)]
@@ -192,12 +473,74 @@ struct Comment {
     is_hidden: bool,
 }
 
+/// A comment held for review. It gets its `cid` when approved,
+/// see [pending_action](inn/fn.pending_action.html).
+#[derive(Encode, Decode, Debug)]
+struct PendingComment {
+    comment: Comment,
+    /// the users mentioned in the comment, notified when approved
+    mentioned: Vec<u32>,
+}
+
+/// An append-only record of a moderation action.
+///
+/// `target` is the path of the affected object, such as `/post/1/2` or `/user/3`.
+#[derive(Encode, Decode, Debug)]
+struct ModLog {
+    lid: u32,
+    iid: u32,
+    uid: u32,
+    action: String,
+    target: String,
+    reason: String,
+    created_at: i64,
+}
+
+/// An invite link of an inn. Users who open it join the inn with `role` directly.
+#[derive(Encode, Decode, Debug)]
+struct Invite {
+    code: String,
+    iid: u32,
+    /// the mod who created it
+    uid: u32,
+    role: u8,
+    /// 0 means unlimited
+    max_uses: u32,
+    uses: u32,
+    expires_at: Option<i64>,
+    created_at: i64,
+}
+
 #[derive(Encode, Decode, Debug)]
 struct Feed {
     link: String,
     title: String,
 }
 
+/// A feed attached to an inn. New items are posted by the bot user in [feed::cron_feed].
+///
+/// The items already in the feed when it is attached are not posted.
+#[derive(Encode, Decode, Debug)]
+struct InnFeed {
+    iid: u32,
+    feed_id: u32,
+    /// the bot user who posts the items
+    uid: u32,
+    /// `{title}` and `{feed}` are replaced by the item title and the feed title
+    title_template: String,
+    /// separated by `#`, `{feed}` is replaced by the feed title
+    tags: String,
+    /// separated by `,`. Items should contain one of them if any, and none of the ones
+    /// starting with `-`
+    keywords: String,
+    /// 0 means no limit
+    max_per_day: u32,
+    /// `yyyymmdd` of the last post
+    posted_date: u32,
+    posted_today: u32,
+    created_at: i64,
+}
+
 #[derive(Encode, Decode, Debug)]
 struct Item {
     link: String,
@@ -310,7 +653,7 @@ use axum::{
 };
 use bincode::config::standard;
 use bincode::{Decode, Encode};
-use chrono::{DateTime, Days, NaiveDateTime, Utc};
+use chrono::{DateTime, Datelike, Days, NaiveDate, NaiveDateTime, Utc};
 use data_encoding::HEXLOWER;
 use image::{imageops::FilterType, ImageFormat};
 use img_parts::{DynImage, ImageEXIF};
@@ -321,7 +664,7 @@ use ring::digest::{Context, SHA1_FOR_LEGACY_USE_ONLY};
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use sled::{Batch, Db, IVec, Iter, Tree};
-use std::{cmp::Ordering, iter::Rev};
+use std::{cmp::Ordering, iter::Rev, ops::Bound};
 use tokio::{fs, signal};
 use tower_http::services::ServeDir;
 use tracing::error;
@@ -331,11 +674,17 @@ use validator::Validate;
 use self::utils::md2html;
 
 pub(super) mod admin;
+pub(super) mod collection;
+pub(super) mod event;
 pub(super) mod feed;
 pub(super) mod inn;
+pub(super) mod invite;
+pub(super) mod pending;
+pub(super) mod poll;
 pub(super) mod solo;
 pub(super) mod user;
 pub(super) mod utils;
+pub(super) mod wiki;
 
 fn into_response<T: Template>(t: &T, ext: &str) -> Response<BoxBody> {
     match t.render() {
@@ -373,12 +722,24 @@ impl IntoResponse for AppError {
             | AppError::ReadOnly
             | AppError::ValidationError(_)
             | AppError::NoJoinedInn
+            | AppError::InvalidInvite
+            | AppError::ContentTooLong
+            | AppError::InnArchived
+            | AppError::WikiTitleInvalid
+            | AppError::InvalidPoll
+            | AppError::PollClosed
+            | AppError::InvalidEvent
+            | AppError::InvalidLink
+            | AppError::InvalidFeedBot
+            | AppError::InvalidExpiry
+            | AppError::InvalidRole
             | AppError::AxumFormRejection(_) => StatusCode::BAD_REQUEST,
             AppError::NotFound => StatusCode::NOT_FOUND,
             AppError::WriteInterval => StatusCode::TOO_MANY_REQUESTS,
+            AppError::AlreadyVoted => StatusCode::CONFLICT,
             AppError::NonLogin => return Redirect::to("/signin").into_response(),
             AppError::Unauthorized => StatusCode::UNAUTHORIZED,
-            AppError::Banned => StatusCode::FORBIDDEN,
+            AppError::Banned(_) | AppError::Restricted(_) => StatusCode::FORBIDDEN,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -727,6 +1088,10 @@ pub(super) async fn shutdown_signal() {
 /// | comment | 0      | 100  |
 /// | post    | 1      | 101  |
 /// | solo    | 2      | 102  |
+/// | approved| 3      | 103  |
+/// | rejected| 4      | 104  |
+///
+/// For approved and rejected, cid 0 means the post itself.
 struct Notification {
     uid: u32,
     username: String,
@@ -743,6 +1108,21 @@ struct InnNotification {
     uid: u32,
 }
 
+/// new comments in the subscribed post
+struct ThreadNotification {
+    iid: u32,
+    pid: u32,
+    post_title: String,
+    count: u32,
+    last_cid: u32,
+}
+
+/// posts and comments waiting for review in the inn
+struct PendingNotification {
+    iid: u32,
+    count: usize,
+}
+
 /// notification.html
 #[derive(Template)]
 #[template(path = "notification.html", escape = "none")]
@@ -750,6 +1130,8 @@ struct NotificationPage<'a> {
     page_data: PageData<'a>,
     notifications: Vec<Notification>,
     inn_notifications: Vec<InnNotification>,
+    pending_notifications: Vec<PendingNotification>,
+    thread_notifications: Vec<ThreadNotification>,
 }
 
 #[derive(Deserialize)]
@@ -803,6 +1185,11 @@ pub(super) async fn notification(
                         break;
                     }
                 }
+                let thread_notifications_tree = db.open_tree("thread_notifications")?;
+                for i in thread_notifications_tree.scan_prefix(&prefix) {
+                    let (key, _) = i?;
+                    thread_notifications_tree.remove(key)?;
+                }
             }
             "delete_batch" => {
                 for (n, i) in tree.scan_prefix(&prefix).enumerate() {
@@ -850,7 +1237,32 @@ pub(super) async fn notification(
         match pid.cmp(&0) {
             Ordering::Greater => {
                 let cid = u8_slice_to_u32(&key[8..12]);
-                if let Some(v) = &db.open_tree("post_comments")?.get(&key[4..12])? {
+                if value[0] % 100 == 3 || value[0] % 100 == 4 {
+                    // review result of my own post or comment
+                    if let Ok(post) = get_one::<Post>(&db, "posts", pid) {
+                        // rejected comments are dropped, the cid is the id they were held with
+                        let comment_content =
+                            match db.open_tree("post_comments")?.get(&key[4..12])? {
+                                Some(v) if value[0] % 100 == 3 => {
+                                    let (comment, _): (Comment, usize) =
+                                        bincode::decode_from_slice(&v, standard())?;
+                                    comment.content
+                                }
+                                _ => String::new(),
+                            };
+                        let notification = Notification {
+                            uid: claim.uid,
+                            username: claim.username.clone(),
+                            pid,
+                            iid: post.iid,
+                            post_title: post.title,
+                            cid,
+                            comment_content,
+                            notification_code: value[0],
+                        };
+                        notifications.push(notification);
+                    }
+                } else if let Some(v) = &db.open_tree("post_comments")?.get(&key[4..12])? {
                     let (comment, _): (Comment, usize) = bincode::decode_from_slice(v, standard())?;
                     let post: Post = get_one(&db, "posts", pid)?;
                     let user: User = get_one(&db, "users", comment.uid)?;
@@ -893,9 +1305,35 @@ pub(super) async fn notification(
     }
     notifications.reverse();
 
+    // kv_pair: uid#pid = count#last_cid
+    let mut thread_notifications = Vec::new();
+    for i in db.open_tree("thread_notifications")?.scan_prefix(&prefix) {
+        let (key, value) = i?;
+        let pid = u8_slice_to_u32(&key[4..8]);
+        if let Ok(post) = get_one::<Post>(&db, "posts", pid) {
+            thread_notifications.push(ThreadNotification {
+                iid: post.iid,
+                pid,
+                post_title: post.title,
+                count: u8_slice_to_u32(&value[0..4]),
+                last_cid: u8_slice_to_u32(&value[4..8]),
+            });
+        }
+        if thread_notifications.len() >= 30 {
+            break;
+        }
+    }
+
     let mut inn_notifications = Vec::new();
+    let mut pending_notifications = Vec::new();
     let mod_inns = get_ids_by_prefix(&db, "mod_inns", prefix, None)?;
     for i in mod_inns {
+        let count = get_count_by_prefix(&db, "post_pending", &u32_to_ivec(i))?
+            + get_count_by_prefix(&db, "comment_pending", &u32_to_ivec(i))?;
+        if count > 0 {
+            pending_notifications.push(PendingNotification { iid: i, count });
+        }
+
         for i in db.open_tree("inn_apply")?.scan_prefix(u32_to_ivec(i)) {
             let (k, _) = i?;
             let inn_notification = InnNotification {
@@ -916,6 +1354,8 @@ pub(super) async fn notification(
         page_data,
         notifications,
         inn_notifications,
+        pending_notifications,
+        thread_notifications,
     };
 
     Ok(into_response(&notification_page, "html"))
@@ -1005,6 +1445,76 @@ fn user_stats(db: &Db, uid: u32, stat_type: &str) -> Result<(), AppError> {
     Ok(())
 }
 
+/// convert a date to `yyyymmdd`, used in the keys of daily stats
+fn date_to_u32(date: NaiveDate) -> u32 {
+    date.year() as u32 * 10000 + date.month() * 100 + date.day()
+}
+
+/// record an inn event of today, such as `join`, `leave` and `post_upvote`
+fn inn_event(db: &Db, iid: u32, event: &str) -> Result<(), AppError> {
+    let date = date_to_u32(Utc::now().date_naive());
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(date), event.as_bytes()].concat();
+    incr_id(&db.open_tree("inn_events")?, k)?;
+    Ok(())
+}
+
+/// record a `post` or `comment` of today in the inn, and count it for the user as a contributor
+fn inn_contribution(db: &Db, iid: u32, uid: u32, event: &str) -> Result<(), AppError> {
+    inn_event(db, iid, event)?;
+    let date = date_to_u32(Utc::now().date_naive());
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(date), &u32_to_ivec(uid)].concat();
+    incr_id(&db.open_tree("inn_contributors")?, k)?;
+    Ok(())
+}
+
+/// count a post or comment of the member in the inn, or a vote their post or comment received.
+///
+/// `stat_type` is `post`, `comment`, `upvote` or `downvote`. Used by
+/// [check_promotion](user/fn.check_promotion.html) instead of scanning all the posts and comments.
+fn member_stats(
+    db: &Db,
+    iid: u32,
+    uid: u32,
+    stat_type: &str,
+    is_add: bool,
+) -> Result<(), AppError> {
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(uid), stat_type.as_bytes()].concat();
+    let tree = db.open_tree("member_stats")?;
+    if is_add {
+        incr_id(&tree, k)?;
+    } else {
+        tree.update_and_fetch(k, decrement)?;
+    }
+    Ok(())
+}
+
+/// append a [ModLog]. Use iid 0 for site-wide actions.
+fn mod_log(
+    db: &Db,
+    iid: u32,
+    uid: u32,
+    action: String,
+    target: String,
+    reason: String,
+) -> Result<(), AppError> {
+    let lid = incr_id(db, "mod_logs_count")?;
+    let log = ModLog {
+        lid,
+        iid,
+        uid,
+        action,
+        target,
+        reason: reason.trim().chars().take(256).collect(),
+        created_at: Utc::now().timestamp(),
+    };
+    let log_encoded = bincode::encode_to_vec(&log, standard())?;
+    let lid_ivec = u32_to_ivec(lid);
+    db.open_tree("mod_logs")?.insert(&lid_ivec, log_encoded)?;
+    let k = [&u32_to_ivec(iid), &lid_ivec].concat();
+    db.open_tree("inn_mod_logs")?.insert(k, &[])?;
+    Ok(())
+}
+
 /// work for [update_and_fetch](https://docs.rs/sled/latest/sled/struct.Db.html#method.update_and_fetch):
 /// increment 1.
 fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
@@ -1024,6 +1534,11 @@ fn increment(old: Option<&[u8]>) -> Option<Vec<u8>> {
     Some(number.to_be_bytes().to_vec())
 }
 
+fn decrement(old: Option<&[u8]>) -> Option<Vec<u8>> {
+    let number = old.map_or(0, u8_slice_to_u32).saturating_sub(1);
+    Some(number.to_be_bytes().to_vec())
+}
+
 fn get_referer(header: Option<TypedHeader<Referer>>) -> Option<String> {
     if let Some(TypedHeader(r)) = header {
         let referer = format!("{r:?}");
@@ -1099,15 +1614,26 @@ fn has_unread(db: &Db, uid: u32) -> Result<bool, AppError> {
         }
     }
 
+    if db
+        .open_tree("thread_notifications")?
+        .scan_prefix(&prefix)
+        .next()
+        .is_some()
+    {
+        return Ok(true);
+    }
+
     let mod_inns = get_ids_by_prefix(db, "mod_inns", &prefix, None)?;
     for i in mod_inns {
-        if db
-            .open_tree("inn_apply")?
-            .scan_prefix(u32_to_ivec(i))
-            .next()
-            .is_some()
-        {
-            return Ok(true);
+        for tree_name in ["inn_apply", "post_pending", "comment_pending"] {
+            if db
+                .open_tree(tree_name)?
+                .scan_prefix(u32_to_ivec(i))
+                .next()
+                .is_some()
+            {
+                return Ok(true);
+            }
         }
     }
 
@@ -1136,11 +1662,27 @@ where
 }
 
 /// Used for pagination.
+///
+/// If the cursor is the last seen id, the range starts right after it.
 fn get_range(count: usize, page_params: &ParamsPage) -> (usize, usize) {
     let anchor = page_params.anchor;
     let n = page_params.n;
     let is_desc = page_params.is_desc;
 
+    if let Some(ref cursor) = page_params.cursor {
+        if cursor.len() == 4 {
+            let id = u8_slice_to_u32(cursor) as usize;
+            if is_desc {
+                let end = id.saturating_sub(1).min(count);
+                let start = if end > n { end - n + 1 } else { 1 };
+                return (start, end);
+            } else {
+                let start = (id + 1).min(count + 1);
+                return (start, (start + n - 1).min(count));
+            }
+        }
+    }
+
     let mut start = if anchor > count { count } else { anchor + 1 };
     let mut end = if start + n < count {
         start + n - 1
@@ -1212,26 +1754,26 @@ fn get_ids_by_prefix(
     page_params: Option<&ParamsPage>,
 ) -> Result<Vec<u32>, AppError> {
     let mut res = vec![];
-    let iter = db.open_tree(tree)?.scan_prefix(&prefix);
+    let tree = db.open_tree(tree)?;
     if let Some(page_params) = page_params {
-        let iter = if page_params.is_desc {
-            IterType::Rev(iter.rev())
-        } else {
-            IterType::Iter(iter)
-        };
+        let iter = iter_by_cursor(&tree, prefix.as_ref(), page_params);
+        let skip = page_params.skip();
         for (idx, i) in iter.enumerate() {
-            if idx < page_params.anchor {
+            if idx < skip {
                 continue;
             }
-            if idx >= page_params.anchor + page_params.n {
+            if idx >= skip + page_params.n {
                 break;
             }
             let (k, _) = i?;
+            if !k.starts_with(prefix.as_ref()) {
+                break;
+            }
             let id = &k[prefix.as_ref().len()..];
             res.push(u8_slice_to_u32(id));
         }
     } else {
-        for i in iter {
+        for i in tree.scan_prefix(&prefix) {
             let (k, _) = i?;
             let id = &k[prefix.as_ref().len()..];
             res.push(u8_slice_to_u32(id));
@@ -1249,21 +1791,21 @@ fn get_ids_by_tag(
     page_params: Option<&ParamsPage>,
 ) -> Result<Vec<u32>, AppError> {
     let mut res = vec![];
-    let iter = db.open_tree(tree)?.scan_prefix(tag);
+    let tree = db.open_tree(tree)?;
     if let Some(page_params) = page_params {
-        let iter = if page_params.is_desc {
-            IterType::Rev(iter.rev())
-        } else {
-            IterType::Iter(iter)
-        };
+        let iter = iter_by_cursor(&tree, tag.as_bytes(), page_params);
+        let skip = page_params.skip();
         for (idx, i) in iter.enumerate() {
-            if idx < page_params.anchor {
+            if idx < skip {
                 continue;
             }
-            if idx >= page_params.anchor + page_params.n {
+            if idx >= skip + page_params.n {
                 break;
             }
             let (k, _) = i?;
+            if !k.starts_with(tag.as_bytes()) {
+                break;
+            }
             let len = k.len();
             let str = String::from_utf8_lossy(&k[0..len - 4]);
             if tag == str {
@@ -1272,7 +1814,7 @@ fn get_ids_by_tag(
             }
         }
     } else {
-        for i in iter {
+        for i in tree.scan_prefix(tag) {
             let (k, _) = i?;
             let len = k.len();
             let str = String::from_utf8_lossy(&k[0..len - 4]);
@@ -1292,7 +1834,7 @@ fn get_ids_by_tag(
 ///
 /// ```no_run
 /// // get the inns which iid is between 101-110.
-/// let page_params = ParamsPage { anchor: 100, n: 10, is_desc: false };
+/// let page_params = ParamsPage { anchor: 100, n: 10, is_desc: false, cursor: None };
 /// let inns: Vec<Inn> = get_batch(&db, "default", "inns_count", "inns", &page_params)?;
 /// ```
 fn get_batch<T, K>(
@@ -1356,10 +1898,139 @@ fn extract_element(input: &str, max_len: usize, char: char) -> Vec<String> {
     vec
 }
 
+/// get the canonical form of a link for de-duplication: http(s) only, without the fragment,
+/// the tracking params and the trailing slash of the path.
+///
+/// Returns `None` if it is not a valid link.
+fn canonical_url(link: &str) -> Option<String> {
+    let mut url = reqwest::Url::parse(link.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+        return None;
+    }
+    url.set_fragment(None);
+
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(k, _)| !k.starts_with("utm_") && k != "fbclid" && k != "gclid")
+        .map(|(k, v)| (k.into_owned(), v.into_owned()))
+        .collect();
+    if query.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(query);
+    }
+
+    if url.path().len() > 1 && url.path().ends_with('/') {
+        let path = url.path().trim_end_matches('/').to_owned();
+        url.set_path(&path);
+    }
+    Some(url.into())
+}
+
+/// Used for pagination.
+///
+/// `cursor` is the last seen key of the previous page. If it is present, `anchor` is ignored
+/// when fetching, so deep pages are not skipped item by item and do not shift when new items
+/// arrive. Old links with only `anchor` still work.
 struct ParamsPage {
     anchor: usize,
     n: usize,
     is_desc: bool,
+    cursor: Option<Vec<u8>>,
+}
+
+impl ParamsPage {
+    /// the number of items to skip, which is 0 when paginating by cursor
+    fn skip(&self) -> usize {
+        if self.cursor.is_some() {
+            0
+        } else {
+            self.anchor
+        }
+    }
+}
+
+/// decode the opaque cursor from url params
+fn decode_cursor(cursor: Option<&str>) -> Option<Vec<u8>> {
+    cursor.and_then(|c| HEXLOWER.decode(c.as_bytes()).ok())
+}
+
+/// encode the last seen key as an opaque cursor
+fn encode_cursor(key: &[u8]) -> String {
+    HEXLOWER.encode(key)
+}
+
+/// iterate the tree under `prefix` in the order of `page_params`, starting right after the cursor.
+///
+/// # Note
+///
+/// In ascending order with a cursor, the iterator does not stop at the end of `prefix`, so the
+/// caller should check it.
+fn iter_by_cursor(tree: &Tree, prefix: &[u8], page_params: &ParamsPage) -> IterType {
+    let cursor = page_params
+        .cursor
+        .as_deref()
+        .filter(|c| c.len() > prefix.len() && c.starts_with(prefix));
+    match (cursor, page_params.is_desc) {
+        (Some(cursor), true) => IterType::Rev(tree.range(prefix..cursor).rev()),
+        (Some(cursor), false) => {
+            IterType::Iter(tree.range::<&[u8], _>((Bound::Excluded(cursor), Bound::Unbounded)))
+        }
+        (None, true) => IterType::Rev(tree.scan_prefix(prefix).rev()),
+        (None, false) => IterType::Iter(tree.scan_prefix(prefix)),
+    }
+}
+
+/// iterate ids of the keys `prefix#id` after the id `cursor`, in the order of `is_desc`.
+fn iter_ids_after(
+    tree: &Tree,
+    prefix: Vec<u8>,
+    cursor: Option<u32>,
+    is_desc: bool,
+) -> impl Iterator<Item = Result<u32, AppError>> {
+    let page_params = ParamsPage {
+        anchor: 0,
+        n: 0,
+        is_desc,
+        cursor: cursor.map(|id| [&prefix[..], &u32_to_ivec(id)].concat()),
+    };
+    let iter = iter_by_cursor(tree, &prefix, &page_params);
+    let len = prefix.len() + 4;
+    iter.take_while(move |i| i.as_ref().map_or(true, |(k, _)| k.starts_with(&prefix)))
+        .filter_map(move |i| match i {
+            Ok((k, _)) if k.len() == len => Some(Ok(u8_slice_to_u32(&k[len - 4..]))),
+            Ok(_) => None,
+            Err(e) => Some(Err(e.into())),
+        })
+}
+
+/// get one page from a list sorted by `key` in ascending order, in the order of `page_params`.
+fn get_page<T, F>(sorted: &[T], key: F, page_params: &ParamsPage) -> Vec<T>
+where
+    T: Clone,
+    F: Fn(&T) -> Vec<u8>,
+{
+    let n = page_params.n;
+    let (start, end) = if let Some(ref cursor) = page_params.cursor {
+        if page_params.is_desc {
+            let end = sorted.partition_point(|i| key(i) < *cursor);
+            (end.saturating_sub(n), end)
+        } else {
+            let start = sorted.partition_point(|i| key(i) <= *cursor);
+            (start, (start + n).min(sorted.len()))
+        }
+    } else if sorted.is_empty() {
+        (0, 0)
+    } else {
+        let (start, end) = get_range(sorted.len(), page_params);
+        (start - 1, end)
+    };
+
+    let mut page = sorted[start..end].to_vec();
+    if page_params.is_desc {
+        page.reverse();
+    }
+    page
 }
 
 enum IterType {
@@ -1382,6 +2053,46 @@ impl Iterator for IterType {
 mod tests {
     use super::*;
 
+    pub(super) fn new_post(pid: u32, iid: u32, title: &str) -> Post {
+        Post {
+            pid,
+            uid: 1,
+            iid,
+            title: title.into(),
+            tags: vec![],
+            content: String::new(),
+            created_at: 0,
+            is_locked: false,
+            is_hidden: false,
+        }
+    }
+
+    /// the body of a response as a string
+    pub(super) async fn body_string(response: Response) -> String {
+        use axum::body::HttpBody;
+
+        let mut body = response.into_body();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_canonical_url() {
+        assert_eq!(
+            canonical_url("HTTPS://Example.COM/a/?utm_source=x&id=1&fbclid=2#top").as_deref(),
+            Some("https://example.com/a?id=1")
+        );
+        assert_eq!(
+            canonical_url("https://example.com").as_deref(),
+            Some("https://example.com/")
+        );
+        assert_eq!(canonical_url("ftp://example.com/"), None);
+        assert_eq!(canonical_url("not a link"), None);
+    }
+
     #[test]
     fn test_u32_to_ivec() {
         let iv = u32_to_ivec(12345678);
@@ -1394,4 +2105,23 @@ mod tests {
         let out = extract_element(input, 3, '@');
         assert_eq!(out, vec!["cc"]);
     }
+
+    #[test]
+    fn test_get_page() {
+        let ids: Vec<u32> = (1..=10).collect();
+        let key = |id: &u32| u32_to_ivec(*id).to_vec();
+        let mut page_params = ParamsPage {
+            anchor: 3,
+            n: 3,
+            is_desc: true,
+            cursor: None,
+        };
+        assert_eq!(get_page(&ids, key, &page_params), vec![7, 6, 5]);
+
+        page_params.cursor = Some(key(&5));
+        assert_eq!(get_page(&ids, key, &page_params), vec![4, 3, 2]);
+
+        page_params.is_desc = false;
+        assert_eq!(get_page(&ids, key, &page_params), vec![6, 7, 8]);
+    }
 }
@@ -0,0 +1,212 @@
+//! ## Review queue
+//!
+//! Inns can hold the new posts, and the comments if `premod_comments` is on, from the roles
+//! below `premod_role` of the [InnConfig](super::InnConfig) for mods to review. Held posts are
+//! only visible to their authors and mods, and they are indexed when approved.
+
+use super::{
+    get_ids_by_prefix, get_one, get_site_config, has_unread,
+    inn::{
+        check_archived, get_out_post_list, insert_comment, notify_subscribers, publish_post,
+        OutPostList, ParamsReason,
+    },
+    into_response, is_mod, ivec_to_u32, mod_log, u32_to_ivec, u8_slice_to_u32,
+    user::check_promotion,
+    user_stats, Claim, Inn, PageData, PendingComment, Post, User,
+};
+use crate::error::AppError;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State, TypedHeader},
+    headers::Cookie,
+    response::{IntoResponse, Redirect},
+};
+use bincode::config::standard;
+use sled::Db;
+
+/// Vec data: pending comment
+pub(super) struct OutPendingComment {
+    pub(super) pid: u32,
+    pub(super) cid: u32,
+    pub(super) uid: u32,
+    pub(super) username: String,
+    pub(super) post_title: String,
+    pub(super) content: String,
+}
+
+/// Page data: `mod_pending.html`
+#[derive(Template)]
+#[template(path = "mod_pending.html")]
+struct PageModPending<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    posts: Vec<OutPostList>,
+    comments: Vec<OutPendingComment>,
+}
+
+/// `GET /mod/:iid/pending` posts and comments waiting for review
+pub(crate) async fn mod_pending(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? {
+        return Err(AppError::Unauthorized);
+    }
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    let pids = get_ids_by_prefix(&db, "post_pending", u32_to_ivec(iid), None)?;
+    let posts = get_out_post_list(&db, &pids)?;
+
+    let mut comments = Vec::new();
+    // kv_pair: iid#pid#id = PendingComment
+    for i in db
+        .open_tree("comment_pending")?
+        .scan_prefix(u32_to_ivec(iid))
+    {
+        let (k, v) = i?;
+        let (pending, _): (PendingComment, usize) = bincode::decode_from_slice(&v, standard())?;
+        let comment = pending.comment;
+        let post: Post = get_one(&db, "posts", comment.pid)?;
+        let user: User = get_one(&db, "users", comment.uid)?;
+        comments.push(OutPendingComment {
+            pid: comment.pid,
+            cid: u8_slice_to_u32(&k[8..12]),
+            uid: comment.uid,
+            username: user.username,
+            post_title: post.title,
+            content: comment.content,
+        });
+    }
+
+    let has_unread = has_unread(&db, claim.uid)?;
+    let page_data = PageData::new("pending", &site_config, Some(claim), has_unread);
+    let page_mod_pending = PageModPending {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        posts,
+        comments,
+    };
+
+    Ok(into_response(&page_mod_pending, "html"))
+}
+
+/// `GET /mod/:iid/pending/:pid/:cid/:action` approve or reject a pending post or comment
+///
+/// cid 0 is for the post itself, otherwise it is the id of the held comment. action is `approve`
+/// or `reject`. Rejected posts are hidden, rejected comments are dropped. Approved comments get
+/// their cid and count only then.
+/// The author will get a notification with code 3 (approved) or 4 (rejected).
+pub(crate) async fn pending_action(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid, cid, action)): Path<(u32, u32, u32, String)>,
+    Query(params): Query<ParamsReason>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+    if !is_mod(&db, claim.uid, iid)? {
+        return Err(AppError::Unauthorized);
+    }
+
+    let is_approved = match action.as_str() {
+        "approve" => true,
+        "reject" => false,
+        _ => return Err(AppError::NotFound),
+    };
+    let notification_code = if is_approved { 3 } else { 4 };
+
+    let mut post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid {
+        return Err(AppError::NotFound);
+    }
+    check_archived(&db, iid)?;
+
+    let pid_ivec = u32_to_ivec(pid);
+    let notification_tree = db.open_tree("notifications")?;
+    let target;
+    if cid == 0 {
+        let k = [&u32_to_ivec(iid), &pid_ivec].concat();
+        let v = db
+            .open_tree("post_pending")?
+            .remove(k)?
+            .ok_or(AppError::NotFound)?;
+
+        if is_approved {
+            let item_id = (v.len() == 4).then(|| ivec_to_u32(&v));
+            publish_post(&db, &post, item_id)?;
+            check_promotion(&db, iid, post.uid)?;
+        } else {
+            post.is_hidden = true;
+            let post_encoded = bincode::encode_to_vec(&post, standard())?;
+            db.open_tree("posts")?.insert(&pid_ivec, post_encoded)?;
+            // the extras of a rejected post were never indexed
+            for tree_name in ["polls", "events", "post_url"] {
+                db.open_tree(tree_name)?.remove(&pid_ivec)?;
+            }
+        }
+
+        let notify_key = [&u32_to_ivec(post.uid), &pid_ivec, &u32_to_ivec(0)].concat();
+        notification_tree.insert(notify_key, vec![notification_code])?;
+        target = format!("/post/{iid}/{pid}");
+    } else {
+        let k = [&u32_to_ivec(iid), &pid_ivec, &u32_to_ivec(cid)].concat();
+        let v = db
+            .open_tree("comment_pending")?
+            .remove(k)?
+            .ok_or(AppError::NotFound)?;
+        let (pending, _): (PendingComment, usize) = bincode::decode_from_slice(&v, standard())?;
+        let uid = pending.comment.uid;
+
+        let notify_cid = if is_approved {
+            let cid = insert_comment(&db, iid, pending.comment)?;
+            let cid_ivec = u32_to_ivec(cid);
+            for &mentioned in &pending.mentioned {
+                let notify_key = [&u32_to_ivec(mentioned), &pid_ivec, &cid_ivec].concat();
+                notification_tree.insert(notify_key, vec![0])?;
+            }
+            if post.uid != uid {
+                let notify_key = [&u32_to_ivec(post.uid), &pid_ivec, &cid_ivec].concat();
+                notification_tree.insert(notify_key, vec![1])?;
+            }
+            let mut skipped = pending.mentioned;
+            skipped.push(uid);
+            skipped.push(post.uid);
+            notify_subscribers(&db, iid, pid, cid, &skipped)?;
+            user_stats(&db, uid, "comment")?;
+            check_promotion(&db, iid, uid)?;
+            target = format!("/post/{iid}/{pid}#{cid}");
+            cid
+        } else {
+            target = format!("/post/{iid}/{pid}");
+            cid
+        };
+
+        let notify_key = [&u32_to_ivec(uid), &pid_ivec, &u32_to_ivec(notify_cid)].concat();
+        notification_tree.insert(notify_key, vec![notification_code])?;
+    }
+
+    let action = match (cid, is_approved) {
+        (0, true) => "approve post",
+        (0, false) => "reject post",
+        (_, true) => "approve comment",
+        (_, false) => "reject comment",
+    };
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        action.into(),
+        target,
+        params.reason.unwrap_or_default(),
+    )?;
+
+    let target = format!("/mod/{iid}/pending");
+    Ok(Redirect::to(&target))
+}
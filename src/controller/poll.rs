@@ -0,0 +1,175 @@
+//! ## Polls
+//!
+//! A post can have a poll of 2 to 10 options, one per line. Polls can allow multiple choices,
+//! hide the voters, and close at a given time. Every user votes once, and votes can't be changed.
+
+use super::{
+    get_one, get_site_config, timestamp_to_date, u32_to_ivec, u8_slice_to_u32, Claim, Inn, InnType,
+    Poll, Post, User,
+};
+use crate::error::AppError;
+use axum::{
+    extract::{Form, Path, State, TypedHeader},
+    headers::Cookie,
+    response::{IntoResponse, Redirect},
+};
+use bincode::config::standard;
+use chrono::Utc;
+use sled::Db;
+
+/// get the options of a new poll, one per line. Empty if there is no poll.
+pub(super) fn get_poll_options(input: Option<&str>) -> Result<Vec<String>, AppError> {
+    let options: Vec<String> = input
+        .unwrap_or_default()
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if options.is_empty() {
+        return Ok(options);
+    }
+    if options.len() < 2 || options.len() > 10 || options.iter().any(|o| o.chars().count() > 100) {
+        return Err(AppError::InvalidPoll);
+    }
+    Ok(options)
+}
+
+/// Vec data: poll option
+pub(super) struct OutPollOption {
+    pub(super) idx: usize,
+    pub(super) text: String,
+    pub(super) votes: usize,
+    pub(super) percent: usize,
+    /// empty if the poll is anonymous
+    pub(super) voters: Vec<(u32, String)>,
+    pub(super) is_voted: bool,
+}
+
+/// Vec data: poll
+pub(super) struct OutPoll {
+    pub(super) options: Vec<OutPollOption>,
+    pub(super) is_multiple: bool,
+    pub(super) is_anonymous: bool,
+    pub(super) close_at: String,
+    pub(super) is_closed: bool,
+    pub(super) has_voted: bool,
+    pub(super) total: usize,
+}
+
+impl OutPoll {
+    pub(super) fn get(db: &Db, pid: u32, claim_uid: Option<u32>) -> Result<Option<Self>, AppError> {
+        let poll: Poll = match db.open_tree("polls")?.get(u32_to_ivec(pid))? {
+            Some(v) => bincode::decode_from_slice(&v, standard())?.0,
+            None => return Ok(None),
+        };
+
+        let mut votes = vec![vec![]; poll.options.len()];
+        let mut my_votes = vec![];
+        let mut total = 0;
+        // kv_pair: pid#uid = option..
+        for i in db.open_tree("poll_votes")?.scan_prefix(u32_to_ivec(pid)) {
+            let (k, v) = i?;
+            let uid = u8_slice_to_u32(&k[4..8]);
+            if Some(uid) == claim_uid {
+                my_votes = v.to_vec();
+            }
+            for idx in v.iter() {
+                if let Some(voters) = votes.get_mut(*idx as usize) {
+                    voters.push(uid);
+                }
+            }
+            total += 1;
+        }
+
+        let mut options = Vec::with_capacity(poll.options.len());
+        for (idx, (text, uids)) in poll.options.into_iter().zip(votes).enumerate() {
+            let mut voters = vec![];
+            if !poll.is_anonymous {
+                for uid in &uids {
+                    let user: User = get_one(db, "users", *uid)?;
+                    voters.push((*uid, user.username));
+                }
+            }
+            options.push(OutPollOption {
+                idx,
+                text,
+                votes: uids.len(),
+                percent: (uids.len() * 100).checked_div(total).unwrap_or_default(),
+                voters,
+                is_voted: my_votes.contains(&(idx as u8)),
+            });
+        }
+
+        let is_closed =
+            matches!(poll.close_at, Some(close_at) if close_at <= Utc::now().timestamp());
+        Ok(Some(OutPoll {
+            options,
+            is_multiple: poll.is_multiple,
+            is_anonymous: poll.is_anonymous,
+            close_at: poll.close_at.map(timestamp_to_date).unwrap_or_default(),
+            is_closed,
+            has_voted: !my_votes.is_empty(),
+            total,
+        }))
+    }
+}
+
+/// `POST /post/:iid/:pid/poll` vote on the poll of a post
+///
+/// The form is `choice=0&choice=2`, and a user can only vote once.
+pub(crate) async fn poll_vote(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, pid)): Path<(u32, u32)>,
+    Form(input): Form<Vec<(String, String)>>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let post: Post = get_one(&db, "posts", pid)?;
+    if post.iid != iid {
+        return Err(AppError::NotFound);
+    }
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    if inn.inn_type == InnType::Private {
+        let k = [&u32_to_ivec(claim.uid), &u32_to_ivec(iid)].concat();
+        if !db.open_tree("user_inns")?.contains_key(k)? {
+            return Err(AppError::NotFound);
+        }
+    }
+    if inn.inn_type == InnType::Archive {
+        return Err(AppError::InnArchived);
+    }
+
+    let poll: Poll = match db.open_tree("polls")?.get(u32_to_ivec(pid))? {
+        Some(v) => bincode::decode_from_slice(&v, standard())?.0,
+        None => return Err(AppError::NotFound),
+    };
+    if matches!(poll.close_at, Some(close_at) if close_at <= Utc::now().timestamp()) {
+        return Err(AppError::PollClosed);
+    }
+
+    let mut choices: Vec<u8> = input
+        .iter()
+        .filter(|(k, _)| k == "choice")
+        .filter_map(|(_, v)| v.parse::<u8>().ok())
+        .filter(|idx| (*idx as usize) < poll.options.len())
+        .collect();
+    choices.sort_unstable();
+    choices.dedup();
+    if !poll.is_multiple {
+        choices.truncate(1);
+    }
+    if choices.is_empty() {
+        return Err(AppError::InvalidPoll);
+    }
+
+    let k = [&u32_to_ivec(pid), &u32_to_ivec(claim.uid)].concat();
+    db.open_tree("poll_votes")?
+        .compare_and_swap(k, None as Option<&[u8]>, Some(choices))?
+        .map_err(|_| AppError::AlreadyVoted)?;
+
+    let target = format!("/post/{iid}/{pid}#poll");
+    Ok(Redirect::to(&target))
+}
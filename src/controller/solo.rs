@@ -1,8 +1,9 @@
 use super::{
-    extract_element, get_count_by_prefix, get_ids_by_prefix, get_ids_by_tag, get_one, get_range,
-    get_referer, get_site_config, has_unread, incr_id, into_response, ivec_to_u32, mark_read,
-    timestamp_to_date, u32_to_ivec, u8_slice_to_u32, user_stats, utils::md2html, Claim, IterType,
-    PageData, ParamsPage, Solo, User, ValidatedForm,
+    decode_cursor, encode_cursor, extract_element, feed::item_excerpt, get_count_by_prefix,
+    get_ids_by_prefix, get_ids_by_tag, get_one, get_page, get_referer, get_site_config, has_unread,
+    incr_id, into_response, iter_by_cursor, ivec_to_u32, mark_read, timestamp_to_date, u32_to_ivec,
+    u8_slice_to_u32, user_stats, utils::md2html, Claim, Item, PageData, ParamsPage, Solo, User,
+    ValidatedForm,
 };
 use crate::error::AppError;
 use askama::Template;
@@ -24,6 +25,8 @@ pub(crate) struct FormSolo {
     content: String,
     visibility: String,
     reply_to: u32,
+    /// the feed item shared as the solo
+    item_id: Option<u32>,
 }
 
 /// Page data: `solo_list.html`
@@ -35,11 +38,15 @@ struct PageSoloList<'a> {
     uid: u32,
     username: String,
     anchor: usize,
+    cursor: String,
     n: usize,
     is_desc: bool,
     is_following: bool,
     filter: Option<String>,
     hashtag: Option<String>,
+    /// the prefilled content when sharing a feed item
+    share: Option<String>,
+    item_id: Option<u32>,
 }
 
 /// Vec data: solo
@@ -105,7 +112,27 @@ impl OutSolo {
     }
 }
 
-fn can_visit_solo(visibility: u32, followers: &[u32], solo_uid: u32, current_uid: u32) -> bool {
+/// get the solos shared from the feed item, which the user can see
+pub(super) fn get_item_solos(
+    db: &Db,
+    item_id: u32,
+    uid: Option<u32>,
+) -> Result<Vec<u32>, AppError> {
+    let mut sids = vec![];
+    for sid in get_ids_by_prefix(db, "item_solos", u32_to_ivec(item_id), None)? {
+        if OutSolo::get(db, sid, uid)?.is_some() {
+            sids.push(sid);
+        }
+    }
+    Ok(sids)
+}
+
+pub(super) fn can_visit_solo(
+    visibility: u32,
+    followers: &[u32],
+    solo_uid: u32,
+    current_uid: u32,
+) -> bool {
     visibility == 0
         || (visibility == 10 && followers.contains(&solo_uid))
         || (visibility == 20 && solo_uid == current_uid)
@@ -115,10 +142,13 @@ fn can_visit_solo(visibility: u32, followers: &[u32], solo_uid: u32, current_uid
 #[derive(Deserialize)]
 pub(crate) struct ParamsSolo {
     anchor: Option<usize>,
+    cursor: Option<String>,
     is_desc: Option<bool>,
     filter: Option<String>,
     hashtag: Option<String>,
     notification_sid: Option<u32>,
+    /// share the feed item
+    item_id: Option<u32>,
 }
 
 /// `GET /solo/user/:uid` solo page
@@ -134,10 +164,17 @@ pub(crate) async fn solo_list(
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
-    let page_params = ParamsPage { anchor, n, is_desc };
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor,
+    };
 
     let mut is_following = false;
     let mut index = Vec::with_capacity(n);
+    let mut cursor = String::new();
     let mut followers = Vec::new();
     let mut current_uid = 0;
     if let Some(ref claim) = claim {
@@ -159,7 +196,8 @@ pub(crate) async fn solo_list(
                 if let Ok(uids) =
                     get_ids_by_prefix(&db, "user_following", u32_to_ivec(claim.uid), None)
                 {
-                    index = get_solos_by_uids(&db, &uids, &followers, current_uid, &page_params)?;
+                    (index, cursor) =
+                        get_solos_by_uids(&db, &uids, &followers, current_uid, &page_params)?;
                 };
             }
         }
@@ -168,18 +206,25 @@ pub(crate) async fn solo_list(
                 if let Ok(sids) =
                     get_ids_by_prefix(&db, "user_solos_like", u32_to_ivec(claim.uid), None)
                 {
-                    let (start, end) = get_range(sids.len(), &page_params);
-                    index = sids[start - 1..end].to_vec();
+                    index = get_page(&sids, |sid| u32_to_ivec(*sid).to_vec(), &page_params);
+                    if let Some(sid) = index.last() {
+                        cursor = encode_cursor(&u32_to_ivec(*sid));
+                    }
                 };
             }
         }
         _ => {
             if let Some(ref hashtag) = params.hashtag {
                 index = get_ids_by_tag(&db, "hashtags", hashtag, Some(&page_params))?;
+                if let Some(sid) = index.last() {
+                    cursor = encode_cursor(&[hashtag.as_bytes(), &u32_to_ivec(*sid)].concat());
+                }
             } else if uid == 0 {
-                index = get_all_solos(&db, "solo_timeline", &followers, current_uid, &page_params)?;
+                (index, cursor) =
+                    get_all_solos(&db, "solo_timeline", &followers, current_uid, &page_params)?;
             } else {
-                index = get_solos_by_uids(&db, &[uid], &followers, current_uid, &page_params)?;
+                (index, cursor) =
+                    get_solos_by_uids(&db, &[uid], &followers, current_uid, &page_params)?;
             }
         }
     }
@@ -193,6 +238,19 @@ pub(crate) async fn solo_list(
 
     let filter = if claim.is_none() { None } else { params.filter };
 
+    let mut share = None;
+    if let (Some(item_id), Some(_)) = (params.item_id, &claim) {
+        let item: Item = get_one(&db, "items", item_id)?;
+        // leave some room within the 1000 chars limit
+        let max = 900usize.saturating_sub(item.title.chars().count() + item.link.len());
+        share = Some(format!(
+            "{}\n\n> {}\n\n{}",
+            item.title,
+            item_excerpt(&item, max),
+            item.link
+        ));
+    }
+
     let has_unread = if let Some(ref claim) = claim {
         has_unread(&db, claim.uid)?
     } else {
@@ -213,11 +271,14 @@ pub(crate) async fn solo_list(
         uid,
         username,
         anchor,
+        cursor,
         n,
         is_desc,
         is_following,
         filter,
         hashtag: params.hashtag,
+        share,
+        item_id: params.item_id,
     };
     Ok(into_response(&page_solo_list, "html"))
 }
@@ -280,34 +341,33 @@ fn get_all_solos(
     followers: &[u32],
     current_uid: u32,
     page_params: &ParamsPage,
-) -> Result<Vec<u32>, AppError> {
+) -> Result<(Vec<u32>, String), AppError> {
     let tree = db.open_tree(timeline_tree)?;
     let mut count: usize = 0;
     let mut result = Vec::with_capacity(page_params.n);
+    let mut cursor = String::new();
+    let skip = page_params.skip();
 
-    let iter = if page_params.is_desc {
-        IterType::Rev(tree.iter().rev())
-    } else {
-        IterType::Iter(tree.iter())
-    };
+    let iter = iter_by_cursor(&tree, &[], page_params);
     for i in iter {
         // kv_pair: sid = uid#visibility
         let (k, v) = i?;
         let solo_uid = u8_slice_to_u32(&v[0..4]);
         let visibility = u8_slice_to_u32(&v[4..8]);
         if can_visit_solo(visibility, followers, solo_uid, current_uid) {
-            if count < page_params.anchor {
+            if count < skip {
                 count += 1;
                 continue;
             }
             result.push(ivec_to_u32(&k));
+            cursor = encode_cursor(&k);
         }
 
         if result.len() == page_params.n {
             break;
         }
     }
-    Ok(result)
+    Ok((result, cursor))
 }
 
 fn get_solos_by_uids(
@@ -316,8 +376,8 @@ fn get_solos_by_uids(
     followers: &[u32],
     current_uid: u32,
     page_params: &ParamsPage,
-) -> Result<Vec<u32>, AppError> {
-    let mut sids = Vec::with_capacity(page_params.n);
+) -> Result<(Vec<u32>, String), AppError> {
+    let mut sids = Vec::new();
     let user_solos_tree = db.open_tree("user_solos")?;
     for uid in uids {
         let prefix = u32_to_ivec(*uid);
@@ -331,12 +391,10 @@ fn get_solos_by_uids(
             }
         }
     }
-    let (start, end) = get_range(sids.len(), page_params);
-    sids = sids[start - 1..end].to_vec();
-    if page_params.is_desc {
-        sids.reverse();
-    }
-    Ok(sids)
+    sids.sort_unstable();
+    let sids = get_page(&sids, |sid| u32_to_ivec(*sid).to_vec(), page_params);
+    let cursor = sids.last().map(|sid| encode_cursor(&u32_to_ivec(*sid)));
+    Ok((sids, cursor.unwrap_or_default()))
 }
 
 /// `POST /solo/user/:uid` solo page
@@ -423,6 +481,13 @@ pub(crate) async fn solo_post(
     let v = [&u32_to_ivec(claim.uid), &u32_to_ivec(visibility)].concat();
     db.open_tree("solo_timeline")?.insert(&sid_ivec, v)?;
 
+    if let Some(item_id) = input.item_id {
+        if db.open_tree("items")?.contains_key(u32_to_ivec(item_id))? {
+            let k = [&u32_to_ivec(item_id), &sid_ivec].concat();
+            db.open_tree("item_solos")?.insert(k, &[])?;
+        }
+    }
+
     user_stats(&db, claim.uid, "solo")?;
     claim.update_last_write(&db)?;
 
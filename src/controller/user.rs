@@ -1,10 +1,12 @@
 //! ## [User] sign up/in/out, user profile/list controller
 
 use super::{
-    generate_nanoid_expire, get_count_by_prefix, get_ids_by_prefix, get_inn_role, get_one,
-    get_range, get_site_config, get_uid_by_name, incr_id, into_response, is_mod, timestamp_to_date,
-    u32_to_ivec, u8_slice_to_u32, user_stats, Claim, Inn, IterType, PageData, ParamsPage,
-    SiteConfig, User, ValidatedForm,
+    decode_cursor, encode_cursor, generate_nanoid_expire, get_count_by_prefix, get_ids_by_prefix,
+    get_inn_role, get_one, get_range, get_site_config, get_uid_by_name, has_unread, incr_id,
+    inn::get_inn_config, inn_event, into_response, is_mod, iter_by_cursor, mod_log,
+    timestamp_to_date, u32_to_ivec, u8_slice_to_u32, user_stats, Claim, Comment, Inn, InnConfig,
+    InnType, IterType, PageData, ParamsPage, Post, PromotionRule, RoleChange, SiteConfig, User,
+    ValidatedForm,
 };
 use crate::{config::CONFIG, controller::get_count, error::AppError};
 use ::rand::{thread_rng, Rng};
@@ -18,9 +20,10 @@ use axum::{
 };
 use bincode::config::standard;
 use captcha::{CaptchaName, Difficulty};
-use chrono::Utc;
+use chrono::{NaiveDateTime, Utc};
 use data_encoding::BASE64;
 use identicon::Identicon;
+use nanoid::nanoid;
 use ring::{
     pbkdf2,
     rand::{self, SecureRandom},
@@ -141,6 +144,7 @@ struct PageUserList<'a> {
     page_data: PageData<'a>,
     users: Vec<OutUserList>,
     anchor: usize,
+    cursor: String,
     n: usize,
     is_desc: bool,
     filter: Option<String>,
@@ -177,28 +181,31 @@ impl OutUserList {
         Ok(users)
     }
 
+    /// Returns the users and the cursor of the next page, which is the last seen `iid#uid`.
     fn get_inn_users(
         db: &Db,
         iid: u32,
         role: Option<u8>,
         page_params: &ParamsPage,
-    ) -> Result<Vec<Self>, AppError> {
+    ) -> Result<(Vec<Self>, String), AppError> {
         let mut users = Vec::with_capacity(page_params.n);
-        let iter = db.open_tree("inn_users")?.scan_prefix(u32_to_ivec(iid));
-        let iter = if page_params.is_desc {
-            IterType::Rev(iter.rev())
-        } else {
-            IterType::Iter(iter)
-        };
+        let mut cursor = String::new();
+        let prefix = u32_to_ivec(iid);
+        let iter = iter_by_cursor(&db.open_tree("inn_users")?, &prefix, page_params);
+        let skip = page_params.skip();
 
         for (idx, i) in iter.enumerate() {
-            if idx < page_params.anchor {
+            if idx < skip {
                 continue;
             }
-            if idx >= page_params.anchor + page_params.n {
+            if idx >= skip + page_params.n {
                 break;
             }
             let (k, v) = i?;
+            if !k.starts_with(&prefix) {
+                break;
+            }
+            cursor = encode_cursor(&k);
             if let Some(role) = role {
                 if v[0] == role {
                     let uid = u8_slice_to_u32(&k[4..]);
@@ -213,14 +220,163 @@ impl OutUserList {
                 users.push(out_user_list);
             }
         }
-        Ok(users)
+        Ok((users, cursor))
+    }
+}
+
+/// Vec data: saved post or comment
+struct OutSaved {
+    iid: u32,
+    inn_name: String,
+    pid: u32,
+    cid: u32,
+    title: String,
+    content: String,
+    saved_at: String,
+}
+
+/// Page data: `user_saved.html`
+#[derive(Template)]
+#[template(path = "user_saved.html", escape = "none")]
+struct PageUserSaved<'a> {
+    page_data: PageData<'a>,
+    saved: Vec<OutSaved>,
+    anchor: usize,
+    n: usize,
+    is_desc: bool,
+    iid: u32,
+}
+
+/// url params: `user_saved.html`
+#[derive(Deserialize)]
+pub(crate) struct ParamsUserSaved {
+    anchor: Option<usize>,
+    is_desc: Option<bool>,
+    iid: Option<u32>,
+}
+
+/// `GET /user/saved` saved posts and comments, could be filtered by inn
+///
+/// The access of private inns is checked when reading, so the content is not shown after leaving.
+pub(crate) async fn user_saved(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Query(params): Query<ParamsUserSaved>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let n = site_config.per_page;
+    let anchor = params.anchor.unwrap_or(0);
+    let is_desc = params.is_desc.unwrap_or(true);
+    let iid = params.iid.unwrap_or_default();
+
+    let uid_ivec = u32_to_ivec(claim.uid);
+    let user_inns_tree = db.open_tree("user_inns")?;
+    let post_comments_tree = db.open_tree("post_comments")?;
+    let mut saved = Vec::with_capacity(n);
+    for (saved_iid, pid, cid, timestamp) in get_saved(&db, claim.uid, iid, anchor, n, is_desc)? {
+        let saved_at = timestamp_to_date(i64::from(timestamp));
+
+        let Ok(inn) = get_one::<Inn>(&db, "inns", saved_iid) else {
+            continue;
+        };
+        let user_inns_k = [&uid_ivec, &u32_to_ivec(saved_iid)].concat();
+        if inn.inn_type == InnType::Private && !user_inns_tree.contains_key(user_inns_k)? {
+            saved.push(OutSaved {
+                iid: saved_iid,
+                inn_name: inn.inn_name,
+                pid,
+                cid,
+                title: "Private".into(),
+                content: String::new(),
+                saved_at,
+            });
+            continue;
+        }
+
+        let Ok(post) = get_one::<Post>(&db, "posts", pid) else {
+            continue;
+        };
+        let mut content = String::new();
+        if cid > 0 {
+            if let Some(v) =
+                post_comments_tree.get([&u32_to_ivec(pid), &u32_to_ivec(cid)].concat())?
+            {
+                let (comment, _): (Comment, usize) = bincode::decode_from_slice(&v, standard())?;
+                content = if comment.is_hidden {
+                    "<p><i>Hidden by mod.</i></p>".into()
+                } else {
+                    comment.content
+                };
+            }
+        }
+
+        saved.push(OutSaved {
+            iid: saved_iid,
+            inn_name: inn.inn_name,
+            pid,
+            cid,
+            title: post.title,
+            content,
+            saved_at,
+        });
+    }
+
+    let has_unread = has_unread(&db, claim.uid)?;
+    let page_data = PageData::new("Saved", &site_config, Some(claim), has_unread);
+    let page_user_saved = PageUserSaved {
+        page_data,
+        saved,
+        anchor,
+        n,
+        is_desc,
+        iid,
+    };
+    Ok(into_response(&page_user_saved, "html"))
+}
+
+/// get a page of the saved `(iid, pid, cid, timestamp)` of the user, only of the inn if `iid`
+/// is not 0. `cid` is 0 for a post.
+fn get_saved(
+    db: &Db,
+    uid: u32,
+    iid: u32,
+    anchor: usize,
+    n: usize,
+    is_desc: bool,
+) -> Result<Vec<(u32, u32, u32, u32)>, AppError> {
+    let iter = db.open_tree("user_saved")?.scan_prefix(u32_to_ivec(uid));
+    let iter = if is_desc {
+        IterType::Rev(iter.rev())
+    } else {
+        IterType::Iter(iter)
+    };
+
+    let mut saved = Vec::with_capacity(n);
+    // kv_pair: uid#pid#cid = iid#timestamp
+    let filtered = iter.filter(|i| match i {
+        Ok((_, v)) => iid == 0 || u8_slice_to_u32(&v[0..4]) == iid,
+        Err(_) => true,
+    });
+    for i in filtered.skip(anchor).take(n) {
+        let (k, v) = i?;
+        saved.push((
+            u8_slice_to_u32(&v[0..4]),
+            u8_slice_to_u32(&k[4..8]),
+            u8_slice_to_u32(&k[8..12]),
+            u8_slice_to_u32(&v[4..8]),
+        ));
     }
+    Ok(saved)
 }
 
 /// url params: `user_list.html`
 #[derive(Deserialize)]
 pub(crate) struct ParamsUserList {
     anchor: Option<usize>,
+    cursor: Option<String>,
     is_desc: Option<bool>,
     filter: Option<String>,
     id: Option<u32>,
@@ -239,11 +395,18 @@ pub(crate) async fn user_list(
     let n = site_config.per_page;
     let anchor = params.anchor.unwrap_or(0);
     let is_desc = params.is_desc.unwrap_or(true);
-    let page_params = ParamsPage { anchor, n, is_desc };
+    let cursor = decode_cursor(params.cursor.as_deref());
+    let page_params = ParamsPage {
+        anchor,
+        n,
+        is_desc,
+        cursor,
+    };
 
     let mut index;
     let count;
     let info;
+    let mut cursor = String::new();
 
     let mut is_admin = false;
     if let Some(ref claim) = claim {
@@ -258,27 +421,34 @@ pub(crate) async fn user_list(
             Some("followers") => {
                 let user: User = get_one(&db, "users", id)?;
                 info = (user.uid, user.username, false);
-                index = get_ids_by_prefix(&db, "user_followers", id_ivec, Some(&page_params))?;
+                index = get_ids_by_prefix(&db, "user_followers", &id_ivec, Some(&page_params))?;
+                if let Some(uid) = index.last() {
+                    cursor = encode_cursor(&[&id_ivec, &u32_to_ivec(*uid)].concat());
+                }
                 users = OutUserList::get_from_uids(&db, index, n)?;
             }
             Some("following") => {
                 let user: User = get_one(&db, "users", id)?;
                 info = (user.uid, user.username, false);
-                index = get_ids_by_prefix(&db, "user_following", id_ivec, Some(&page_params))?;
+                index = get_ids_by_prefix(&db, "user_following", &id_ivec, Some(&page_params))?;
+                if let Some(uid) = index.last() {
+                    cursor = encode_cursor(&[&id_ivec, &u32_to_ivec(*uid)].concat());
+                }
                 users = OutUserList::get_from_uids(&db, index, n)?;
             }
             Some("inn") => {
                 let inn: Inn = get_one(&db, "inns", id)?;
-                let need_apply = inn.inn_type != "Public";
+                let need_apply = inn.inn_type.need_apply();
                 info = (inn.iid, inn.inn_name, need_apply);
                 is_admin = false;
                 if let Some(ref claim) = claim {
                     is_admin = is_mod(&db, claim.uid, inn.iid)?;
                 }
 
-                if inn.inn_type == "Private" && !is_admin {
+                if inn.inn_type == InnType::Private && !is_admin {
                 } else {
-                    users = OutUserList::get_inn_users(&db, id, params.role, &page_params)?;
+                    (users, cursor) =
+                        OutUserList::get_inn_users(&db, id, params.role, &page_params)?;
                 }
             }
             _ => return Ok(Redirect::to("/user/list").into_response()),
@@ -286,20 +456,17 @@ pub(crate) async fn user_list(
     } else {
         info = (0, "all".to_owned(), false);
         if let Some(role) = params.role {
-            let iter = db.open_tree("users")?.iter();
-            let iter = if page_params.is_desc {
-                IterType::Rev(iter.rev())
-            } else {
-                IterType::Iter(iter)
-            };
+            let iter = iter_by_cursor(&db.open_tree("users")?, &[], &page_params);
+            let skip = page_params.skip();
             for (idx, i) in iter.enumerate() {
-                if idx < page_params.anchor {
+                if idx < skip {
                     continue;
                 }
-                if idx >= page_params.anchor + page_params.n {
+                if idx >= skip + page_params.n {
                     break;
                 }
-                let (_, v) = i?;
+                let (k, v) = i?;
+                cursor = encode_cursor(&k);
                 let (user, _): (User, usize) = bincode::decode_from_slice(&v, standard())?;
                 if user.role == role {
                     let out_user_list = OutUserList::new(user.uid, user.username, user.about, role);
@@ -313,6 +480,9 @@ pub(crate) async fn user_list(
             if is_desc {
                 index.reverse();
             }
+            if let Some(uid) = index.last() {
+                cursor = encode_cursor(&u32_to_ivec(*uid));
+            }
             users = OutUserList::get_from_uids(&db, index, n)?;
         }
     }
@@ -322,6 +492,7 @@ pub(crate) async fn user_list(
         page_data,
         users,
         anchor,
+        cursor,
         n,
         is_desc,
         filter: params.filter,
@@ -337,9 +508,13 @@ pub(crate) async fn user_list(
 #[derive(Deserialize)]
 pub(crate) struct FormRole {
     role: String,
+    reason: Option<String>,
+    expiry: Option<String>,
 }
 
 /// `POST /role/:id/:uid`
+///
+/// If `expiry` is set, the old role will be restored when it expires. See [restore_expired_roles].
 pub(crate) async fn role_post(
     State(db): State<Db>,
     cookie: Option<TypedHeader<Cookie>>,
@@ -350,6 +525,12 @@ pub(crate) async fn role_post(
     let site_config = get_site_config(&db)?;
     let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
 
+    let reason = form.reason.unwrap_or_default();
+    let expiry = match form.expiry.filter(|e| !e.is_empty()) {
+        Some(expiry) => Some(expire_seconds(&expiry).ok_or(AppError::InvalidExpiry)?),
+        None => None,
+    };
+
     let target;
     match id.cmp(&0) {
         Ordering::Greater => {
@@ -358,24 +539,16 @@ pub(crate) async fn role_post(
                 return Err(AppError::Unauthorized);
             }
 
-            let inn_users_k = [&u32_to_ivec(id), &u32_to_ivec(uid)].concat();
-
             // protect super
-            if let Some(user_inn_role) = get_inn_role(&db, id, uid)? {
+            let old_role = get_inn_role(&db, id, uid)?;
+            if let Some(user_inn_role) = old_role {
                 if user_inn_role > inn_role {
                     return Err(AppError::Unauthorized);
                 }
-
-                if user_inn_role == 1 && form.role != "Pending" {
-                    db.open_tree("inn_apply")?.remove(&inn_users_k)?;
-                }
             }
 
             let inn_role: u8 = match form.role.as_str() {
-                "Pending" => {
-                    db.open_tree("inn_apply")?.insert(&inn_users_k, &[])?;
-                    1
-                }
+                "Pending" => 1,
                 "Deny" => 2,
                 "Limited" => 3,
                 "Intern" => 4,
@@ -391,21 +564,17 @@ pub(crate) async fn role_post(
                 _ => unreachable!(),
             };
 
-            db.open_tree("inn_users")?
-                .insert(&inn_users_k, &[inn_role])?;
+            set_inn_role(&db, id, uid, inn_role)?;
+            record_role_change(&db, id, uid, old_role, reason.clone(), expiry)?;
 
-            let user_inns_k = [&u32_to_ivec(uid), &u32_to_ivec(id)].concat();
-            if inn_role >= 3 {
-                db.open_tree("user_inns")?.insert(&user_inns_k, &[])?;
-            } else {
-                db.open_tree("user_inns")?.remove(&user_inns_k)?;
-            }
-
-            if inn_role >= 8 {
-                db.open_tree("mod_inns")?.insert(&user_inns_k, &[])?;
-            } else {
-                db.open_tree("mod_inns")?.remove(&user_inns_k)?;
-            }
+            mod_log(
+                &db,
+                id,
+                claim.uid,
+                format!("set role {}", form.role),
+                format!("/user/{uid}"),
+                reason,
+            )?;
 
             target = format!("/user/list?filter=inn&id={id}");
         }
@@ -415,9 +584,11 @@ pub(crate) async fn role_post(
             }
 
             let mut user: User = get_one(&db, "users", uid)?;
+            let old_role = user.role;
             user.role = match form.role.as_str() {
                 "Admin" => 255,
                 "Senior" => 100,
+                "Bot" => 20,
                 "Standard" => 10,
                 "Banned" => 0,
                 _ => unreachable!(),
@@ -427,6 +598,15 @@ pub(crate) async fn role_post(
                 .insert(&u32_to_ivec(uid), user_encode)?;
 
             Claim::update_role(&db, uid)?;
+            record_role_change(&db, 0, uid, Some(old_role), reason.clone(), expiry)?;
+            mod_log(
+                &db,
+                0,
+                claim.uid,
+                format!("set role {}", form.role),
+                format!("/user/{uid}"),
+                reason,
+            )?;
             target = "/user/list".to_string();
         }
         Ordering::Less => unreachable!(),
@@ -435,6 +615,291 @@ pub(crate) async fn role_post(
     Ok(Redirect::to(&target))
 }
 
+/// set inn role and update the index of inn apply, user inns and mod inns.
+///
+/// role 0 means the user is removed from the inn.
+pub(super) fn set_inn_role(db: &Db, iid: u32, uid: u32, role: u8) -> Result<(), AppError> {
+    let inn_users_k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+    let user_inns_k = [&u32_to_ivec(uid), &u32_to_ivec(iid)].concat();
+
+    if role == 0 {
+        db.open_tree("inn_users")?.remove(&inn_users_k)?;
+        db.open_tree("inn_joined")?.remove(&inn_users_k)?;
+        clear_role_change(db, iid, uid)?;
+    } else {
+        db.open_tree("inn_users")?.insert(&inn_users_k, &[role])?;
+    }
+
+    if role == 1 {
+        db.open_tree("inn_apply")?.insert(&inn_users_k, &[])?;
+    } else {
+        db.open_tree("inn_apply")?.remove(&inn_users_k)?;
+    }
+
+    if role >= 3 {
+        if db
+            .open_tree("user_inns")?
+            .insert(&user_inns_k, &[])?
+            .is_none()
+        {
+            inn_event(db, iid, "join")?;
+        }
+        let inn_joined_tree = db.open_tree("inn_joined")?;
+        if !inn_joined_tree.contains_key(&inn_users_k)? {
+            let now = u32_to_ivec(Utc::now().timestamp() as u32);
+            inn_joined_tree.insert(&inn_users_k, now)?;
+        }
+    } else if db.open_tree("user_inns")?.remove(&user_inns_k)?.is_some() {
+        inn_event(db, iid, "leave")?;
+    }
+
+    if role >= 8 {
+        db.open_tree("mod_inns")?.insert(&user_inns_k, &[])?;
+    } else {
+        db.open_tree("mod_inns")?.remove(&user_inns_k)?;
+    }
+
+    Ok(())
+}
+
+/// record the reason and expiry of a role change, replacing the previous one. `expiry` is in
+/// seconds.
+///
+/// If the previous change has not expired yet, its `old_role` is kept, so extending a ban
+/// still restores the role before the first ban.
+fn record_role_change(
+    db: &Db,
+    iid: u32,
+    uid: u32,
+    old_role: Option<u8>,
+    reason: String,
+    expiry: Option<i64>,
+) -> Result<(), AppError> {
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+    let role_changes_tree = db.open_tree("role_changes")?;
+    let role_expiry_tree = db.open_tree("role_expiry")?;
+
+    let mut old_role = old_role;
+    if let Some(v) = role_changes_tree.remove(&k)? {
+        let (change, _): (RoleChange, usize) = bincode::decode_from_slice(&v, standard())?;
+        if let Some(expires_at) = change.expires_at {
+            role_expiry_tree.remove(role_expiry_key(expires_at, iid, uid))?;
+            old_role = change.old_role;
+        }
+    }
+
+    let expires_at = expiry.map(|seconds| Utc::now().timestamp() + seconds);
+    if reason.is_empty() && expires_at.is_none() {
+        return Ok(());
+    }
+
+    let change = RoleChange {
+        old_role,
+        reason,
+        expires_at,
+    };
+    let change_encoded = bincode::encode_to_vec(&change, standard())?;
+    role_changes_tree.insert(&k, change_encoded)?;
+    if let Some(expires_at) = expires_at {
+        role_expiry_tree.insert(role_expiry_key(expires_at, iid, uid), &[])?;
+    }
+
+    Ok(())
+}
+
+/// key of `role_expiry`: `timestamp#iid#uid`, the timestamp is big-endian i64 so the keys sort
+/// by time
+fn role_expiry_key(expires_at: i64, iid: u32, uid: u32) -> Vec<u8> {
+    [
+        &expires_at.to_be_bytes()[..],
+        &u32_to_ivec(iid),
+        &u32_to_ivec(uid),
+    ]
+    .concat()
+}
+
+/// drop the reason and expiry of the role change when the membership ends, so the old role is
+/// not restored later
+pub(super) fn clear_role_change(db: &Db, iid: u32, uid: u32) -> Result<(), AppError> {
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+    if let Some(v) = db.open_tree("role_changes")?.remove(&k)? {
+        let (change, _): (RoleChange, usize) = bincode::decode_from_slice(&v, standard())?;
+        if let Some(expires_at) = change.expires_at {
+            db.open_tree("role_expiry")?
+                .remove(role_expiry_key(expires_at, iid, uid))?;
+        }
+    }
+    Ok(())
+}
+
+/// Cron job: restore the roles whose changes have expired.
+///
+/// The keys of `role_expiry` are `timestamp#iid#uid`, iid 0 is for site roles.
+/// They are sorted by the timestamp, so the scan stops at the first one not expired.
+pub(crate) async fn restore_expired_roles(db: &Db) -> Result<(), AppError> {
+    let tree = db.open_tree("role_expiry")?;
+    let role_changes_tree = db.open_tree("role_changes")?;
+    let now = Utc::now().timestamp();
+    for i in tree.iter() {
+        let (k, _) = i?;
+        if k.len() != 16 {
+            tree.remove(&k)?;
+            continue;
+        }
+        let expires_at = i64::from_be_bytes(k[0..8].try_into().unwrap());
+        let iid = u8_slice_to_u32(&k[8..12]);
+        let uid = u8_slice_to_u32(&k[12..16]);
+        if expires_at >= now {
+            break;
+        }
+        tree.remove(&k)?;
+
+        let changes_k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+        let Some(v) = role_changes_tree.remove(&changes_k)? else {
+            continue;
+        };
+        let (change, _): (RoleChange, usize) = bincode::decode_from_slice(&v, standard())?;
+
+        let old_role = if iid == 0 {
+            let mut user: User = get_one(db, "users", uid)?;
+            user.role = change.old_role.unwrap_or(10);
+            let user_encode = bincode::encode_to_vec(&user, standard())?;
+            db.open_tree("users")?
+                .insert(u32_to_ivec(uid), user_encode)?;
+            Claim::update_role(db, uid)?;
+            user.role
+        } else {
+            let old_role = change.old_role.unwrap_or_default();
+            set_inn_role(db, iid, uid, old_role)?;
+            old_role
+        };
+
+        mod_log(
+            db,
+            iid,
+            0,
+            format!("restore role {old_role}"),
+            format!("/user/{uid}"),
+            "expired".into(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Cron job: promote the Interns who meet the [PromotionRule] of their inns.
+pub(crate) async fn promote_members(db: &Db) -> Result<(), AppError> {
+    for i in db.open_tree("inn_configs")?.iter() {
+        let (k, v) = i?;
+        let (inn_config, _): (InnConfig, usize) = bincode::decode_from_slice(&v, standard())?;
+        if inn_config.promotion_rule == PromotionRule::default() {
+            continue;
+        }
+        let iid = u8_slice_to_u32(&k);
+        for i in db.open_tree("inn_users")?.scan_prefix(&k) {
+            let (k, v) = i?;
+            if v[0] == 4 {
+                let uid = u8_slice_to_u32(&k[4..8]);
+                check_promotion(db, iid, uid)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// promote the Intern to Fellow if the [PromotionRule] of the inn is met.
+///
+/// Members under a role change set by mods are skipped. For members who joined
+/// before the join time was recorded, the days are counted from the first check.
+pub(super) fn check_promotion(db: &Db, iid: u32, uid: u32) -> Result<(), AppError> {
+    let iid_ivec = u32_to_ivec(iid);
+    let rule = get_inn_config(db, iid)?.promotion_rule;
+    if rule == PromotionRule::default() {
+        return Ok(());
+    }
+    if get_inn_role(db, iid, uid)? != Some(4) {
+        return Ok(());
+    }
+
+    let uid_ivec = u32_to_ivec(uid);
+    let k = [&iid_ivec, &uid_ivec].concat();
+    if db.open_tree("role_changes")?.contains_key(&k)? {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp();
+    let inn_joined_tree = db.open_tree("inn_joined")?;
+    let joined_at = match inn_joined_tree.get(&k)? {
+        Some(v) => i64::from(u8_slice_to_u32(&v)),
+        None => {
+            inn_joined_tree.insert(&k, u32_to_ivec(now as u32))?;
+            now
+        }
+    };
+    if now - joined_at < i64::from(rule.days) * 24 * 3600 {
+        return Ok(());
+    }
+
+    let stats = |stat_type: &str| -> Result<i64, AppError> {
+        let k = [&iid_ivec, &uid_ivec, stat_type.as_bytes()].concat();
+        Ok(get_count(db, "member_stats", k)? as i64)
+    };
+    // net upvotes of the posts and comments in the inn
+    let upvotes = stats("upvote")? - stats("downvote")?;
+    if stats("post")? < i64::from(rule.posts)
+        || stats("comment")? < i64::from(rule.comments)
+        || upvotes < i64::from(rule.upvotes)
+    {
+        return Ok(());
+    }
+
+    set_inn_role(db, iid, uid, 5)?;
+    mod_log(
+        db,
+        iid,
+        0,
+        "set role Fellow".into(),
+        format!("/user/{uid}"),
+        "promotion rule".into(),
+    )?;
+
+    Ok(())
+}
+
+/// The error for a user whose role is not enough, with the reason of the last role change if any.
+pub(super) fn role_error(db: &Db, iid: u32, uid: u32) -> AppError {
+    let k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+    let change = match db.open_tree("role_changes").and_then(|tree| tree.get(k)) {
+        Ok(Some(v)) => match bincode::decode_from_slice::<RoleChange, _>(&v, standard()) {
+            Ok((change, _)) => Some(change),
+            Err(e) => return e.into(),
+        },
+        Ok(None) => None,
+        Err(e) => return e.into(),
+    };
+
+    let note = change
+        .map(|change| {
+            let mut note = String::new();
+            if !change.reason.is_empty() {
+                note.push_str(&format!("Reason: {}. ", change.reason));
+            }
+            if let Some(expires_at) = change.expires_at {
+                let until = NaiveDateTime::from_timestamp_opt(expires_at, 0)
+                    .unwrap_or_default()
+                    .format("%Y-%m-%d %H:%M UTC");
+                note.push_str(&format!("Until {until}."));
+            }
+            note
+        })
+        .unwrap_or_default();
+
+    match iid {
+        0 => AppError::Banned(note),
+        _ if note.is_empty() => AppError::Unauthorized,
+        _ => AppError::Restricted(note),
+    }
+}
+
 /// Form data: `/user/setting`
 #[derive(Deserialize, Validate)]
 pub(crate) struct FormUser {
@@ -444,6 +909,7 @@ pub(crate) struct FormUser {
     about: String,
     #[validate(length(max = 256))]
     url: String,
+    auto_subscribe: Option<bool>,
 }
 
 /// Page data: `user_setting.html`
@@ -456,6 +922,9 @@ struct PageUserSetting<'a> {
     url: String,
     about: String,
     sessions: Vec<String>,
+    auto_subscribe: bool,
+    feed_token: Option<String>,
+    domain: String,
 }
 
 /// `GET /user/setting`
@@ -477,6 +946,15 @@ pub(crate) async fn user_setting(
         }
     }
 
+    let auto_subscribe = !db
+        .open_tree("auto_subscribe_off")?
+        .contains_key(u32_to_ivec(claim.uid))?;
+
+    let feed_token = db
+        .open_tree("user_feed_tokens")?
+        .get(u32_to_ivec(claim.uid))?
+        .map(|v| String::from_utf8_lossy(&v).into_owned());
+
     let page_user_setting = PageUserSetting {
         uid: claim.uid,
         page_data: PageData::new("setting", &site_config, Some(claim), false),
@@ -484,6 +962,9 @@ pub(crate) async fn user_setting(
         about: user.about,
         url: user.url,
         sessions,
+        auto_subscribe,
+        feed_token,
+        domain: site_config.domain.trim_end_matches('/').to_owned(),
     };
 
     Ok(into_response(&page_user_setting, "html"))
@@ -575,6 +1056,39 @@ pub(crate) async fn remove_session(
     Ok(Redirect::to("/user/setting"))
 }
 
+#[derive(Deserialize)]
+pub(crate) struct ParamsFeedToken {
+    revoke: Option<bool>,
+}
+
+/// `GET /user/feed_token`
+///
+/// Generate a new feed token and revoke the old one, or only revoke it with `?revoke=true`.
+pub(crate) async fn user_feed_token(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Query(params): Query<ParamsFeedToken>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let uid_ivec = u32_to_ivec(claim.uid);
+    let feed_tokens_tree = db.open_tree("feed_tokens")?;
+    let user_feed_tokens_tree = db.open_tree("user_feed_tokens")?;
+    if let Some(old) = user_feed_tokens_tree.remove(&uid_ivec)? {
+        feed_tokens_tree.remove(old)?;
+    }
+
+    if params.revoke != Some(true) {
+        let token = nanoid!(32);
+        feed_tokens_tree.insert(&token, &uid_ivec)?;
+        user_feed_tokens_tree.insert(&uid_ivec, token.as_bytes())?;
+    }
+
+    Ok(Redirect::to("/user/setting#feed_token"))
+}
+
 /// `POST /user/setting`
 pub(crate) async fn user_setting_post(
     State(db): State<Db>,
@@ -602,6 +1116,14 @@ pub(crate) async fn user_setting_post(
         tree.insert(&input.username, u32_to_ivec(user.uid))?;
     }
 
+    if input.auto_subscribe.unwrap_or_default() {
+        db.open_tree("auto_subscribe_off")?
+            .remove(u32_to_ivec(claim.uid))?;
+    } else {
+        db.open_tree("auto_subscribe_off")?
+            .insert(u32_to_ivec(claim.uid), &[])?;
+    }
+
     user.username = input.username;
     user.about = input.about;
     user.url = input.url;
@@ -1021,9 +1543,9 @@ impl Claim {
     /// generate a Claim from user and store it in session tree, then return a cookie with a session id.
     fn generate_cookie(db: &Db, user: User, expiry: &str) -> Result<String, AppError> {
         if user.role == 0 {
-            return Err(AppError::Banned);
+            return Err(role_error(db, 0, user.uid));
         }
-        let seconds = expire_seconds(expiry);
+        let seconds = expire_seconds(expiry).ok_or(AppError::InvalidExpiry)?;
         let now = Utc::now().timestamp();
         let session_id = generate_nanoid_expire(seconds);
 
@@ -1048,8 +1570,10 @@ impl Claim {
 }
 
 /// Compute expire time in seconds from a string like "1h", "1day".
-fn expire_seconds(expiry: &str) -> i64 {
-    match expiry {
+///
+/// Returns `None` for unknown values, which come from user input.
+pub(super) fn expire_seconds(expiry: &str) -> Option<i64> {
+    let seconds = match expiry {
         "30m" => 1800,
         "1h" => 3600,
         "2h" => 2 * 3600,
@@ -1063,8 +1587,9 @@ fn expire_seconds(expiry: &str) -> i64 {
         "5days" => 5 * 24 * 3600,
         "1week" => 7 * 24 * 3600,
         "2weeks" => 2 * 7 * 24 * 3600,
-        _ => unreachable!(),
-    }
+        _ => return None,
+    };
+    Some(seconds)
 }
 
 #[cfg(test)]
@@ -1088,4 +1613,87 @@ mod tests {
         // must generate different password_hash and salt with the same password
         assert_ne!(password_hash, password_hash2);
     }
+
+    #[test]
+    fn test_get_saved() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("user_saved").unwrap();
+        // (iid, pid, cid) saved by user 1, and a post saved by user 2
+        for (uid, iid, pid, cid) in [
+            (1, 1, 1, 0),
+            (1, 1, 1, 3),
+            (1, 2, 5, 0),
+            (1, 1, 7, 0),
+            (2, 1, 9, 0),
+        ] {
+            let k = [&u32_to_ivec(uid), &u32_to_ivec(pid), &u32_to_ivec(cid)].concat();
+            let v = [&u32_to_ivec(iid), &u32_to_ivec(100)].concat();
+            tree.insert(k, v).unwrap();
+        }
+        let pids = |iid, anchor, n, is_desc| -> Vec<(u32, u32)> {
+            get_saved(&db, 1, iid, anchor, n, is_desc)
+                .unwrap()
+                .into_iter()
+                .map(|(_, pid, cid, _)| (pid, cid))
+                .collect()
+        };
+
+        assert_eq!(pids(0, 0, 10, true), [(7, 0), (5, 0), (1, 3), (1, 0)]);
+        assert_eq!(pids(0, 1, 2, false), [(1, 3), (5, 0)]);
+        assert_eq!(pids(1, 0, 10, true), [(7, 0), (1, 3), (1, 0)]);
+        assert_eq!(pids(1, 1, 1, true), [(1, 3)]);
+        assert_eq!(get_saved(&db, 1, 2, 0, 10, true).unwrap(), [(2, 5, 0, 100)]);
+    }
+
+    #[test]
+    fn test_check_promotion() {
+        use crate::controller::member_stats;
+
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let (iid, uid) = (1, 2);
+        let inn_config = InnConfig {
+            promotion_rule: PromotionRule {
+                posts: 2,
+                comments: 1,
+                days: 3,
+                upvotes: 1,
+            },
+            ..Default::default()
+        };
+        let inn_config_encoded = bincode::encode_to_vec(&inn_config, standard()).unwrap();
+        db.open_tree("inn_configs")
+            .unwrap()
+            .insert(u32_to_ivec(iid), inn_config_encoded)
+            .unwrap();
+        set_inn_role(&db, iid, uid, 4).unwrap();
+        let promote = || {
+            check_promotion(&db, iid, uid).unwrap();
+            get_inn_role(&db, iid, uid).unwrap()
+        };
+
+        // enough posts, comments and upvotes, but joined just now
+        for stat_type in ["post", "post", "comment", "upvote"] {
+            member_stats(&db, iid, uid, stat_type, true).unwrap();
+        }
+        assert_eq!(promote(), Some(4));
+
+        // joined 3 days ago, but a downvote takes the upvote back
+        let k = [&u32_to_ivec(iid), &u32_to_ivec(uid)].concat();
+        let joined_at = Utc::now().timestamp() - 3 * 24 * 3600;
+        db.open_tree("inn_joined")
+            .unwrap()
+            .insert(&k, u32_to_ivec(joined_at as u32))
+            .unwrap();
+        member_stats(&db, iid, uid, "downvote", true).unwrap();
+        assert_eq!(promote(), Some(4));
+
+        // members under a role change set by mods are skipped
+        member_stats(&db, iid, uid, "upvote", true).unwrap();
+        let role_changes_tree = db.open_tree("role_changes").unwrap();
+        role_changes_tree.insert(&k, &[]).unwrap();
+        assert_eq!(promote(), Some(4));
+
+        role_changes_tree.remove(&k).unwrap();
+        assert_eq!(promote(), Some(5));
+    }
 }
@@ -0,0 +1,539 @@
+//! ## Wiki
+//!
+//! Every inn has a wiki. Pages are written in markdown and linked to each other by `[[Title]]`.
+//! Mods choose the min inn role to edit the wiki, Fellows by default. Every save is kept in the
+//! history, and an old revision can be viewed by `?rev=`.
+//!
+//! Private inns' wiki is only visible to the members, and the wiki of an archived inn is read-only.
+
+use super::{
+    get_inn_role, get_one, get_site_config, has_unread, incr_id, inn::get_inn_config,
+    into_response, is_mod, mod_log, timestamp_to_date, u32_to_ivec, u8_slice_to_u32,
+    utils::md2html, Claim, Inn, InnType, PageData, User, ValidatedForm, WikiPage,
+};
+use crate::error::AppError;
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State, TypedHeader},
+    headers::Cookie,
+    response::{IntoResponse, Redirect},
+};
+use bincode::config::standard;
+use chrono::Utc;
+use serde::Deserialize;
+use sled::Db;
+use validator::Validate;
+
+/// chars which are not allowed in titles, as they break urls or `[[Title]]` links
+const TITLE_FORBIDDEN: [char; 7] = ['/', '?', '#', '%', '[', ']', '|'];
+
+/// percent-encode the title to be used in the url path
+fn encode_title(title: &str) -> String {
+    let mut out = String::with_capacity(title.len());
+    for b in title.bytes() {
+        if b.is_ascii_alphanumeric() || b"-_.~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{b:02X}"));
+        }
+    }
+    out
+}
+
+/// convert `[[Title]]` to markdown links of the inn wiki
+fn wiki_links(md: &str, iid: u32) -> String {
+    let mut out = String::with_capacity(md.len());
+    let mut rest = md;
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let title = rest[start + 2..start + 2 + len].trim();
+        out.push_str(&rest[..start]);
+        if title.is_empty() || title.contains(&TITLE_FORBIDDEN[..]) || title.contains('\n') {
+            out.push_str(&rest[start..start + 4 + len]);
+        } else {
+            let link = encode_title(title);
+            out.push_str(&format!("[{title}](/inn/{iid}/wiki/{link})"));
+        }
+        rest = &rest[start + 4 + len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// get the inn role of the viewer. The wiki of private inns is only visible to the members.
+fn get_wiki_role(db: &Db, inn: &Inn, claim: Option<&Claim>) -> Result<u8, AppError> {
+    let inn_role = match claim {
+        Some(claim) => get_inn_role(db, inn.iid, claim.uid)?.unwrap_or_default(),
+        None => 0,
+    };
+    if inn.inn_type == InnType::Private && inn_role < 3 {
+        return Err(AppError::NotFound);
+    }
+    Ok(inn_role)
+}
+
+/// whether the user can edit the wiki of the inn
+fn can_edit(db: &Db, inn: &Inn, claim: &Claim, inn_role: u8) -> Result<bool, AppError> {
+    if inn.inn_type == InnType::Archive {
+        return Ok(false);
+    }
+    if claim.role == u8::MAX || is_mod(db, claim.uid, inn.iid)? {
+        return Ok(true);
+    }
+    Ok(inn_role >= get_inn_config(db, inn.iid)?.wiki_role())
+}
+
+/// get wid by `iid#title`
+fn get_wid(db: &Db, iid: u32, title: &str) -> Result<Option<u32>, AppError> {
+    let k = [&u32_to_ivec(iid), title.as_bytes()].concat();
+    Ok(db
+        .open_tree("inn_wiki")?
+        .get(k)?
+        .map(|v| u8_slice_to_u32(&v)))
+}
+
+/// Vec data: wiki page or revision
+struct OutWiki {
+    title: String,
+    link: String,
+    rev: u32,
+    uid: u32,
+    username: String,
+    updated_at: String,
+}
+
+impl OutWiki {
+    fn new(db: &Db, page: &WikiPage) -> Result<Self, AppError> {
+        let user: User = get_one(db, "users", page.uid)?;
+        Ok(OutWiki {
+            title: page.title.clone(),
+            link: encode_title(&page.title),
+            rev: page.rev,
+            uid: page.uid,
+            username: user.username,
+            updated_at: timestamp_to_date(page.updated_at),
+        })
+    }
+}
+
+/// Page data: `wiki_index.html`
+#[derive(Template)]
+#[template(path = "wiki_index.html")]
+struct PageWikiIndex<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    pages: Vec<OutWiki>,
+    can_edit: bool,
+}
+
+/// Url params: `wiki_index.html`
+#[derive(Deserialize)]
+pub(crate) struct ParamsWikiIndex {
+    title: Option<String>,
+}
+
+/// `GET /inn/:iid/wiki` wiki page index
+///
+/// With `?title=`, redirect to the edit page of the title.
+pub(crate) async fn wiki_index(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path(iid): Path<u32>,
+    Query(params): Query<ParamsWikiIndex>,
+) -> Result<impl IntoResponse, AppError> {
+    if let Some(title) = params.title {
+        let target = format!("/inn/{iid}/wiki/{}/edit", encode_title(title.trim()));
+        return Ok(Redirect::to(&target).into_response());
+    }
+
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    let inn_role = get_wiki_role(&db, &inn, claim.as_ref())?;
+
+    let mut pages = Vec::new();
+    // kv_pair: iid#title = wid
+    for i in db.open_tree("inn_wiki")?.scan_prefix(u32_to_ivec(iid)) {
+        let (_, v) = i?;
+        let page: WikiPage = get_one(&db, "wiki_pages", u8_slice_to_u32(&v))?;
+        pages.push(OutWiki::new(&db, &page)?);
+    }
+
+    let mut editable = false;
+    let has_unread = match claim {
+        Some(ref claim) => {
+            editable = can_edit(&db, &inn, claim, inn_role)?;
+            has_unread(&db, claim.uid)?
+        }
+        None => false,
+    };
+    let page_data = PageData::new("Wiki", &site_config, claim, has_unread);
+    let page_wiki_index = PageWikiIndex {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        pages,
+        can_edit: editable,
+    };
+
+    Ok(into_response(&page_wiki_index, "html"))
+}
+
+/// Page data: `wiki.html`
+#[derive(Template)]
+#[template(path = "wiki.html")]
+struct PageWiki<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    title: String,
+    link: String,
+    page: Option<OutWiki>,
+    content_html: String,
+    /// viewing an old revision
+    is_old: bool,
+    can_edit: bool,
+    is_mod: bool,
+}
+
+/// Url params: `wiki.html`
+#[derive(Deserialize)]
+pub(crate) struct ParamsWiki {
+    rev: Option<u32>,
+}
+
+/// `GET /inn/:iid/wiki/:title` wiki page
+pub(crate) async fn wiki(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, title)): Path<(u32, String)>,
+    Query(params): Query<ParamsWiki>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    let inn_role = get_wiki_role(&db, &inn, claim.as_ref())?;
+
+    let mut page = None;
+    let mut content_html = String::new();
+    let mut is_old = false;
+    if let Some(wid) = get_wid(&db, iid, &title)? {
+        let mut wiki_page: WikiPage = get_one(&db, "wiki_pages", wid)?;
+        if let Some(rev) = params.rev.filter(|rev| *rev != wiki_page.rev) {
+            let k = [&u32_to_ivec(wid), &u32_to_ivec(rev)].concat();
+            let v = db
+                .open_tree("wiki_history")?
+                .get(k)?
+                .ok_or(AppError::NotFound)?;
+            (wiki_page, _) = bincode::decode_from_slice(&v, standard())?;
+            is_old = true;
+        }
+        content_html = md2html(&wiki_links(&wiki_page.content, iid));
+        page = Some(OutWiki::new(&db, &wiki_page)?);
+    }
+
+    let mut editable = false;
+    let mut is_inn_mod = false;
+    let has_unread = match claim {
+        Some(ref claim) => {
+            editable = can_edit(&db, &inn, claim, inn_role)?;
+            is_inn_mod = is_mod(&db, claim.uid, iid)? || claim.role == u8::MAX;
+            has_unread(&db, claim.uid)?
+        }
+        None => false,
+    };
+    let page_title = title.clone();
+    let page_data = PageData::new(&page_title, &site_config, claim, has_unread);
+    let page_wiki = PageWiki {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        link: encode_title(&title),
+        title,
+        page,
+        content_html,
+        is_old,
+        can_edit: editable,
+        is_mod: is_inn_mod,
+    };
+
+    Ok(into_response(&page_wiki, "html"))
+}
+
+/// Page data: `wiki_history.html`
+#[derive(Template)]
+#[template(path = "wiki_history.html")]
+struct PageWikiHistory<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    title: String,
+    link: String,
+    revisions: Vec<OutWiki>,
+}
+
+/// `GET /inn/:iid/wiki/:title/history` revisions of a wiki page
+pub(crate) async fn wiki_history(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, title)): Path<(u32, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let site_config = get_site_config(&db)?;
+    let claim = cookie.and_then(|cookie| Claim::get(&db, &cookie, &site_config));
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    get_wiki_role(&db, &inn, claim.as_ref())?;
+    let wid = get_wid(&db, iid, &title)?.ok_or(AppError::NotFound)?;
+
+    let mut revisions = Vec::new();
+    // kv_pair: wid#rev = WikiPage
+    for i in db
+        .open_tree("wiki_history")?
+        .scan_prefix(u32_to_ivec(wid))
+        .rev()
+    {
+        let (_, v) = i?;
+        let (wiki_page, _): (WikiPage, usize) = bincode::decode_from_slice(&v, standard())?;
+        revisions.push(OutWiki::new(&db, &wiki_page)?);
+    }
+
+    let has_unread = match claim {
+        Some(ref claim) => has_unread(&db, claim.uid)?,
+        None => false,
+    };
+    let page_data = PageData::new("Wiki history", &site_config, claim, has_unread);
+    let page_wiki_history = PageWikiHistory {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        link: encode_title(&title),
+        title,
+        revisions,
+    };
+
+    Ok(into_response(&page_wiki_history, "html"))
+}
+
+/// Page data: `wiki_edit.html`
+#[derive(Template)]
+#[template(path = "wiki_edit.html")]
+struct PageWikiEdit<'a> {
+    page_data: PageData<'a>,
+    iid: u32,
+    inn_name: String,
+    title: String,
+    link: String,
+    content: String,
+}
+
+/// `GET /inn/:iid/wiki/:title/edit` wiki page create/edit page
+pub(crate) async fn wiki_edit(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, title)): Path<(u32, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    let inn_role = get_wiki_role(&db, &inn, Some(&claim))?;
+    if !can_edit(&db, &inn, &claim, inn_role)? {
+        return Err(AppError::Unauthorized);
+    }
+
+    let content = match get_wid(&db, iid, &title)? {
+        Some(wid) => get_one::<WikiPage>(&db, "wiki_pages", wid)?.content,
+        None => String::new(),
+    };
+
+    let page_data = PageData::new("Edit wiki", &site_config, Some(claim), false);
+    let page_wiki_edit = PageWikiEdit {
+        page_data,
+        iid,
+        inn_name: inn.inn_name,
+        link: encode_title(&title),
+        title,
+        content,
+    };
+
+    Ok(into_response(&page_wiki_edit, "html"))
+}
+
+/// Form data: `/inn/:iid/wiki/:title/edit` wiki page create/edit page
+#[derive(Deserialize, Validate)]
+pub(crate) struct FormWiki {
+    #[validate(length(min = 1, max = 65535))]
+    content: String,
+}
+
+/// `POST /inn/:iid/wiki/:title/edit` wiki page create/edit page
+///
+/// Every save is a new revision.
+pub(crate) async fn wiki_edit_post(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, title)): Path<(u32, String)>,
+    ValidatedForm(input): ValidatedForm<FormWiki>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    let inn: Inn = get_one(&db, "inns", iid)?;
+    let inn_role = get_wiki_role(&db, &inn, Some(&claim))?;
+    if !can_edit(&db, &inn, &claim, inn_role)? {
+        return Err(if inn.inn_type == InnType::Archive {
+            AppError::InnArchived
+        } else {
+            AppError::Unauthorized
+        });
+    }
+
+    let title = title.trim().to_owned();
+    if title.is_empty() || title.chars().count() > 64 || title.contains(&TITLE_FORBIDDEN[..]) {
+        return Err(AppError::WikiTitleInvalid);
+    }
+
+    let (wid, rev) = match get_wid(&db, iid, &title)? {
+        Some(wid) => {
+            let old: WikiPage = get_one(&db, "wiki_pages", wid)?;
+            if old.content == input.content {
+                let target = format!("/inn/{iid}/wiki/{}", encode_title(&title));
+                return Ok(Redirect::to(&target));
+            }
+            (wid, old.rev + 1)
+        }
+        None => (incr_id(&db, "wiki_count")?, 1),
+    };
+
+    let wiki_page = WikiPage {
+        wid,
+        iid,
+        title,
+        content: input.content,
+        uid: claim.uid,
+        rev,
+        updated_at: Utc::now().timestamp(),
+    };
+    let wid_ivec = u32_to_ivec(wid);
+    let page_encoded = bincode::encode_to_vec(&wiki_page, standard())?;
+    db.open_tree("wiki_pages")?
+        .insert(&wid_ivec, page_encoded.clone())?;
+    let k = [&wid_ivec, &u32_to_ivec(rev)].concat();
+    db.open_tree("wiki_history")?.insert(k, page_encoded)?;
+    let k = [&u32_to_ivec(iid), wiki_page.title.as_bytes()].concat();
+    db.open_tree("inn_wiki")?.insert(k, wid_ivec)?;
+
+    let target = format!("/inn/{iid}/wiki/{}", encode_title(&wiki_page.title));
+    Ok(Redirect::to(&target))
+}
+
+/// `GET /inn/:iid/wiki/:title/delete` delete a wiki page with its history. Only for mods.
+pub(crate) async fn wiki_delete(
+    State(db): State<Db>,
+    cookie: Option<TypedHeader<Cookie>>,
+    Path((iid, title)): Path<(u32, String)>,
+) -> Result<impl IntoResponse, AppError> {
+    let cookie = cookie.ok_or(AppError::NonLogin)?;
+    let site_config = get_site_config(&db)?;
+    let claim = Claim::get(&db, &cookie, &site_config).ok_or(AppError::NonLogin)?;
+
+    if !is_mod(&db, claim.uid, iid)? && claim.role != u8::MAX {
+        return Err(AppError::Unauthorized);
+    }
+
+    let wid = get_wid(&db, iid, &title)?.ok_or(AppError::NotFound)?;
+    let wid_ivec = u32_to_ivec(wid);
+    db.open_tree("wiki_pages")?.remove(&wid_ivec)?;
+    let wiki_history_tree = db.open_tree("wiki_history")?;
+    for i in wiki_history_tree.scan_prefix(&wid_ivec) {
+        let (k, _) = i?;
+        wiki_history_tree.remove(k)?;
+    }
+    let k = [&u32_to_ivec(iid), title.as_bytes()].concat();
+    db.open_tree("inn_wiki")?.remove(k)?;
+
+    mod_log(
+        &db,
+        iid,
+        claim.uid,
+        "delete wiki".to_owned(),
+        title,
+        String::new(),
+    )?;
+
+    let target = format!("/inn/{iid}/wiki");
+    Ok(Redirect::to(&target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wiki_links() {
+        assert_eq!(
+            wiki_links("see [[FAQ]] and [[Rules of the inn]]", 1),
+            "see [FAQ](/inn/1/wiki/FAQ) and [Rules of the inn](/inn/1/wiki/Rules%20of%20the%20inn)"
+        );
+        assert_eq!(wiki_links("[[a/b]] [[]] [[x", 1), "[[a/b]] [[]] [[x");
+    }
+
+    #[test]
+    fn test_wiki_access() {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        let mut inn = Inn {
+            iid: 1,
+            inn_name: "inn".into(),
+            about: String::new(),
+            description: String::new(),
+            topics: vec![],
+            inn_type: InnType::Private,
+            early_birds: 0,
+            created_at: 0,
+        };
+        let claim = |uid| Claim {
+            uid,
+            username: String::new(),
+            role: 10,
+            last_write: 0,
+            session_id: String::new(),
+        };
+        // user 1 is an Intern, user 2 a Fellow and user 3 a mod
+        let inn_users_tree = db.open_tree("inn_users").unwrap();
+        for (uid, role) in [(1, 4), (2, 5), (3, 8)] {
+            let k = [&u32_to_ivec(1), &u32_to_ivec(uid)].concat();
+            inn_users_tree.insert(k, &[role]).unwrap();
+        }
+        db.open_tree("mod_inns")
+            .unwrap()
+            .insert([&u32_to_ivec(3), &u32_to_ivec(1)].concat(), &[])
+            .unwrap();
+
+        // the wiki of a private inn is only for the members
+        assert!(matches!(
+            get_wiki_role(&db, &inn, None),
+            Err(AppError::NotFound)
+        ));
+        assert!(matches!(
+            get_wiki_role(&db, &inn, Some(&claim(4))),
+            Err(AppError::NotFound)
+        ));
+        assert_eq!(get_wiki_role(&db, &inn, Some(&claim(1))).unwrap(), 4);
+
+        // Fellows can edit by default
+        assert!(!can_edit(&db, &inn, &claim(1), 4).unwrap());
+        assert!(can_edit(&db, &inn, &claim(2), 5).unwrap());
+        assert!(can_edit(&db, &inn, &claim(3), 8).unwrap());
+
+        // nobody can edit an archived inn
+        inn.inn_type = InnType::Archive;
+        assert!(!can_edit(&db, &inn, &claim(3), 8).unwrap());
+    }
+}
@@ -35,8 +35,10 @@ pub(super) enum AppError {
     Unauthorized,
     #[error("Please login first")]
     NonLogin,
-    #[error("You have been banned")]
-    Banned,
+    #[error("You have been banned. {}", .0)]
+    Banned(String),
+    #[error("Your role in this inn has been restricted. {}", .0)]
+    Restricted(String),
     #[error("The post has been locked by mod")]
     Locked,
     #[error("The post has been hidden by mod")]
@@ -51,6 +53,30 @@ pub(super) enum AppError {
     AxumFormRejection(#[from] axum::extract::rejection::FormRejection),
     #[error("Invalid feed link")]
     InvalidFeedLink,
+    #[error("The invite link has expired or been used up")]
+    InvalidInvite,
+    #[error("The title or the content is too long")]
+    ContentTooLong,
+    #[error("The inn has been archived")]
+    InnArchived,
+    #[error("Wiki title should be 1 to 64 chars, without '/', '?', '#', '%', '[', ']' or '|'")]
+    WikiTitleInvalid,
+    #[error("A poll should have 2 to 10 options, each no longer than 100 chars")]
+    InvalidPoll,
+    #[error("The poll has been closed")]
+    PollClosed,
+    #[error("You have voted")]
+    AlreadyVoted,
+    #[error("The event should end after it starts")]
+    InvalidEvent,
+    #[error("Invalid link, it should start with http:// or https://")]
+    InvalidLink,
+    #[error("The bot should be yourself or a bot account, and a member of the inn who can post")]
+    InvalidFeedBot,
+    #[error("Invalid expiry")]
+    InvalidExpiry,
+    #[error("Invalid role")]
+    InvalidRole,
     #[error(transparent)]
     Reqwest(#[from] reqwest::Error),
 }
@@ -9,7 +9,9 @@ use crate::{
     app_router::router,
     controller::{
         feed::cron_feed,
+        inn::refresh_related,
         shutdown_signal,
+        user::{promote_members, restore_expired_roles},
         utils::{clear_invalid, CURRENT_SHA256},
     },
 };
@@ -55,6 +57,9 @@ async fn main() -> Result<(), AppError> {
             if let Err(e) = clear_invalid(&db2, "sessions").await {
                 error!(%e);
             }
+            if let Err(e) = restore_expired_roles(&db2).await {
+                error!(%e);
+            }
             sleep_seconds(300).await;
         }
     });
@@ -68,6 +73,18 @@ async fn main() -> Result<(), AppError> {
             if let Err(e) = clear_invalid(&db2, "user_stats").await {
                 error!(%e);
             }
+            if let Err(e) = clear_invalid(&db2, "post_viewers").await {
+                error!(%e);
+            }
+            if let Err(e) = clear_invalid(&db2, "viewer_ids").await {
+                error!(%e);
+            }
+            if let Err(e) = promote_members(&db2).await {
+                error!(%e);
+            }
+            if let Err(e) = refresh_related(&db2).await {
+                error!(%e);
+            }
             sleep_seconds(3600 * 8).await;
         }
     });